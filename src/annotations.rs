@@ -0,0 +1,397 @@
+use std::{collections::BTreeMap, fs};
+
+use crate::symbols::parse_hex_address;
+
+// Annotation files describe what the tracer can't work out on its own, one address per line.
+// Subroutines can be described by how their calls come back:
+//
+//     address noreturn             subroutine never returns to its caller
+//     address inline-data count    every call is followed by `count` data bytes
+//     address pointer-table [count]  every call is followed by a table of code pointers
+//
+// and data by its shape, so it's printed as that rather than as plain bytes:
+//
+//     address bytes count [per-line]             a table of bytes, `per-line` of them to a line
+//     address words count                        a table of little-endian words
+//     address pointers count [code]              a table of pointers, traced as code with `code`
+//     address split-pointers count high [code]   pointer low bytes, with the high bytes at `high`
+//     address records count field[:size]...      records made of named one or two byte fields
+//     address text length                        a string, decoded with the character map
+//
+//     charmap byte "characters"    maps `byte`, and the bytes after it, to these characters
+//
+// Addresses are hex, written the same way as in symbol files. Pointer tables without a count end
+// at the first pointer that isn't into PRG ROM, or where code or another label starts. Without a
+// character map, text is decoded as ASCII. Blank lines and lines starting with `;` or `#` are
+// ignored.
+
+const DEFAULT_BYTES_PER_LINE: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubroutineAnnotation {
+    NoReturn,
+    InlineData(usize),
+    PointerTable(Option<usize>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataAnnotation {
+    Bytes { count: usize, bytes_per_line: usize },
+    Words(usize),
+    Pointers { count: usize, is_code: bool },
+    SplitPointers { count: usize, high_address: usize, is_code: bool },
+    Records { count: usize, fields: Vec<RecordField> },
+    Text(usize),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordField {
+    pub name: String,
+    pub bytes: usize,
+}
+
+/// Either kind of annotation, as found after the address on a line.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Annotation {
+    Subroutine(SubroutineAnnotation),
+    Data(DataAnnotation),
+}
+
+pub struct Annotations {
+    subroutines: BTreeMap<usize, SubroutineAnnotation>,
+    data: BTreeMap<usize, DataAnnotation>,
+    character_map: BTreeMap<u8, char>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl Annotations {
+    pub fn new() -> Self {
+        Self {
+            subroutines: BTreeMap::new(),
+            data: BTreeMap::new(),
+            character_map: BTreeMap::new(),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_from_file(filename: &str) -> Self {
+        let contents = match fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read annotation file: {error}"),
+        };
+
+        match Self::parse(&contents) {
+            Ok(annotations) => annotations,
+            Err(error) => panic!("[ERROR] Could not parse annotation file {filename}: {error}"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut annotations = Self::new();
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+
+            let (address_text, annotation_text) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            if address_text == "charmap" {
+                let (byte, characters) = parse_character_map_entry(annotation_text).map_err(|error| format!("line {}: {error}", line_index + 1))?;
+                annotations.insert_characters(byte, &characters);
+                continue;
+            }
+
+            let Some(address) = parse_hex_address(address_text) else {
+                return Err(format!("line {}: invalid address `{address_text}`", line_index + 1));
+            };
+            let annotation = parse_annotation(annotation_text).map_err(|error| format!("line {}: {error}", line_index + 1))?;
+            annotations.insert(address, annotation);
+        }
+
+        Ok(annotations)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn insert(&mut self, address: usize, annotation: Annotation) {
+        match annotation {
+            Annotation::Subroutine(annotation) => self.insert_subroutine(address, annotation),
+            Annotation::Data(annotation) => {
+                self.data.insert(address, annotation);
+            },
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn insert_subroutine(&mut self, address: usize, annotation: SubroutineAnnotation) {
+        self.subroutines.insert(address, annotation);
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Maps `first_byte`, and the bytes following it, to `characters` when decoding text.
+    pub fn insert_characters(&mut self, first_byte: u8, characters: &str) {
+        for (byte, character) in (first_byte..=u8::MAX).zip(characters.chars()) {
+            self.character_map.insert(byte, character);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Merges `other` into these annotations; its entries win when both cover the same address.
+    pub fn extend(&mut self, other: Annotations) {
+        self.subroutines.extend(other.subroutines);
+        self.data.extend(other.data);
+        self.character_map.extend(other.character_map);
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn get_subroutine(&self, address: usize) -> Option<SubroutineAnnotation> {
+        self.subroutines.get(&address).copied()
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn data(&self) -> &BTreeMap<usize, DataAnnotation> {
+        &self.data
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Decodes a byte of text, falling back to printable ASCII when there's no character map.
+    pub fn get_character(&self, byte: u8) -> Option<char> {
+        if self.character_map.is_empty() {
+            return (byte.is_ascii_graphic() || byte == b' ').then_some(byte as char);
+        }
+        self.character_map.get(&byte).copied()
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl Annotation {
+    /// Returns the annotation as it's written in an annotation file, after the address.
+    pub fn format(&self) -> String {
+        match self {
+            Annotation::Subroutine(SubroutineAnnotation::NoReturn) => String::from("noreturn"),
+            Annotation::Subroutine(SubroutineAnnotation::InlineData(count)) => format!("inline-data {count}"),
+            Annotation::Subroutine(SubroutineAnnotation::PointerTable(Some(count))) => format!("pointer-table {count}"),
+            Annotation::Subroutine(SubroutineAnnotation::PointerTable(None)) => String::from("pointer-table"),
+            Annotation::Data(DataAnnotation::Bytes { count, bytes_per_line }) => format!("bytes {count} {bytes_per_line}"),
+            Annotation::Data(DataAnnotation::Words(count)) => format!("words {count}"),
+            Annotation::Data(DataAnnotation::Pointers { count, is_code }) => {
+                format!("pointers {count}{}", if *is_code { " code" } else { "" })
+            },
+            Annotation::Data(DataAnnotation::SplitPointers { count, high_address, is_code }) => {
+                format!("split-pointers {count} {high_address:04X}{}", if *is_code { " code" } else { "" })
+            },
+            Annotation::Data(DataAnnotation::Records { count, fields }) => {
+                let fields: Vec<String> = fields.iter()
+                    .map(|field| if field.bytes == 1 { field.name.clone() } else { format!("{}:{}", field.name, field.bytes) })
+                    .collect();
+                format!("records {count} {}", fields.join(" "))
+            },
+            Annotation::Data(DataAnnotation::Text(length)) => format!("text {length}"),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Parses the part of an annotation line after the address, e.g. `inline-data 2`.
+pub fn parse_annotation(text: &str) -> Result<Annotation, String> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let count_at = |index: usize| -> Result<Option<usize>, String> {
+        fields.get(index)
+            .map(|count| count.parse::<usize>().map_err(|_| format!("invalid count `{count}`")))
+            .transpose()
+    };
+    let table_count_at = |index: usize| -> Result<usize, String> {
+        match count_at(index)? {
+            Some(0) => Err(format!("`{}` needs a count of at least 1", fields[0])),
+            Some(count) => Ok(count),
+            None => Err(format!("missing `{}` count", fields[0])),
+        }
+    };
+    let is_code_at = |index: usize| -> Result<bool, String> {
+        match fields.get(index) {
+            Some(&"code") => Ok(true),
+            Some(field) => Err(format!("expected `code`, found `{field}`")),
+            None => Ok(false),
+        }
+    };
+
+    // Each kind takes a fixed number of fields, counting its own, except records, which take the rest.
+    let (annotation, field_count) = match fields.first().copied() {
+        Some("noreturn") => (Annotation::Subroutine(SubroutineAnnotation::NoReturn), 1),
+        Some("inline-data") => match count_at(1)? {
+            Some(count) => (Annotation::Subroutine(SubroutineAnnotation::InlineData(count)), 2),
+            None => return Err(String::from("missing inline data byte count")),
+        },
+        Some("pointer-table") => (Annotation::Subroutine(SubroutineAnnotation::PointerTable(count_at(1)?)), 2),
+        Some("bytes") => {
+            let count = table_count_at(1)?;
+            let bytes_per_line = match count_at(2)? {
+                Some(0) => return Err(String::from("bytes per line must be at least 1")),
+                bytes_per_line => bytes_per_line.unwrap_or(DEFAULT_BYTES_PER_LINE),
+            };
+            (Annotation::Data(DataAnnotation::Bytes { count, bytes_per_line }), 3)
+        },
+        Some("words") => (Annotation::Data(DataAnnotation::Words(table_count_at(1)?)), 2),
+        Some("pointers") => (Annotation::Data(DataAnnotation::Pointers { count: table_count_at(1)?, is_code: is_code_at(2)? }), 3),
+        Some("split-pointers") => {
+            let count = table_count_at(1)?;
+            let Some(high_address) = fields.get(2).and_then(|field| parse_hex_address(field)) else {
+                return Err(String::from("missing or invalid high byte table address"));
+            };
+            (Annotation::Data(DataAnnotation::SplitPointers { count, high_address, is_code: is_code_at(3)? }), 4)
+        },
+        Some("records") => {
+            let count = table_count_at(1)?;
+            let record_fields = fields.get(2..).unwrap_or_default().iter()
+                .map(|field| parse_record_field(field))
+                .collect::<Result<Vec<RecordField>, String>>()?;
+            if record_fields.is_empty() {
+                return Err(String::from("missing record fields"));
+            }
+            (Annotation::Data(DataAnnotation::Records { count, fields: record_fields }), fields.len())
+        },
+        Some("text") => (Annotation::Data(DataAnnotation::Text(table_count_at(1)?)), 2),
+        Some(kind) => return Err(format!("unknown annotation `{kind}`")),
+        None => return Err(String::from("missing annotation")),
+    };
+
+    if let Some(extra_field) = fields.get(field_count) {
+        return Err(format!("unexpected `{extra_field}` after `{}`", fields[0]));
+    }
+
+    Ok(annotation)
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_record_field(text: &str) -> Result<RecordField, String> {
+    let (name, bytes) = match text.split_once(':') {
+        Some((name, "1")) => (name, 1),
+        Some((name, "2")) => (name, 2),
+        Some(_) => return Err(format!("record field `{text}` must be one or two bytes")),
+        None => (text, 1),
+    };
+    if name.is_empty() {
+        return Err(format!("record field `{text}` needs a name"));
+    }
+
+    Ok(RecordField { name: String::from(name), bytes })
+}
+
+// ---------------------------------------------------------------------------
+
+/// Parses the part of a `charmap` line after the keyword, e.g. `0A "ABC"`.
+fn parse_character_map_entry(text: &str) -> Result<(u8, String), String> {
+    let (byte_text, characters) = text.trim().split_once(char::is_whitespace).unwrap_or((text.trim(), ""));
+    let Some(byte) = parse_hex_address(byte_text).and_then(|byte| u8::try_from(byte).ok()) else {
+        return Err(format!("invalid byte `{byte_text}`"));
+    };
+    let Some(characters) = characters.trim().strip_prefix('"').and_then(|characters| characters.strip_suffix('"')) else {
+        return Err(format!("expected quoted characters, found `{}`", characters.trim()));
+    };
+
+    Ok((byte, String::from(characters)))
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_subroutine_annotations() {
+        let annotations = Annotations::parse("\
+; comment
+$8E04 pointer-table
+8123 noreturn
+0x9000 inline-data 2
+$9100 pointer-table 4
+").unwrap();
+
+        assert_eq!(annotations.get_subroutine(0x8E04), Some(SubroutineAnnotation::PointerTable(None)));
+        assert_eq!(annotations.get_subroutine(0x8123), Some(SubroutineAnnotation::NoReturn));
+        assert_eq!(annotations.get_subroutine(0x9000), Some(SubroutineAnnotation::InlineData(2)));
+        assert_eq!(annotations.get_subroutine(0x9100), Some(SubroutineAnnotation::PointerTable(Some(4))));
+        assert_eq!(annotations.get_subroutine(0x9200), None);
+    }
+
+    #[test]
+    fn parses_data_annotations() {
+        let annotations = Annotations::parse("\
+C000 bytes 32 16
+C020 words 4
+C028 pointers 3 code
+C030 split-pointers 2 C032
+C040 records 2 x y tile position:2
+C050 text 5
+").unwrap();
+
+        let data = annotations.data();
+        assert_eq!(data[&0xC000], DataAnnotation::Bytes { count: 32, bytes_per_line: 16 });
+        assert_eq!(data[&0xC020], DataAnnotation::Words(4));
+        assert_eq!(data[&0xC028], DataAnnotation::Pointers { count: 3, is_code: true });
+        assert_eq!(data[&0xC030], DataAnnotation::SplitPointers { count: 2, high_address: 0xC032, is_code: false });
+        assert_eq!(data[&0xC040], DataAnnotation::Records {
+            count: 2,
+            fields: vec![
+                RecordField { name: String::from("x"), bytes: 1 },
+                RecordField { name: String::from("y"), bytes: 1 },
+                RecordField { name: String::from("tile"), bytes: 1 },
+                RecordField { name: String::from("position"), bytes: 2 },
+            ],
+        });
+        assert_eq!(data[&0xC050], DataAnnotation::Text(5));
+
+        for annotation in data.values() {
+            let annotation = Annotation::Data(annotation.clone());
+            assert_eq!(parse_annotation(&annotation.format()), Ok(annotation));
+        }
+    }
+
+    #[test]
+    fn decodes_text_with_the_character_map() {
+        let mut annotations = Annotations::new();
+        assert_eq!(annotations.get_character(b'A'), Some('A'));
+        assert_eq!(annotations.get_character(0x00), None);
+
+        annotations.extend(Annotations::parse("charmap 0A \"ABC\"\ncharmap $24 \" \"").unwrap());
+        assert_eq!(annotations.get_character(0x0A), Some('A'));
+        assert_eq!(annotations.get_character(0x0C), Some('C'));
+        assert_eq!(annotations.get_character(0x24), Some(' '));
+        assert_eq!(annotations.get_character(b'A'), None);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(Annotations::parse("8000 inline-data").is_err());
+        assert!(Annotations::parse("8000 sometimes-returns").is_err());
+        assert!(Annotations::parse("8000 pointer-table lots").is_err());
+        assert!(Annotations::parse("zz noreturn").is_err());
+        assert!(Annotations::parse("8000 words 0").is_err());
+        assert!(Annotations::parse("8000 pointers 4 data").is_err());
+        assert!(Annotations::parse("8000 split-pointers 4").is_err());
+        assert!(Annotations::parse("8000 records 4 x:3").is_err());
+        assert!(Annotations::parse("8000 records 4").is_err());
+        assert!(Annotations::parse("8000 noreturn 5").is_err());
+        assert!(Annotations::parse("8000 inline-data 2 3").is_err());
+        assert!(Annotations::parse("8000 bytes 4 2 1").is_err());
+        assert!(Annotations::parse("8000 pointers 4 code code").is_err());
+        assert!(Annotations::parse("8000 text 4 5").is_err());
+        assert!(Annotations::parse("charmap 100 \"A\"").is_err());
+        assert!(Annotations::parse("charmap 00 A").is_err());
+    }
+}
@@ -0,0 +1,594 @@
+use std::{cell::Cell, io::{self, IsTerminal, Read, Write}, process::{Command, Stdio}, sync::atomic::{AtomicBool, Ordering}};
+
+use crate::cartridge::Cartridge;
+use crate::project::Project;
+use crate::symbols::parse_hex_address;
+
+// An interactive terminal browser for the listing, drawn with ANSI escapes in the alternate
+// screen. The terminal is switched into raw mode with `stty`, so no dependencies are needed.
+//
+//     up/down, j/k         move the cursor           pgup/pgdn, home/end   scroll
+//     g                    go to an address or label
+//     enter, right         follow the operand        backspace, left       go back
+//     x                    list the cross-references to the address under the cursor
+//     n                    name the address under the cursor
+//     ;                    comment the address under the cursor
+//     q                    quit
+//
+// Names and comments are saved to the project file straight away, and the ROM is disassembled
+// again so they show up everywhere they're used. With `--no-project` the project file is neither
+// loaded nor saved, so they only last until quitting.
+
+const HELP: &str = "g:go  enter:follow  bksp:back  x:xrefs  n:name  ;:comment  q:quit";
+
+const SIGWINCH: i32 = 28;
+const SIG_DFL: usize = 0;
+
+// Set by the SIGWINCH handler, so the terminal size is only queried again after a resize.
+static WAS_RESIZED: AtomicBool = AtomicBool::new(true);
+
+unsafe extern "C" {
+    fn signal(signal_number: i32, handler: usize) -> usize;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Escape,
+    Backspace,
+    Character(char),
+    Unknown,
+}
+
+struct Terminal {
+    saved_settings: String,
+    size: Cell<(usize, usize)>,
+}
+
+struct KeyReader {
+    pending: Vec<u8>,
+}
+
+struct Browser<'a> {
+    cartridge_filename: &'a str,
+    load_cartridge: &'a dyn Fn() -> Cartridge,
+    project: Project,
+    project_filename: Option<String>,
+    cartridge: Cartridge,
+    lines: Vec<(Option<usize>, String)>,
+    cursor: usize,
+    top: usize,
+    history: Vec<usize>,
+    message: String,
+}
+
+// ---------------------------------------------------------------------------
+
+/// Browses the disassembly of the ROM produced by `load_cartridge`, which loads it with all of
+/// the command line options applied but doesn't disassemble it yet.
+pub fn browse(cartridge_filename: &str, load_cartridge: &dyn Fn() -> Cartridge, should_use_project: bool) {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        panic!("[ERROR] Browsing needs a terminal!");
+    }
+
+    let project_filename = should_use_project.then(|| Project::filename_for(cartridge_filename));
+    let project = project_filename.as_deref().map_or_else(Project::new, Project::load_or_new);
+    let cartridge = disassemble_with_project(load_cartridge, &project);
+
+    let mut browser = Browser {
+        cartridge_filename,
+        load_cartridge,
+        project,
+        project_filename,
+        lines: cartridge.listing_lines(),
+        cartridge,
+        cursor: 0,
+        top: 0,
+        history: Vec::new(),
+        message: String::from(HELP),
+    };
+    browser.cursor = browser.lines.iter().position(|(address, _)| address.is_some()).unwrap_or(0);
+
+    let terminal = Terminal::enter();
+    let mut key_reader = KeyReader { pending: Vec::new() };
+    browser.run(&terminal, &mut key_reader);
+}
+
+// ---------------------------------------------------------------------------
+
+fn disassemble_with_project(load_cartridge: &dyn Fn() -> Cartridge, project: &Project) -> Cartridge {
+    let mut cartridge = load_cartridge();
+    cartridge.load_project(project);
+    cartridge.disassemble();
+    cartridge
+}
+
+// ---------------------------------------------------------------------------
+
+impl Browser<'_> {
+    fn run(&mut self, terminal: &Terminal, key_reader: &mut KeyReader) {
+        loop {
+            let (rows, columns) = terminal.size();
+            self.draw(rows, columns);
+
+            let page_rows = rows.saturating_sub(1).max(1);
+            match key_reader.read_key() {
+                Key::Up | Key::Character('k') => self.move_cursor(-1),
+                Key::Down | Key::Character('j') => self.move_cursor(1),
+                Key::PageUp => self.move_cursor(-(page_rows as isize)),
+                Key::PageDown => self.move_cursor(page_rows as isize),
+                Key::Home => self.cursor = 0,
+                Key::End => self.cursor = self.lines.len().saturating_sub(1),
+                Key::Enter | Key::Right => self.follow_operand(),
+                Key::Backspace | Key::Left => self.go_back(),
+                Key::Character('g') => {
+                    if let Some(target) = self.prompt(terminal, key_reader, "Go to address or label: ", "") {
+                        self.go_to_target(&target);
+                    }
+                },
+                Key::Character('x') => self.show_cross_references(terminal, key_reader),
+                Key::Character('n') => self.rename(terminal, key_reader),
+                Key::Character(';') => self.comment(terminal, key_reader),
+                Key::Character('q') => return,
+                _ => {},
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn draw(&mut self, rows: usize, columns: usize) {
+        let listing_rows = rows.saturating_sub(1).max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + listing_rows {
+            self.top = self.cursor + 1 - listing_rows;
+        }
+
+        let mut screen = String::from("\x1b[H");
+        for row in 0..listing_rows {
+            let line_index = self.top + row;
+            let text = self.lines.get(line_index).map_or("", |(_, text)| text.as_str());
+            let text = fit_to_width(text, columns);
+            if line_index == self.cursor {
+                screen.push_str(&format!("\x1b[7m{text}\x1b[0m"));
+            } else {
+                screen.push_str(&format!("{}\x1b[K", text.trim_end()));
+            }
+            screen.push_str("\r\n");
+        }
+        let status = format!("{} | {}", self.cartridge_filename, self.message);
+        screen.push_str(&format!("\x1b[7m{}\x1b[0m", fit_to_width(&status, columns.saturating_sub(1))));
+
+        print!("{screen}");
+        let _ = io::stdout().flush();
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn move_cursor(&mut self, line_count: isize) {
+        let last_line = self.lines.len().saturating_sub(1) as isize;
+        self.cursor = (self.cursor as isize + line_count).clamp(0, last_line) as usize;
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the address of the line under the cursor, or of the nearest line above it that
+    /// has one.
+    fn cursor_address(&self) -> Option<usize> {
+        self.lines.get(..=self.cursor)?.iter()
+            .rev()
+            .find_map(|(address, _)| *address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn go_to_address(&mut self, address: usize) {
+        let Some(line_index) = self.lines.iter().position(|(line_address, _)| *line_address == Some(address)) else {
+            self.message = match self.cartridge.name_at(address) {
+                Some(name) => format!("{name} (${address:04X}) isn't in the listing"),
+                None => format!("${address:04X} isn't in the listing"),
+            };
+            return;
+        };
+
+        self.history.push(self.cursor);
+        self.cursor = line_index;
+        self.top = line_index.saturating_sub(3);
+        self.message = String::from(HELP);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn go_to_target(&mut self, target: &str) {
+        let target = target.trim();
+        match self.cartridge.address_of_name(target).or_else(|| parse_hex_address(target)) {
+            Some(address) => self.go_to_address(address),
+            None => self.message = format!("No address or label called `{target}`"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn follow_operand(&mut self) {
+        let target_address = self.cursor_address().and_then(|address| self.cartridge.instruction_target_address(address));
+        match target_address {
+            Some(target_address) => self.go_to_address(target_address),
+            None => self.message = String::from("Nothing to follow here"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn go_back(&mut self) {
+        match self.history.pop() {
+            Some(line_index) => self.cursor = line_index,
+            None => self.message = String::from("Nowhere to go back to"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn show_cross_references(&mut self, terminal: &Terminal, key_reader: &mut KeyReader) {
+        let Some(address) = self.cursor_address() else {
+            return;
+        };
+        let cross_references = self.cartridge.cross_references_to(address);
+        if cross_references.is_empty() {
+            self.message = format!("Nothing refers to ${address:04X}");
+            return;
+        }
+
+        let items: Vec<String> = cross_references.iter()
+            .map(|(from_address, kind)| {
+                let line = bytes_line_index(&self.lines, *from_address).map_or("", |line_index| self.lines[line_index].1.trim());
+                format!("{kind:<10} {line}")
+            })
+            .collect();
+        let title = match self.cartridge.name_at(address) {
+            Some(name) => format!("Cross-references to {name} (${address:04X})"),
+            None => format!("Cross-references to ${address:04X}"),
+        };
+
+        if let Some(index) = self.choose(terminal, key_reader, &title, &items) {
+            self.go_to_address(cross_references[index].0);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn rename(&mut self, terminal: &Terminal, key_reader: &mut KeyReader) {
+        let Some(address) = self.cursor_address() else {
+            return;
+        };
+        let current_name = self.cartridge.name_at(address).unwrap_or_default();
+        let Some(name) = self.prompt(terminal, key_reader, &format!("Name ${address:04X}: "), &current_name) else {
+            return;
+        };
+
+        let name = name.trim();
+        if name.is_empty() {
+            self.project.labels.remove(&address);
+        } else if !is_valid_label(name) {
+            self.message = String::from("Names can only use letters, digits and underscores, and can't start with a digit");
+            return;
+        } else {
+            self.project.labels.insert(address, String::from(name));
+        }
+        self.save_and_reload(address);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn comment(&mut self, terminal: &Terminal, key_reader: &mut KeyReader) {
+        let Some(address) = self.cursor_address() else {
+            return;
+        };
+        let current_comment = self.project.comments.get(&address).cloned().unwrap_or_default();
+        let Some(comment) = self.prompt(terminal, key_reader, &format!("Comment ${address:04X}: "), &current_comment) else {
+            return;
+        };
+
+        let comment = comment.trim();
+        if comment.is_empty() {
+            self.project.comments.remove(&address);
+        } else {
+            self.project.comments.insert(address, String::from(comment));
+        }
+        self.save_and_reload(address);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn save_and_reload(&mut self, address: usize) {
+        if let Some(project_filename) = &self.project_filename {
+            self.project.save_to_file(project_filename);
+        }
+
+        // Disassembling prints its report, which the redraw clears away again.
+        print!("\x1b[2J\x1b[H");
+        self.cartridge = disassemble_with_project(self.load_cartridge, &self.project);
+        self.lines = self.cartridge.listing_lines();
+        print!("\x1b[2J");
+
+        let history = std::mem::take(&mut self.history);
+        self.cursor = self.cursor.min(self.lines.len().saturating_sub(1));
+        self.go_to_address(address);
+        self.history = history;
+        self.message = match &self.project_filename {
+            Some(project_filename) => format!("Saved {project_filename}"),
+            None => String::from("Not saved, since --no-project was given"),
+        };
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Reads a line of text on the status line, returning None if it's cancelled with escape.
+    fn prompt(&mut self, terminal: &Terminal, key_reader: &mut KeyReader, label: &str, initial_text: &str) -> Option<String> {
+        let mut text = String::from(initial_text);
+        loop {
+            let (rows, columns) = terminal.size();
+            print!("\x1b[{rows};1H\x1b[7m{}\x1b[0m\x1b[K\x1b[?25h", fit_to_width(&format!("{label}{text}"), columns.saturating_sub(1)).trim_end());
+            let _ = io::stdout().flush();
+
+            let key = key_reader.read_key();
+            print!("\x1b[?25l");
+            match key {
+                Key::Enter => return Some(text),
+                Key::Escape => {
+                    self.message = String::from(HELP);
+                    return None;
+                },
+                Key::Backspace => {
+                    text.pop();
+                },
+                Key::Character(character) => text.push(character),
+                _ => {},
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Shows `items` in a box over the listing and returns the one picked with enter, or None if
+    /// it's closed with escape.
+    fn choose(&mut self, terminal: &Terminal, key_reader: &mut KeyReader, title: &str, items: &[String]) -> Option<usize> {
+        let mut selected: usize = 0;
+        loop {
+            let (rows, columns) = terminal.size();
+            self.draw(rows, columns);
+
+            let width = columns.saturating_sub(8).max(20);
+            let visible_count = items.len().min(rows.saturating_sub(6).max(1));
+            let first_visible = selected.saturating_sub(visible_count - 1);
+
+            let mut popup = format!("\x1b[3;5H\x1b[7m{}\x1b[0m", fit_to_width(&format!(" {title}"), width));
+            for (row, item_index) in (first_visible..first_visible + visible_count).enumerate() {
+                let text = fit_to_width(&format!("  {}", items[item_index]), width);
+                let style = if item_index == selected { "\x1b[7m" } else { "\x1b[0m\x1b[100m" };
+                popup.push_str(&format!("\x1b[{};5H{style}{text}\x1b[0m", row + 4));
+            }
+            popup.push_str(&format!("\x1b[{};5H\x1b[7m{}\x1b[0m", visible_count + 4, fit_to_width(" enter:go  esc:close", width)));
+            print!("{popup}");
+            let _ = io::stdout().flush();
+
+            match key_reader.read_key() {
+                Key::Up | Key::Character('k') => selected = selected.saturating_sub(1),
+                Key::Down | Key::Character('j') => selected = (selected + 1).min(items.len() - 1),
+                Key::Enter => return Some(selected),
+                Key::Escape | Key::Character('q') => return None,
+                _ => {},
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl Terminal {
+    fn enter() -> Self {
+        let saved_settings = run_stty(&["-g"]).trim().to_string();
+        run_stty(&["raw", "-echo"]);
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe.
+        unsafe { signal(SIGWINCH, note_resize as *const () as usize) };
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        let _ = io::stdout().flush();
+
+        Self { saved_settings, size: Cell::new((24, 80)) }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the number of rows and columns, which are only queried again after the terminal
+    /// has been resized.
+    fn size(&self) -> (usize, usize) {
+        if WAS_RESIZED.swap(false, Ordering::Relaxed) {
+            let size = run_stty(&["size"]);
+            let mut fields = size.split_whitespace().filter_map(|field| field.parse::<usize>().ok());
+            self.size.set(match (fields.next(), fields.next()) {
+                (Some(rows), Some(columns)) if rows > 0 && columns > 0 => (rows, columns),
+                _ => (24, 80),
+            });
+        }
+        self.size.get()
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        // SAFETY: this puts back the default action, ignoring resizes.
+        unsafe { signal(SIGWINCH, SIG_DFL) };
+        run_stty(&[&self.saved_settings]);
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl KeyReader {
+    fn read_key(&mut self) -> Key {
+        if self.pending.is_empty() {
+            let mut buffer = [0u8; 64];
+            loop {
+                match io::stdin().read(&mut buffer) {
+                    Ok(byte_count) if byte_count > 0 => break self.pending.extend_from_slice(&buffer[..byte_count]),
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    _ => return Key::Character('q'),
+                }
+            }
+        }
+
+        let (key, byte_count) = decode_key(&self.pending);
+        self.pending.drain(..byte_count);
+        key
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Decodes the first key in `bytes`, returning it along with how many bytes it took up.
+fn decode_key(bytes: &[u8]) -> (Key, usize) {
+    match bytes {
+        [] => (Key::Unknown, 0),
+        [0x1B, b'[' | b'O', rest @ ..] => {
+            // Escape sequences end with a letter or `~`, e.g. `ESC [ A` or `ESC [ 5 ~`.
+            let Some(end) = rest.iter().position(|byte| byte.is_ascii_alphabetic() || *byte == b'~') else {
+                return (Key::Escape, bytes.len());
+            };
+            let key = match &rest[..=end] {
+                b"A" => Key::Up,
+                b"B" => Key::Down,
+                b"C" => Key::Right,
+                b"D" => Key::Left,
+                b"H" | b"1~" | b"7~" => Key::Home,
+                b"F" | b"4~" | b"8~" => Key::End,
+                b"5~" => Key::PageUp,
+                b"6~" => Key::PageDown,
+                _ => Key::Unknown,
+            };
+            (key, end + 3)
+        },
+        [0x1B, ..] => (Key::Escape, 1),
+        [b'\r' | b'\n', ..] => (Key::Enter, 1),
+        [0x7F | 0x08, ..] => (Key::Backspace, 1),
+        [byte, ..] if *byte < 0x20 => (Key::Unknown, 1),
+        _ => {
+            let character_length = match bytes[0] {
+                0xF0.. => 4,
+                0xE0.. => 3,
+                0xC0.. => 2,
+                _ => 1,
+            };
+            let character_bytes = &bytes[..character_length.min(bytes.len())];
+            match std::str::from_utf8(character_bytes).ok().and_then(|text| text.chars().next()) {
+                Some(character) => (Key::Character(character), character_bytes.len()),
+                None => (Key::Unknown, character_bytes.len()),
+            }
+        },
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the index of the line showing the bytes at `address`. Its labels and comments come
+/// before it, so it's the last of the lines for the address.
+fn bytes_line_index(lines: &[(Option<usize>, String)], address: usize) -> Option<usize> {
+    let first_line_index = lines.iter().position(|(line_address, _)| *line_address == Some(address))?;
+    lines[first_line_index..].iter()
+        .enumerate()
+        .filter_map(|(line_offset, (line_address, _))| Some((first_line_index + line_offset, (*line_address)?)))
+        .take_while(|(_, line_address)| *line_address == address)
+        .last()
+        .map(|(line_index, _)| line_index)
+}
+
+// ---------------------------------------------------------------------------
+
+fn is_valid_label(name: &str) -> bool {
+    name.chars().next().is_some_and(|character| character.is_ascii_alphabetic() || character == '_')
+        && name.chars().all(|character| character.is_ascii_alphanumeric() || character == '_')
+}
+
+// ---------------------------------------------------------------------------
+
+/// Cuts `text` off at `width` characters, or pads it with spaces to that width.
+fn fit_to_width(text: &str, width: usize) -> String {
+    let text: String = text.chars().take(width).collect();
+    let padding = width - text.chars().count();
+    format!("{text}{}", " ".repeat(padding))
+}
+
+// ---------------------------------------------------------------------------
+
+extern "C" fn note_resize(_signal_number: i32) {
+    WAS_RESIZED.store(true, Ordering::Relaxed);
+}
+
+// ---------------------------------------------------------------------------
+
+fn run_stty(args: &[&str]) -> String {
+    match Command::new("stty").args(args).stdin(Stdio::inherit()).output() {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).to_string(),
+        Err(error) => panic!("[ERROR] Could not run stty to set up the terminal: {error}"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_keys() {
+        assert_eq!(decode_key(b"\x1b[A"), (Key::Up, 3));
+        assert_eq!(decode_key(b"\x1b[6~j"), (Key::PageDown, 4));
+        assert_eq!(decode_key(b"\x1bOH"), (Key::Home, 3));
+        assert_eq!(decode_key(b"\x1b"), (Key::Escape, 1));
+        assert_eq!(decode_key(b"\r"), (Key::Enter, 1));
+        assert_eq!(decode_key(b"\x7f"), (Key::Backspace, 1));
+        assert_eq!(decode_key(b"xy"), (Key::Character('x'), 1));
+        assert_eq!(decode_key("é".as_bytes()), (Key::Character('é'), 2));
+    }
+
+    #[test]
+    fn finds_the_line_showing_the_bytes_at_an_address() {
+        let lines: Vec<(Option<usize>, String)> = [
+            (Some(0x8000), "Reset: [8000]"),
+            (Some(0x8000), "loop_0: [8000]"),
+            (None, "    ; wait"),
+            (Some(0x8000), "    JMP loop_0        # 8000 | 4C 00 80"),
+            (Some(0x8003), "    .byte $00        # 8003"),
+        ].into_iter().map(|(address, text)| (address, String::from(text))).collect();
+
+        assert_eq!(bytes_line_index(&lines, 0x8000), Some(3));
+        assert_eq!(bytes_line_index(&lines, 0x8003), Some(4));
+        assert_eq!(bytes_line_index(&lines, 0x8001), None);
+    }
+
+    #[test]
+    fn validates_labels() {
+        assert!(is_valid_label("Reset_2"));
+        assert!(is_valid_label("_loop"));
+        assert!(!is_valid_label("2fast"));
+        assert!(!is_valid_label("has space"));
+        assert!(!is_valid_label(""));
+    }
+
+    #[test]
+    fn fits_text_to_width() {
+        assert_eq!(fit_to_width("abc", 5), "abc  ");
+        assert_eq!(fit_to_width("abcdef", 4), "abcd");
+    }
+}
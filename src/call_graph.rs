@@ -0,0 +1,362 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::control_flow::{escape_dot, BasicBlock, ControlFlowGraph, EdgeKind};
+
+// The calls between routines, built from the control flow graph. Besides JSRs, control can pass
+// into another routine without pushing a return address, by jumping, branching or falling into
+// it. Those tail calls leave the callee returning to the caller's caller, so they don't add to
+// the call depth.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CallKind {
+    Call,
+    TailCall,
+}
+
+pub struct CallGraph {
+    routines: BTreeSet<usize>,
+    callees: BTreeMap<usize, BTreeSet<(usize, CallKind)>>,
+    /// The strongly connected components, each one after every component it calls into.
+    components: Vec<BTreeSet<usize>>,
+    component_ids: BTreeMap<usize, usize>,
+    component_depths: Vec<Option<usize>>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl CallKind {
+    fn depth(&self) -> usize {
+        match self {
+            CallKind::Call => 1,
+            CallKind::TailCall => 0,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl CallGraph {
+    pub fn new(
+        control_flow_graph: &ControlFlowGraph,
+        basic_blocks: &BTreeMap<usize, BasicBlock>,
+        routine_entry_points: &BTreeSet<usize>,
+    ) -> Self {
+        let mut callees = BTreeMap::new();
+
+        for entry_point in routine_entry_points {
+            let mut routine_callees = BTreeSet::new();
+            for block_address in control_flow_graph.routine_blocks(basic_blocks, *entry_point, routine_entry_points) {
+                for (target_address, kind) in &basic_blocks[&block_address].successors {
+                    if *kind == EdgeKind::Call {
+                        routine_callees.insert((*target_address, CallKind::Call));
+                    } else if routine_entry_points.contains(target_address) && target_address != entry_point {
+                        routine_callees.insert((*target_address, CallKind::TailCall));
+                    }
+                }
+            }
+            callees.insert(*entry_point, routine_callees);
+        }
+
+        let mut call_graph = Self {
+            routines: routine_entry_points.clone(),
+            callees,
+            components: Vec::new(),
+            component_ids: BTreeMap::new(),
+            component_depths: Vec::new(),
+        };
+        call_graph.find_components();
+        call_graph.calculate_component_depths();
+        call_graph
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Finds the strongly connected components with Tarjan's algorithm, which emits a component
+    /// only after all the ones reachable from it. It's run with an explicit stack, since call
+    /// chains can be long.
+    fn find_components(&mut self) {
+        let mut nodes: BTreeSet<usize> = self.routines.clone();
+        nodes.extend(self.callees.values().flatten().map(|(callee, _)| *callee));
+
+        let mut indices: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut low_links: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut component_stack: Vec<usize> = Vec::new();
+        let mut on_component_stack: BTreeSet<usize> = BTreeSet::new();
+
+        for root in nodes {
+            if indices.contains_key(&root) {
+                continue;
+            }
+
+            // Each frame holds a routine and the callees it still has to visit.
+            let mut frames: Vec<(usize, Vec<usize>)> = Vec::new();
+            let mut pending_routine = Some(root);
+            loop {
+                if let Some(routine) = pending_routine.take() {
+                    indices.insert(routine, indices.len());
+                    low_links.insert(routine, indices[&routine]);
+                    component_stack.push(routine);
+                    on_component_stack.insert(routine);
+                    frames.push((routine, self.callees(routine).into_iter().map(|(callee, _)| callee).collect()));
+                }
+
+                let Some((routine, remaining_callees)) = frames.last_mut() else {
+                    break;
+                };
+                let routine = *routine;
+
+                if let Some(callee) = remaining_callees.pop() {
+                    if !indices.contains_key(&callee) {
+                        pending_routine = Some(callee);
+                    } else if on_component_stack.contains(&callee) {
+                        low_links.insert(routine, low_links[&routine].min(indices[&callee]));
+                    }
+                    continue;
+                }
+
+                frames.pop();
+                if let Some((caller, _)) = frames.last() {
+                    low_links.insert(*caller, low_links[caller].min(low_links[&routine]));
+                }
+
+                if low_links[&routine] == indices[&routine] {
+                    let mut component = BTreeSet::new();
+                    while let Some(member) = component_stack.pop() {
+                        on_component_stack.remove(&member);
+                        self.component_ids.insert(member, self.components.len());
+                        component.insert(member);
+                        if member == routine {
+                            break;
+                        }
+                    }
+                    self.components.push(component);
+                }
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Works out the deepest nesting of JSRs from each component. The components are in callee
+    /// first order, so the depths of everything a component calls are already known.
+    fn calculate_component_depths(&mut self) {
+        for (component_id, component) in self.components.iter().enumerate() {
+            let mut max_depth = if self.is_recursive(component) { None } else { Some(0) };
+            for routine in component {
+                for (callee, kind) in self.callees(*routine) {
+                    let callee_component_id = self.component_ids[&callee];
+                    if callee_component_id == component_id {
+                        continue;
+                    }
+
+                    max_depth = match (max_depth, self.component_depths[callee_component_id]) {
+                        (Some(max_depth), Some(callee_depth)) => Some(max_depth.max(kind.depth() + callee_depth)),
+                        _ => None,
+                    };
+                }
+            }
+            self.component_depths.push(max_depth);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn routines(&self) -> &BTreeSet<usize> {
+        &self.routines
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn callees(&self, routine: usize) -> Vec<(usize, CallKind)> {
+        self.callees.get(&routine).map_or_else(Vec::new, |callees| callees.iter().copied().collect())
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn callers(&self, routine: usize) -> Vec<(usize, CallKind)> {
+        self.callees.iter()
+            .flat_map(|(caller, callees)| callees.iter()
+                .filter(|(callee, _)| *callee == routine)
+                .map(|(_, kind)| (*caller, *kind)))
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn is_leaf(&self, routine: usize) -> bool {
+        self.callees.get(&routine).is_none_or(BTreeSet::is_empty)
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns every routine reachable from `roots`, including the roots themselves.
+    pub fn reachable_from(&self, roots: &[usize]) -> BTreeSet<usize> {
+        let mut reachable_routines = BTreeSet::new();
+        let mut pending_routines = roots.to_vec();
+
+        while let Some(routine) = pending_routines.pop() {
+            if !reachable_routines.insert(routine) {
+                continue;
+            }
+            pending_routines.extend(self.callees(routine).into_iter().map(|(callee, _)| callee));
+        }
+
+        reachable_routines
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the groups of routines that can end up calling themselves, directly or through
+    /// each other. Routines that only tail call each other in a loop aren't recursive, since
+    /// the stack doesn't grow.
+    pub fn recursive_groups(&self) -> Vec<BTreeSet<usize>> {
+        let mut recursive_groups: Vec<BTreeSet<usize>> = self.components.iter()
+            .filter(|component| self.is_recursive(component))
+            .cloned()
+            .collect();
+        recursive_groups.sort();
+        recursive_groups
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the deepest nesting of JSRs starting from `root`, or `None` if a recursive
+    /// routine can be reached from it, since the depth is unbounded then.
+    pub fn max_call_depth(&self, root: usize) -> Option<usize> {
+        self.component_ids.get(&root).map_or(Some(0), |component_id| self.component_depths[*component_id])
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn is_recursive(&self, component: &BTreeSet<usize>) -> bool {
+        component.iter().any(|routine| {
+            self.callees(*routine).into_iter()
+                .any(|(callee, kind)| kind == CallKind::Call && component.contains(&callee))
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Renders the calls between routines as a Graphviz digraph. Tail calls are drawn dashed.
+pub fn format_call_graph_dot(call_graph: &CallGraph, name_of: &dyn Fn(usize) -> String) -> String {
+    let mut dot = String::from("digraph \"call_graph\" {\n");
+    dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+    for routine in call_graph.routines() {
+        dot.push_str(&format!("    \"{:04X}\" [label=\"{}\"];\n", routine, escape_dot(&name_of(*routine))));
+    }
+
+    for routine in call_graph.routines() {
+        for (callee, kind) in call_graph.callees(*routine) {
+            let style = match kind {
+                CallKind::Call => "solid",
+                CallKind::TailCall => "dashed",
+            };
+            dot.push_str(&format!("    \"{:04X}\" -> \"{:04X}\" [style={style}];\n", routine, callee));
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a graph where each routine is a single block at its entry point.
+    fn create_call_graph(edges: &[(usize, usize, EdgeKind)]) -> CallGraph {
+        let mut control_flow_graph = ControlFlowGraph::new();
+        let mut routine_entry_points = BTreeSet::new();
+        for (from_address, to_address, kind) in edges {
+            for address in [*from_address, *to_address] {
+                control_flow_graph.add_entry_point(address);
+                control_flow_graph.add_instruction(address, 3);
+                routine_entry_points.insert(address);
+            }
+            control_flow_graph.add_edge(*from_address, *to_address, *kind);
+        }
+
+        CallGraph::new(&control_flow_graph, &control_flow_graph.basic_blocks(), &routine_entry_points)
+    }
+
+    #[test]
+    fn callers_callees_and_leaves() {
+        let call_graph = create_call_graph(&[
+            (0x8000, 0x9000, EdgeKind::Call),
+            (0x8000, 0x9100, EdgeKind::Call),
+            (0x9000, 0x9100, EdgeKind::Jump),
+        ]);
+
+        assert_eq!(call_graph.callees(0x8000), vec![(0x9000, CallKind::Call), (0x9100, CallKind::Call)]);
+        assert_eq!(call_graph.callers(0x9100), vec![(0x8000, CallKind::Call), (0x9000, CallKind::TailCall)]);
+        assert!(call_graph.is_leaf(0x9100));
+        assert!(!call_graph.is_leaf(0x9000));
+    }
+
+    #[test]
+    fn tail_calls_do_not_add_depth() {
+        let call_graph = create_call_graph(&[
+            (0x8000, 0x9000, EdgeKind::Call),
+            (0x9000, 0x9100, EdgeKind::Jump),
+            (0x9100, 0x9200, EdgeKind::Call),
+        ]);
+
+        assert_eq!(call_graph.max_call_depth(0x8000), Some(2));
+        assert_eq!(call_graph.max_call_depth(0x9200), Some(0));
+    }
+
+    #[test]
+    fn detects_recursion() {
+        let call_graph = create_call_graph(&[
+            (0x8000, 0x9000, EdgeKind::Call),
+            (0x9000, 0x9100, EdgeKind::Call),
+            (0x9100, 0x9000, EdgeKind::Jump),
+            (0x8000, 0xA000, EdgeKind::Jump),
+            (0xA000, 0x8000, EdgeKind::Jump),
+        ]);
+
+        assert_eq!(call_graph.recursive_groups(), vec![BTreeSet::from([0x9000, 0x9100])]);
+        assert_eq!(call_graph.max_call_depth(0x8000), None);
+        assert_eq!(call_graph.max_call_depth(0xA000), None);
+    }
+
+    #[test]
+    fn finds_components_in_long_chains_and_cycles() {
+        let mut edges: Vec<(usize, usize, EdgeKind)> = (0..2000).map(|index| (0x8000 + index * 3, 0x8003 + index * 3, EdgeKind::Call)).collect();
+        edges.push((0x8000 + 1000 * 3, 0x8000 + 500 * 3, EdgeKind::Call));
+
+        let call_graph = create_call_graph(&edges);
+        let recursive_groups = call_graph.recursive_groups();
+        assert_eq!(recursive_groups.len(), 1);
+        assert_eq!(recursive_groups[0].len(), 501);
+        assert_eq!(call_graph.max_call_depth(0x8000), None);
+        assert_eq!(call_graph.max_call_depth(0x8000 + 1001 * 3), Some(999));
+    }
+
+    #[test]
+    fn reachability() {
+        let call_graph = create_call_graph(&[
+            (0x8000, 0x9000, EdgeKind::Call),
+            (0xA000, 0x9100, EdgeKind::Call),
+        ]);
+
+        assert_eq!(call_graph.reachable_from(&[0x8000]), BTreeSet::from([0x8000, 0x9000]));
+    }
+
+    #[test]
+    fn formats_dot() {
+        let call_graph = create_call_graph(&[
+            (0x8000, 0x9000, EdgeKind::Call),
+            (0x9000, 0x9100, EdgeKind::Jump),
+        ]);
+        let name_of = |address: usize| format!("L{address:04X}");
+
+        let dot = format_call_graph_dot(&call_graph, &name_of);
+        assert!(dot.contains("\"8000\" -> \"9000\" [style=solid];"));
+        assert!(dot.contains("\"9000\" -> \"9100\" [style=dashed];"));
+    }
+}
@@ -1,19 +1,79 @@
-use std::{collections::HashMap, fs::File, io::Read, vec};
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, fs, vec};
 
-use crate::{instruction::disassemble_instruction, labeller::{Labeller}};
+use crate::annotations::{Annotations, DataAnnotation, RecordField, SubroutineAnnotation};
+use crate::call_graph::{format_call_graph_dot, CallGraph, CallKind};
+use crate::checksums::{crc32, format_sha1, sha1};
+use crate::code_data_log::CodeDataLog;
+use crate::control_flow::{format_routine_dot, ControlFlowGraph, EdgeKind};
+use crate::debug_info::DebugInfo;
+use crate::emulator::{emulate, EmulationOptions};
+use crate::fceux::{format_nl, load_nl_file, name_list_bank, FceuxName};
+use crate::fds::{self, is_fds_image, parse_fds, DiskFile, DiskFileKind};
+use crate::header_database::HeaderDatabase;
+use crate::html::{format_pages, Listing, ListingBank, ListingLine, OutsideAddress, HEX_ROW_BYTES};
+use crate::instruction::{disassemble_instruction, register_name, DisassembledInstruction};
+use crate::json::JsonValue;
+use crate::labeller::Labeller;
+use crate::linear_sweep::find_probable_code;
+use crate::mesen::{load_mlb_file, MesenMemoryType};
+use crate::nes_header::{parse_nes_file, NesHeader, CHR_ROM_BANK_BYTES, NES_HEADER_BYTES, PRG_ROM_BANK_BYTES};
+use crate::nsf::{self, is_nsf_file, parse_nsf};
+use crate::opcodes::{decode_opcode, AddressingMode};
+use crate::project::Project;
+use crate::register_writes::RegisterWriteTracker;
+use crate::return_address::manipulates_return_address;
+use crate::stack_usage::{analyze_stack_usage, INTERRUPT_BYTES};
+use crate::symbols::{Symbol, SymbolTable};
+use crate::timing::{calculate_instruction_timing, ends_basic_block, BasicBlockTiming};
+use crate::unif::{board_mapper_id, is_unif_file, parse_unif};
 
-const NES_HEADER_BYTES: usize = 16;
+const FDS_MAPPER_ID: u16 = 20;
+const FDS_RAM_START: usize = 0x6000;
+const FDS_RAM_END: usize = 0xE000;
+
+/// Where a cartridge's NMI, RESET and IRQ vectors start, at the end of the last PRG bank.
+const CARTRIDGE_VECTORS_ADDRESS: usize = 0xFFFA;
+
+/// The PRG ROM sizes an NROM board can hold, repeated as needed to fill $8000-$FFFF.
+const NROM_PRG_ROM_SIZES: &[usize] = &[0x2000, 0x4000, 0x8000];
+
+/// Bumped whenever the layout of the JSON export changes in a way that could break its readers.
+const JSON_SCHEMA_VERSION: i64 = 1;
+
+/// The vectors in the order they're stored in memory, for the exported vectors file.
+const VECTOR_ORDER: &[&str] = &["NMI1", "NMI2", "NMI3", "NMI", "RESET", "IRQ", "INIT", "PLAY"];
 
 pub struct Cartridge {
-    mapper_id: u8,
-    prg_rom_bank_count: u8,
-    chr_rom_bank_count: u8,
+    mapper_id: u16,
+    prg_rom_bank_count: usize,
+    #[allow(dead_code)]
+    chr_rom_bank_count: usize,
     prg_rom_contents: Vec<u8>,
     chr_rom_contents: Vec<u8>,
+    prg_base_address: usize,
+    music_entry_points: Option<(usize, usize)>,
+    header: Option<NesHeader>,
+    prg_bank_mapping: Option<[usize; 2]>,
 
+    vectors: Vec<(&'static str, usize)>,
     global_labels: HashMap<usize, String>,
     labeller: Labeller,
     text_lines: HashMap<usize, TextLine>,
+    inline_data: BTreeMap<usize, InlineData>,
+    data_tables: BTreeMap<usize, DataTable>,
+    unexplored_data: BTreeMap<usize, usize>,
+    control_flow_graph: ControlFlowGraph,
+    annotations: Annotations,
+    return_address_subroutines: BTreeSet<usize>,
+    project_entry_points: BTreeMap<usize, String>,
+    forced_code: BTreeMap<usize, usize>,
+    forced_data: BTreeMap<usize, usize>,
+
+    debug_info: Option<DebugInfo>,
+    code_data_log: Option<CodeDataLog>,
+    emulation_options: Option<EmulationOptions>,
+    should_show_cycle_counts: bool,
+    should_sweep_for_code: bool,
 }
 
 // ---------------------------------------------------------------------------
@@ -22,157 +82,3032 @@ impl Cartridge {
     pub fn load_from_file(filename: &str) -> Self {
         println!("------------------------------------------------------------------------------");
 
-        let mut file = match File::open(filename) {
-            Ok(file) => file,
-            Err(error) => panic!("[ERROR] Could not open cartridge file: {error}"),
+        let contents = match fs::read(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read cartridge file: {error}"),
+        };
+
+        let header = &contents[..contents.len().min(NES_HEADER_BYTES)];
+        if is_fds_image(header) {
+            panic!("[ERROR] {filename} is a Famicom Disk System image; give it an .fds extension to load it as one!");
+        }
+        if is_unif_file(header) {
+            panic!("[ERROR] {filename} is a UNIF file; give it a .unf extension to load it as one!");
+        }
+        if is_nsf_file(header) {
+            panic!("[ERROR] {filename} is an NSF music rip; give it an .nsf or .nsfe extension to load it as one!");
+        }
+
+        let nes_file = match parse_nes_file(&contents) {
+            Ok(nes_file) => nes_file,
+            Err(error) => panic!("[ERROR] Could not load cartridge from file: {error}"),
         };
+        let mapper_id = nes_file.header.mapper_id;
+        if !fits_mapper(mapper_id, nes_file.prg_rom_contents.len()) {
+            panic!("[ERROR] Mapper {mapper_id} can't hold the {} bytes of PRG ROM in {filename}!", nes_file.prg_rom_contents.len());
+        }
+        let prg_rom_bank_count = nes_file.header.prg_rom_bytes.div_ceil(PRG_ROM_BANK_BYTES);
+        let chr_rom_bank_count = nes_file.header.chr_rom_bytes.div_ceil(CHR_ROM_BANK_BYTES);
+
+        println!("Mapper: {mapper_id}");
+        if nes_file.header.is_nes2 {
+            println!("Submapper: {}", nes_file.header.submapper_id);
+        }
+        println!("PRG ROM bank count: {prg_rom_bank_count}");
+        println!("CHR ROM bank count: {chr_rom_bank_count}");
+
+        let mut cartridge = Self::new(
+            mapper_id,
+            prg_rom_bank_count,
+            chr_rom_bank_count,
+            nes_file.prg_rom_contents.to_vec(),
+            nes_file.chr_rom_contents.to_vec(),
+            0x8000,
+        );
+        cartridge.header = Some(nes_file.header);
+        cartridge
+    }
+
+    // -----------------------------------------------------------------------
 
-        let mut header = [0u8; NES_HEADER_BYTES];
-        if let Err(error) = file.read_exact(&mut header) {
-            panic!("[ERROR] Could not read cartridge file header: {error}");
+    /// Loads a UNIF file, taking the mapper from its board name. Chunks that aren't understood are
+    /// reported and skipped.
+    pub fn load_from_unif_file(filename: &str) -> Self {
+        println!("------------------------------------------------------------------------------");
+
+        let contents = match fs::read(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read UNIF file: {error}"),
+        };
+        let unif_file = match parse_unif(&contents) {
+            Ok(unif_file) => unif_file,
+            Err(error) => panic!("[ERROR] Could not parse UNIF file {filename}: {error}"),
+        };
+        let Some(mapper_id) = board_mapper_id(&unif_file.board_name) else {
+            panic!("[ERROR] UNIF board {} doesn't match any known mapper!", unif_file.board_name);
+        };
+        if !fits_mapper(mapper_id as u16, unif_file.prg_rom_contents.len()) {
+            panic!("[ERROR] Board {} can't hold the {} bytes of PRG ROM in {filename}!", unif_file.board_name, unif_file.prg_rom_contents.len());
         }
 
-        let mapper_id = (header[7] & 0xF0) | (header[6] >> 4);
-        let prg_rom_bank_count = header[4];
-        let chr_rom_bank_count = header[5];
+        let prg_rom_bank_count = unif_file.prg_rom_contents.len().div_ceil(PRG_ROM_BANK_BYTES);
+        let chr_rom_bank_count = unif_file.chr_rom_contents.len().div_ceil(CHR_ROM_BANK_BYTES);
 
+        println!("UNIF revision: {}", unif_file.revision);
+        if let Some(name) = &unif_file.name {
+            println!("Name: {name}");
+        }
+        println!("Board: {}", unif_file.board_name);
         println!("Mapper: {mapper_id}");
         println!("PRG ROM bank count: {prg_rom_bank_count}");
         println!("CHR ROM bank count: {chr_rom_bank_count}");
+        if let Some(mirroring) = unif_file.mirroring {
+            println!("Mirroring: {mirroring}");
+        }
+        println!("Battery: {}", if unif_file.has_battery { "yes" } else { "no" });
+        for (id, length) in &unif_file.unknown_chunks {
+            println!("Unknown chunk: {id} ({length} bytes), skipped");
+        }
 
-        let mut prg_rom_contents = vec![0u8; prg_rom_bank_count as usize * 16384];
-        if let Err(error) = file.read_exact(&mut prg_rom_contents) {
-            panic!("[ERROR] Could not load cartridge from file: {error}");
+        Self::new(
+            mapper_id as u16,
+            prg_rom_bank_count,
+            chr_rom_bank_count,
+            unif_file.prg_rom_contents,
+            unif_file.chr_rom_contents,
+            0x8000,
+        )
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Loads one side of a Famicom Disk System image. The PRG files the BIOS boots are loaded
+    /// into the $6000-$DFFF RAM first, then any others that don't overlap them, and each file's
+    /// name is attached to its load address as a comment.
+    pub fn load_from_fds_file(filename: &str, side_index: usize) -> Self {
+        println!("------------------------------------------------------------------------------");
+
+        let contents = match fs::read(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read disk image file: {error}"),
         };
+        let mut sides = match parse_fds(&contents) {
+            Ok(sides) => sides,
+            Err(error) => panic!("[ERROR] Could not parse disk image file {filename}: {error}"),
+        };
+        if side_index >= sides.len() {
+            panic!("[ERROR] Disk side {} was requested, but {filename} only has {} sides!", side_index + 1, sides.len());
+        }
+        let side = sides.swap_remove(side_index);
+
+        println!("Disk side: {} of {}", side_index + 1, sides.len() + 1);
+        println!("Game name: {}", side.game_name);
+        println!("Disk number: {}, side number: {}", side.disk_number + 1, side.side_number + 1);
+        println!("Files: {} ({} declared, boot files have ids up to ${:02X})", side.files.len(), side.declared_file_count, side.boot_file_id);
+
+        let mut ram_contents = vec![0u8; FDS_RAM_END - FDS_RAM_START];
+        let mut loaded_offsets = vec![false; ram_contents.len()];
+        let mut symbols = SymbolTable::new();
+
+        let (boot_files, other_files): (Vec<&DiskFile>, Vec<&DiskFile>) = side.files.iter()
+            .partition(|file| file.id <= side.boot_file_id && file.number < side.declared_file_count as u8);
+        for file in boot_files.iter().chain(other_files.iter()) {
+            let end_address = file.load_address + file.contents.len();
+            let status = if file.kind != DiskFileKind::Program {
+                format!("{} data, not disassembled", file.kind.name())
+            } else if file.load_address < FDS_RAM_START || end_address > FDS_RAM_END {
+                String::from("outside of $6000-$DFFF, not disassembled")
+            } else if loaded_offsets[file.load_address - FDS_RAM_START..end_address - FDS_RAM_START].iter().any(|is_loaded| *is_loaded) {
+                String::from("overlaps an earlier file, not disassembled")
+            } else {
+                let start_offset = file.load_address - FDS_RAM_START;
+                ram_contents[start_offset..start_offset + file.contents.len()].copy_from_slice(&file.contents);
+                loaded_offsets[start_offset..start_offset + file.contents.len()].fill(true);
+                symbols.insert_comment(file.load_address, format!("disk file #{} {}, {} bytes", file.number, file.name, file.contents.len()));
+                String::from(if boot_files.iter().any(|boot_file| std::ptr::eq(*boot_file, *file)) { "loaded at boot" } else { "loaded" })
+            };
+
+            println!(
+                "  #{} id ${:02X} {:<8} {} ${:04X}-${:04X}: {status}",
+                file.number, file.id, file.name, file.kind.name(), file.load_address, end_address.saturating_sub(1));
+        }
+
+        for (address, name, size) in fds::REGISTERS {
+            symbols.insert(*address, Symbol { name: name.to_string(), size: *size, comment: None });
+        }
+        for (address, name, _) in fds::BIOS_ROUTINES {
+            symbols.insert(*address, Symbol { name: name.to_string(), size: 1, comment: None });
+        }
+
+        let mut cartridge = Self::new(FDS_MAPPER_ID, 0, 0, ram_contents, Vec::new(), FDS_RAM_START);
+        cartridge.load_symbols(symbols);
+        for (address, _, pointer_bytes) in fds::BIOS_ROUTINES {
+            if *pointer_bytes > 0 {
+                cartridge.annotations.insert_subroutine(*address, SubroutineAnnotation::InlineData(*pointer_bytes));
+            }
+        }
+        cartridge.annotations.insert_subroutine(fds::BIOS_JUMP_ENGINE, SubroutineAnnotation::PointerTable(None));
+
+        cartridge
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Loads an NSF or NSFe music rip, with its banks mapped the way the header initializes them.
+    /// The init and play routines become the entry points, in place of the vectors. Bank switches
+    /// made by the music aren't followed, so banks that aren't mapped initially are only reported.
+    pub fn load_from_nsf_file(filename: &str) -> Self {
+        println!("------------------------------------------------------------------------------");
 
-        let mut chr_rom_contents = vec![0u8; chr_rom_bank_count as usize * 8192];
-        if let Err(error) = file.read_exact(&mut chr_rom_contents) {
-            panic!("[ERROR] Could not load cartridge from file: {error}");
+        let contents = match fs::read(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read music file: {error}"),
+        };
+        let nsf_file = match parse_nsf(&contents) {
+            Ok(nsf_file) => nsf_file,
+            Err(error) => panic!("[ERROR] Could not parse music file {filename}: {error}"),
         };
 
+        let expansion_chip_names = nsf_file.expansion_chip_names();
+        println!("Title: {}", nsf_file.title);
+        println!("Artist: {}", nsf_file.artist);
+        println!("Copyright: {}", nsf_file.copyright);
+        println!("Songs: {} (starting with {})", nsf_file.song_count, nsf_file.starting_song);
+        println!("Load address: ${:04X}", nsf_file.load_address);
+        println!("Init address: ${:04X}", nsf_file.init_address);
+        println!("Play address: ${:04X}", nsf_file.play_address);
+        println!("Expansion audio: {}", if expansion_chip_names.is_empty() { String::from("none") } else { expansion_chip_names.join(", ") });
+        for chunk in &nsf_file.skipped_chunks {
+            println!("Skipped optional chunk: {chunk}");
+        }
+
+        // Only the FDS sound chip comes with RAM below $8000 that music can be loaded into.
+        let has_fds_audio = nsf_file.expansion_chips & 0x04 != 0;
+        let prg_base_address = if has_fds_audio { FDS_RAM_START } else { 0x8000 };
+        if nsf_file.load_address < prg_base_address {
+            panic!("[ERROR] Load address ${:04X} is below ${:04X}!", nsf_file.load_address, prg_base_address);
+        }
+
+        let mut prg_contents = vec![0u8; 0x10000 - prg_base_address];
+        let mut symbols = SymbolTable::new();
+        match nsf_file.initial_banks {
+            Some(initial_banks) => {
+                let mut padded_data = vec![0u8; nsf_file.load_address % nsf::BANK_BYTES];
+                padded_data.extend_from_slice(&nsf_file.data);
+                let banks: Vec<&[u8]> = padded_data.chunks(nsf::BANK_BYTES).collect();
+
+                println!("Banks: {} of 4 KiB, initially mapped as:", banks.len());
+                let mut mapped_banks = BTreeSet::new();
+                for (register, name, bank_address) in nsf::BANK_REGISTERS {
+                    if *bank_address < prg_base_address {
+                        continue;
+                    }
+
+                    // With FDS audio, $6000 and $7000 start out with the banks given for $E000 and $F000.
+                    let bank = initial_banks[bank_address / nsf::BANK_BYTES % 8] as usize;
+                    let offset = bank_address - prg_base_address;
+                    if let Some(bank_contents) = banks.get(bank) {
+                        prg_contents[offset..offset + bank_contents.len()].copy_from_slice(bank_contents);
+                    }
+                    mapped_banks.insert(bank);
+                    println!("  ${:04X}-${:04X}: bank {bank}", bank_address, bank_address + nsf::BANK_BYTES - 1);
+                    symbols.insert(*register, Symbol { name: name.to_string(), size: 1, comment: None });
+                }
+
+                // Only the initial mapping is traced, so whatever the music switches in later is missed.
+                let unmapped_banks: Vec<String> = (0..banks.len())
+                    .filter(|bank| !mapped_banks.contains(bank))
+                    .map(|bank| bank.to_string())
+                    .collect();
+                if !unmapped_banks.is_empty() {
+                    println!("  warning: banks {} aren't mapped initially, so they aren't disassembled", unmapped_banks.join(", "));
+                }
+            },
+            None => {
+                let offset = nsf_file.load_address - prg_base_address;
+                let byte_count = nsf_file.data.len().min(prg_contents.len() - offset);
+                prg_contents[offset..offset + byte_count].copy_from_slice(&nsf_file.data[..byte_count]);
+                if byte_count < nsf_file.data.len() {
+                    println!("Ignored {} bytes past $FFFF", nsf_file.data.len() - byte_count);
+                }
+            },
+        }
+
+        let mut cartridge = Self::new(0, 0, 0, prg_contents, Vec::new(), prg_base_address);
+        cartridge.music_entry_points = Some((nsf_file.init_address, nsf_file.play_address));
+
+        for (flag, _, registers) in nsf::EXPANSION_CHIPS {
+            if nsf_file.expansion_chips & flag == 0 {
+                continue;
+            }
+            for (address, name, size) in *registers {
+                if *address >= prg_base_address {
+                    cartridge.labeller.add_write_only_register(*address, name);
+                } else {
+                    symbols.insert(*address, Symbol { name: name.to_string(), size: *size, comment: None });
+                }
+            }
+        }
+        cartridge.load_symbols(symbols);
+
+        cartridge
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn new(
+        mapper_id: u16,
+        prg_rom_bank_count: usize,
+        chr_rom_bank_count: usize,
+        prg_rom_contents: Vec<u8>,
+        chr_rom_contents: Vec<u8>,
+        prg_base_address: usize,
+    ) -> Self {
         Self {
             mapper_id,
             prg_rom_bank_count,
             chr_rom_bank_count,
             prg_rom_contents,
             chr_rom_contents,
+            prg_base_address,
+            music_entry_points: None,
+            header: None,
+            prg_bank_mapping: None,
 
+            vectors: Vec::new(),
             global_labels: HashMap::new(),
             labeller: Labeller::new(),
             text_lines: HashMap::new(),
+            inline_data: BTreeMap::new(),
+            data_tables: BTreeMap::new(),
+            unexplored_data: BTreeMap::new(),
+            control_flow_graph: ControlFlowGraph::new(),
+            annotations: Annotations::new(),
+            return_address_subroutines: BTreeSet::new(),
+            project_entry_points: BTreeMap::new(),
+            forced_code: BTreeMap::new(),
+            forced_data: BTreeMap::new(),
+
+            debug_info: None,
+            code_data_log: None,
+            emulation_options: None,
+            should_show_cycle_counts: false,
+            should_sweep_for_code: false,
         }
     }
 
     // -----------------------------------------------------------------------
 
-    pub fn disassemble(&mut self) {
-        println!("------------------------------------------------------------------------------");
+    pub fn load_symbols(&mut self, symbols: SymbolTable) {
+        self.labeller.add_symbols(symbols);
+    }
 
-        if self.mapper_id != 0 {
-            panic!("[ERROR] Mapper is {}, but only mapper 0 is supported right now!", self.mapper_id);
-        }
+    // -----------------------------------------------------------------------
 
-        let vectors_base_address: usize = if self.prg_rom_bank_count > 1 { 0x7FFA } else { 0x3FFA };
-        let nmi_vector = (self.prg_rom_contents[vectors_base_address + 1] as usize) << 8;
-        let nmi_vector = nmi_vector | (self.prg_rom_contents[vectors_base_address] as usize);
-        let reset_vector = (self.prg_rom_contents[vectors_base_address + 3] as usize) << 8;
-        let reset_vector = reset_vector | (self.prg_rom_contents[vectors_base_address + 2] as usize);
-        let irq_vector = (self.prg_rom_contents[vectors_base_address + 5] as usize) << 8;
-        let irq_vector = irq_vector | (self.prg_rom_contents[vectors_base_address + 4] as usize);
+    /// Applies a project file on top of anything else loaded. Its names and comments win over
+    /// loaded symbols, keeping the size of a symbol that gets renamed, and its entry points and
+    /// regions are used when disassembling.
+    pub fn load_project(&mut self, project: &Project) {
+        let existing_symbols = self.labeller.symbols();
+        let mut symbols = SymbolTable::new();
 
-        println!("  nmi vector: ${:04X}", nmi_vector);
-        println!("reset vector: ${:04X}", reset_vector);
-        println!("  irq vector: ${:04X}", irq_vector);
-        println!("------------------------------------------------------------------------------");
+        for (address, name) in &project.labels {
+            let existing_symbol = existing_symbols.get_exact(*address);
+            symbols.insert(*address, Symbol {
+                name: name.clone(),
+                size: existing_symbol.map_or(1, |symbol| symbol.size),
+                comment: project.comments.get(address).cloned().or_else(|| existing_symbol.and_then(|symbol| symbol.comment.clone())),
+            });
+        }
+        for (address, comment) in &project.comments {
+            match existing_symbols.get_exact(*address) {
+                // A symbol's own comment would hide one stored separately.
+                Some(symbol) if !project.labels.contains_key(address) => symbols.insert(*address, Symbol {
+                    name: symbol.name.clone(),
+                    size: symbol.size,
+                    comment: Some(comment.clone()),
+                }),
+                _ => symbols.insert_comment(*address, comment.clone()),
+            }
+        }
 
-        self.disassemble_from_entry_point(reset_vector, "RESET");
-        self.disassemble_from_entry_point(nmi_vector, "NMI");
+        self.labeller.add_symbols(symbols);
 
-        //todo smb1 disables irq from the start, so let's not disassemble from irq for now
-        //self.disassemble_from_entry_point(irq_vector, "IRQ");
+        for (address, annotation) in &project.annotations {
+            self.annotations.insert(*address, annotation.clone());
+        }
+        for (first_byte, characters) in &project.character_map {
+            self.annotations.insert_characters(*first_byte as u8, characters);
+        }
+        self.project_entry_points.extend(project.entry_points.clone());
+        self.forced_code.extend(project.code_regions.iter().map(|(start_address, end_address)| (*start_address, end_address + 1)));
+        self.forced_data.extend(project.data_regions.iter().map(|(start_address, end_address)| (*start_address, end_address + 1)));
+        if !project.banks.is_empty() {
+            self.map_prg_banks(&project.banks);
+        }
     }
 
     // -----------------------------------------------------------------------
 
-    fn disassemble_from_entry_point(&mut self, entry_point: usize, entry_point_label: &str) {
-        self.global_labels.insert(entry_point, entry_point_label.to_string());
+    /// Narrows the PRG ROM down to the two 16 KiB banks the project places at $8000 and $C000, so
+    /// a bank-switched cartridge can be disassembled one configuration at a time. Like most
+    /// mappers, $8000 defaults to the first bank and $C000 to the last.
+    fn map_prg_banks(&mut self, banks: &BTreeMap<usize, usize>) {
+        if !self.is_cartridge() {
+            panic!("[ERROR] Only cartridges have PRG banks to map!");
+        }
+        if self.code_data_log.is_some() {
+            panic!("[ERROR] Code/data logs can't be used with a PRG bank mapping yet!");
+        }
 
-        let mut entry_points: Vec<usize> = Vec::new();
-        entry_points.push(entry_point);
+        let last_bank = self.prg_rom_bank_count - 1;
+        let mapping = [banks.get(&0x8000).copied().unwrap_or(0), banks.get(&0xC000).copied().unwrap_or(last_bank)];
+        if let Some(bank) = mapping.iter().find(|bank| **bank > last_bank) {
+            panic!("[ERROR] The project maps PRG bank {bank}, but there are only {} banks!", self.prg_rom_bank_count);
+        }
 
-        while !entry_points.is_empty() {
-            let mut current_address = match entry_points.pop() {
-                Some(address) => address,
-                None => panic!("[ERROR] Attempted to get a new entry point that didn't exist...this shouldn't happen!"),
-            };
+        println!("PRG banks mapped: $8000 bank {}, $C000 bank {}", mapping[0], mapping[1]);
 
-            let mut is_current_section_processing_complete = false;
-            while !is_current_section_processing_complete {
-                if self.text_lines.contains_key(&current_address) {
-                    is_current_section_processing_complete = true;
-                    continue;
-                }
+        let mut prg_rom_contents = Vec::new();
+        for bank in mapping {
+            let start_offset = (bank * PRG_ROM_BANK_BYTES).min(self.prg_rom_contents.len());
+            let end_offset = (start_offset + PRG_ROM_BANK_BYTES).min(self.prg_rom_contents.len());
+            prg_rom_contents.extend_from_slice(&self.prg_rom_contents[start_offset..end_offset]);
+        }
+        self.prg_rom_contents = prg_rom_contents;
+        self.prg_rom_bank_count = 2;
+        self.prg_bank_mapping = Some(mapping);
+    }
 
-                let result = disassemble_instruction(
-                    &self.prg_rom_contents, current_address - 0x8000, current_address, &mut self.labeller);
-                if result.is_none() {
-                    is_current_section_processing_complete = true;
-                    continue;
-                }
+    // -----------------------------------------------------------------------
 
-                let result = result.unwrap();
-                if let Some(new_entry_point) = result.address_to_process_later && !self.text_lines.contains_key(&new_entry_point) {
-                    entry_points.push(new_entry_point);
-                }
+    pub fn load_mesen_labels(&mut self, filename: &str) {
+        let mut symbols = SymbolTable::new();
 
-                self.text_lines.insert(
-                    current_address,
-                    TextLine {
-                        contents: result.text_line,
-                        bytes: result.bytes_count,
-                    }
-                );
+        for mesen_label in load_mlb_file(filename) {
+            let start_address = match mesen_label.memory_type {
+                MesenMemoryType::PrgRom => self.prg_rom_offset_to_address(mesen_label.start_offset),
+                MesenMemoryType::InternalRam => mesen_label.start_offset,
+                MesenMemoryType::WorkRam | MesenMemoryType::SaveRam => 0x6000 + mesen_label.start_offset,
+                MesenMemoryType::Register => mesen_label.start_offset,
+            };
 
-                is_current_section_processing_complete = result.is_section_complete;
-                current_address += result.bytes_count;
+            match mesen_label.label {
+                Some(label) => symbols.insert(start_address, Symbol {
+                    name: label,
+                    size: mesen_label.end_offset - mesen_label.start_offset + 1,
+                    comment: mesen_label.comment,
+                }),
+                None => if let Some(comment) = mesen_label.comment {
+                    symbols.insert_comment(start_address, comment);
+                },
             }
         }
+
+        self.labeller.add_symbols(symbols);
     }
 
     // -----------------------------------------------------------------------
 
-    pub fn print_disassembly(&self) {
-        let mut address = 0usize;
-        while address < 65536 {
-            if let Some(global_label) = self.global_labels.get(&address) {
-                println!("\n\n\n{global_label}: [{:04X}]", address);
+    pub fn load_fceux_name_list(&mut self, filename: &str) {
+        let bank = name_list_bank(filename);
+        if let Some(bank) = bank && self.is_cartridge() && bank * PRG_ROM_BANK_BYTES >= self.prg_rom_contents.len() {
+            println!("  warning: {filename} names PRG bank {bank:X}, which this cartridge doesn't have");
+            return;
+        }
+
+        self.add_fceux_names(load_nl_file(filename), bank);
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Adds names from a name list, moving the ones in a 16 KiB PRG `bank` file to where that bank's
+    /// bytes are disassembled, as the Mesen import does with PRG ROM offsets. Disk images and music
+    /// rips only use their CPU addresses.
+    fn add_fceux_names(&mut self, fceux_names: Vec<FceuxName>, bank: Option<usize>) {
+        let mut symbols = SymbolTable::new();
+
+        for fceux_name in fceux_names {
+            let address = match bank {
+                Some(bank) if self.is_cartridge() && fceux_name.address >= 0x8000 => {
+                    self.prg_rom_offset_to_address(bank * PRG_ROM_BANK_BYTES + fceux_name.address % PRG_ROM_BANK_BYTES)
+                },
+                _ => fceux_name.address,
+            };
+
+            match fceux_name.name {
+                Some(name) => symbols.insert(address, Symbol {
+                    name,
+                    size: fceux_name.size,
+                    comment: fceux_name.comment,
+                }),
+                None => if let Some(comment) = fceux_name.comment {
+                    symbols.insert_comment(address, comment);
+                },
             }
+        }
+
+        self.labeller.add_symbols(symbols);
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_debug_info(&mut self, filename: &str) {
+        let debug_info = DebugInfo::load_from_file(filename);
+
+        let mut symbols = SymbolTable::new();
+        for debug_symbol in &debug_info.symbols {
+            symbols.insert(debug_symbol.address, Symbol {
+                name: debug_symbol.name.clone(),
+                size: debug_symbol.size.max(1),
+                comment: None,
+            });
+        }
+
+        self.labeller.add_symbols(symbols);
+        self.debug_info = Some(debug_info);
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_annotations(&mut self, filename: &str) {
+        self.annotations.extend(Annotations::load_from_file(filename));
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_code_data_log(&mut self, filename: &str) {
+        if !self.is_cartridge() {
+            panic!("[ERROR] Code/data logs aren't supported for disk images or music rips yet!");
+        }
+        self.code_data_log = Some(CodeDataLog::load_from_file(filename, self.prg_rom_contents.len()));
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Saves the code/data log, including anything learned from emulation, in the FCEUX format.
+    pub fn save_code_data_log(&self, filename: &str) {
+        match &self.code_data_log {
+            Some(code_data_log) => code_data_log.save_to_file(filename, self.chr_rom_contents.len()),
+            None => panic!("[ERROR] There's no code/data log to save; load one or enable emulation first!"),
+        }
+        println!("Wrote {filename}");
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn enable_emulation(&mut self, emulation_options: EmulationOptions) {
+        if !self.is_cartridge() {
+            panic!("[ERROR] Emulation isn't supported for disk images or music rips yet!");
+        }
+        self.emulation_options = Some(emulation_options);
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Prints the PRG and CHR checksums and looks them up in `database`, warning about anything
+    /// the file's header disagrees with. If `should_use_database_header` is set, the database's
+    /// mapper is used for disassembly instead of the header's.
+    pub fn identify(&mut self, database: &HeaderDatabase, should_use_database_header: bool) {
+        println!("------------------------------------------------------------------------------");
+        println!("Identification:");
+        println!("  PRG ROM CRC32: {:08X}, SHA-1: {}", crc32(&self.prg_rom_contents), format_sha1(&sha1(&self.prg_rom_contents)));
+        if !self.chr_rom_contents.is_empty() {
+            println!("  CHR ROM CRC32: {:08X}, SHA-1: {}", crc32(&self.chr_rom_contents), format_sha1(&sha1(&self.chr_rom_contents)));
+        }
+
+        let Some(entry) = database.find(&self.prg_rom_contents, &self.chr_rom_contents) else {
+            println!("  Not found in header database ({} entries)", database.len());
+            return;
+        };
+        println!("  Title: {}", entry.title);
+        println!("  Database mapper: {}, submapper: {}, mirroring: {}", entry.header.mapper_id, entry.header.submapper_id, entry.header.mirroring.name());
+
+        let Some(header) = &self.header else {
+            return;
+        };
+        let database_header = &entry.header;
+        if header.mapper_id != database_header.mapper_id {
+            println!("  warning: header says mapper {}, database says {}", header.mapper_id, database_header.mapper_id);
+        }
+        if header.is_nes2 && header.submapper_id != database_header.submapper_id {
+            println!("  warning: header says submapper {}, database says {}", header.submapper_id, database_header.submapper_id);
+        }
+        if header.mirroring != database_header.mirroring {
+            println!("  warning: header says {} mirroring, database says {}", header.mirroring.name(), database_header.mirroring.name());
+        }
+        if header.has_battery != database_header.has_battery {
+            let battery_name = |has_battery| if has_battery { "a battery" } else { "no battery" };
+            println!("  warning: header says {}, database says {}", battery_name(header.has_battery), battery_name(database_header.has_battery));
+        }
+        if header.prg_rom_bytes != database_header.prg_rom_bytes || header.chr_rom_bytes != database_header.chr_rom_bytes {
+            println!(
+                "  warning: header says {} bytes PRG ROM and {} bytes CHR ROM, database says {} and {}",
+                header.prg_rom_bytes, header.chr_rom_bytes, database_header.prg_rom_bytes, database_header.chr_rom_bytes,
+            );
+        }
+
+        if should_use_database_header {
+            println!("  Using the database header for disassembly");
+            self.mapper_id = database_header.mapper_id;
+            self.header = Some(NesHeader {
+                has_trainer: header.has_trainer,
+                ..database_header.clone()
+            });
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn enable_cycle_counts(&mut self) {
+        self.should_show_cycle_counts = true;
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn enable_linear_sweep(&mut self) {
+        self.should_sweep_for_code = true;
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Writes a Graphviz file with the control flow graph of every routine, plus `call_graph.dot`
+    /// with the calls between them, into `directory`.
+    pub fn export_control_flow_graphs(&self, directory: &str) {
+        if let Err(error) = fs::create_dir_all(directory) {
+            panic!("[ERROR] Could not create directory {directory}: {error}");
+        }
+
+        let basic_blocks = self.control_flow_graph.basic_blocks();
+        let routine_entry_points = self.routine_entry_points();
+        let name_of = |address: usize| self.preferred_label_at(address).map_or_else(|| format!("${address:04X}"), String::from);
+        let instruction_text_of = |address: usize| self.text_lines.get(&address).map_or_else(String::new, |text_line| text_line.instruction_text.clone());
+
+        for entry_point in &routine_entry_points {
+            let routine_name = name_of(*entry_point);
+            let routine_blocks = self.control_flow_graph.routine_blocks(&basic_blocks, *entry_point, &routine_entry_points);
+            let dot = format_routine_dot(&routine_name, &basic_blocks, &routine_blocks, &name_of, &instruction_text_of);
+            write_text_file(&format!("{directory}/{}.dot", sanitize_filename(&routine_name)), &dot);
+        }
+
+        let call_graph = CallGraph::new(&self.control_flow_graph, &basic_blocks, &routine_entry_points);
+        let dot = format_call_graph_dot(&call_graph, &name_of);
+        write_text_file(&format!("{directory}/call_graph.dot"), &dot);
+    }
 
-            if let Some(branch_label) = self.labeller.get_branch_target_label(address) {
-                println!("{branch_label}: [{:04X}]", address);
+    // -----------------------------------------------------------------------
+
+    /// Prints the callers and callees of every routine, how deep the calls nest from each
+    /// vector, and which routines are recursive, leaves, or never reached from a vector.
+    pub fn print_call_graph_report(&self) {
+        let routine_entry_points = self.routine_entry_points();
+        let call_graph = CallGraph::new(&self.control_flow_graph, &self.control_flow_graph.basic_blocks(), &routine_entry_points);
+        let name_of = |address: usize| match self.preferred_label_at(address) {
+            Some(label) => format!("{label} (${address:04X})"),
+            None => format!("${address:04X}"),
+        };
+        let describe_calls = |calls: Vec<(usize, CallKind)>| {
+            if calls.is_empty() {
+                return String::from("none");
             }
+            calls.into_iter()
+                .map(|(address, kind)| match kind {
+                    CallKind::Call => name_of(address),
+                    CallKind::TailCall => format!("{} [tail call]", name_of(address)),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
 
-            if let Some(jump_label) = self.labeller.get_jump_target_label(address) {
-                println!("{jump_label}: [{:04X}]", address);
+        println!("------------------------------------------------------------------------------");
+        println!("Call graph:");
+
+        let mut roots = Vec::new();
+        println!("  maximum call depth:");
+        for (vector_name, address) in &self.vectors {
+            if !routine_entry_points.contains(address) {
+                println!("    {vector_name} (${address:04X}): not traced");
+                continue;
             }
 
-            if let Some(subroutine_label) = self.labeller.get_subroutine_label(address) {
-                println!("\n\n\n{subroutine_label}: [{:04X}]", address);
+            roots.push(*address);
+            match call_graph.max_call_depth(*address) {
+                Some(depth) => println!("    {vector_name} (${address:04X}): {depth}"),
+                None => println!("    {vector_name} (${address:04X}): unbounded (reaches recursion)"),
             }
+        }
 
-            if let Some(text_line) = self.text_lines.get(&address) {
-                println!("{}", text_line.contents);
-                address += text_line.bytes;
-            } else {
-                address += 1;
+        println!("  routines:");
+        for routine in call_graph.routines() {
+            println!("    {}", name_of(*routine));
+            println!("      callers: {}", describe_calls(call_graph.callers(*routine)));
+            println!("      callees: {}", describe_calls(call_graph.callees(*routine)));
+        }
+
+        let leaf_routines: Vec<String> = call_graph.routines().iter()
+            .filter(|routine| call_graph.is_leaf(**routine))
+            .map(|routine| name_of(*routine))
+            .collect();
+        println!("  leaf routines: {}", leaf_routines.len());
+        for leaf_routine in leaf_routines {
+            println!("    {leaf_routine}");
+        }
+
+        let recursive_groups = call_graph.recursive_groups();
+        println!("  recursive routines: {}", recursive_groups.len());
+        for recursive_group in recursive_groups {
+            let names: Vec<String> = recursive_group.into_iter().map(name_of).collect();
+            println!("    {}", names.join(" <-> "));
+        }
+
+        let reachable_routines = call_graph.reachable_from(&roots);
+        let unreachable_routines: Vec<String> = call_graph.routines().difference(&reachable_routines)
+            .map(|routine| name_of(*routine))
+            .collect();
+        println!("  unreachable from any vector: {}", unreachable_routines.len());
+        for unreachable_routine in unreachable_routines {
+            println!("    {unreachable_routine}");
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Prints the worst-case stack usage from each vector, assuming the interrupts can nest on
+    /// top of the main code, and flags routines whose pushes and pulls don't balance.
+    pub fn print_stack_usage_report(&self) {
+        let routine_entry_points = self.routine_entry_points();
+        let opcode_at = |address: usize| self.instruction_bytes_at(address).0;
+        let stack_usages = analyze_stack_usage(
+            &self.control_flow_graph, &self.control_flow_graph.basic_blocks(), &routine_entry_points, &opcode_at);
+        let name_of = |address: usize| match self.preferred_label_at(address) {
+            Some(label) => format!("{label} (${address:04X})"),
+            None => format!("${address:04X}"),
+        };
+
+        println!("------------------------------------------------------------------------------");
+        println!("Stack usage:");
+
+        println!("  worst case from each vector:");
+        let mut total_bytes = Some(0);
+        for (vector_name, address) in &self.vectors {
+            let Some(stack_usage) = stack_usages.get(address) else {
+                println!("    {vector_name} (${address:04X}): not traced");
+                continue;
+            };
+
+            let interrupt_bytes = if *vector_name == "RESET" { 0 } else { INTERRUPT_BYTES };
+            let vector_bytes = stack_usage.max_bytes.map(|max_bytes| max_bytes + interrupt_bytes);
+            match vector_bytes {
+                Some(bytes) if interrupt_bytes > 0 => {
+                    println!("    {vector_name} (${address:04X}): {bytes} bytes (including {interrupt_bytes} for the interrupt)");
+                },
+                Some(bytes) => println!("    {vector_name} (${address:04X}): {bytes} bytes"),
+                None => println!("    {vector_name} (${address:04X}): unbounded (reaches recursion)"),
             }
+
+            total_bytes = total_bytes.zip(vector_bytes).map(|(total_bytes, vector_bytes)| total_bytes + vector_bytes);
+        }
+        match total_bytes {
+            Some(total_bytes) => println!("  worst case with interrupts nesting: {total_bytes} of 256 bytes"),
+            None => println!("  worst case with interrupts nesting: unbounded"),
+        }
+
+        // Subroutines known to take their own return address pull more than they push on purpose.
+        let imbalances: Vec<(usize, usize, &String)> = stack_usages.iter()
+            .filter(|(routine, _)| self.annotations.get_subroutine(**routine).is_none() && !self.return_address_subroutines.contains(*routine))
+            .flat_map(|(routine, stack_usage)| stack_usage.imbalances.iter()
+                .map(|(address, description)| (*routine, *address, description)))
+            .collect();
+        println!("  unbalanced pushes and pulls: {}", imbalances.len());
+        for (routine, address, description) in imbalances {
+            println!("    {} at ${:04X}: {description}", name_of(routine), address);
         }
     }
-}
 
-// ---------------------------------------------------------------------------
+    // -----------------------------------------------------------------------
 
-struct TextLine {
-    contents: String,
-    bytes: usize,
+    /// Returns the addresses that start a routine: the vectors, JSR targets and entry points
+    /// found outside of the static trace.
+    fn routine_entry_points(&self) -> BTreeSet<usize> {
+        let mut routine_entry_points: BTreeSet<usize> = self.global_labels.keys().copied().collect();
+        routine_entry_points.extend(self.labeller.subroutine_addresses());
+        routine_entry_points.extend(self.labeller.entry_point_addresses());
+        routine_entry_points.extend(self.labeller.probable_code_addresses());
+        routine_entry_points.retain(|address| self.text_lines.contains_key(address));
+
+        routine_entry_points
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Writes `<rom>.<bank>.nl` for each 16 KiB PRG bank and `<rom>.ram.nl` for RAM, so FCEUX's
+    /// debugger shows the disassembly's names.
+    pub fn export_fceux_name_lists(&self, cartridge_filename: &str) {
+        let (ram_names, bank_names) = self.fceux_names();
+
+        write_name_list(&format!("{cartridge_filename}.ram.nl"), &ram_names);
+        for (bank, names) in bank_names.iter().enumerate() {
+            write_name_list(&format!("{cartridge_filename}.{bank:X}.nl"), names);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the names below $8000 and the names in each 16 KiB PRG bank. A 16 KiB PRG ROM
+    /// mirrored at $C000 is all in bank 0.
+    fn fceux_names(&self) -> (Vec<FceuxName>, Vec<Vec<FceuxName>>) {
+        let symbols = self.labeller.symbols();
+
+        let mut addresses: BTreeSet<usize> = self.labeller.labelled_addresses();
+        addresses.extend(self.global_labels.keys());
+        addresses.extend(symbols.symbols().map(|(address, _)| address));
+        addresses.extend(symbols.comment_addresses());
+
+        let mut ram_names = Vec::new();
+        let mut bank_names: Vec<Vec<FceuxName>> = (0..self.prg_rom_bank_count).map(|_| Vec::new()).collect();
+
+        for address in addresses {
+            let fceux_name = FceuxName {
+                address,
+                size: symbols.get_exact(address).map_or(1, |symbol| symbol.size),
+                name: self.preferred_label_at(address).map(String::from),
+                comment: symbols.get_comment(address).cloned(),
+            };
+
+            // Disk images and music rips aren't split into 16 KiB banks.
+            if address < 0x8000 || !self.is_cartridge() {
+                ram_names.push(fceux_name);
+            } else if let Some(names) = self.address_to_prg_rom_offset(address).and_then(|offset| bank_names.get_mut(offset / PRG_ROM_BANK_BYTES)) {
+                names.push(fceux_name);
+            }
+        }
+
+        (ram_names, bank_names)
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Picks the single name used for `address` when only one is allowed, preferring the user's
+    /// names over the vector names over the generated ones.
+    fn preferred_label_at(&self, address: usize) -> Option<&str> {
+        self.labeller.symbols().get_exact(address).map(|symbol| symbol.name.as_str())
+            .or_else(|| self.global_labels.get(&address).map(String::as_str))
+            .or_else(|| self.labeller.get_subroutine_label(address).map(String::as_str))
+            .or_else(|| self.labeller.get_entry_point_label(address).map(String::as_str))
+            .or_else(|| self.labeller.get_probable_code_label(address).map(String::as_str))
+            .or_else(|| self.labeller.get_jump_target_label(address).map(String::as_str))
+            .or_else(|| self.labeller.get_branch_target_label(address).map(String::as_str))
+            .or_else(|| self.labeller.get_data_label(address).map(String::as_str))
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the address of a PRG ROM byte. A cartridge's PRG ROM smaller than 32 KiB is placed
+    /// against the end of memory, where the vectors that lead into it are.
+    fn prg_rom_offset_to_address(&self, offset: usize) -> usize {
+        if self.is_mirrored_cartridge() {
+            0x10000 - self.prg_rom_contents.len() + offset
+        } else {
+            self.prg_base_address + offset
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns whether this was loaded from a cartridge, rather than a disk image or music rip.
+    fn is_cartridge(&self) -> bool {
+        self.mapper_id != FDS_MAPPER_ID && self.music_entry_points.is_none()
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns whether `address` is mapped to PRG, including the mirrors of a 16 KiB PRG ROM but not
+    /// the disk BIOS.
+    fn is_prg_address(&self, address: usize) -> bool {
+        let end_address = if self.mapper_id == FDS_MAPPER_ID { FDS_RAM_END } else { 0x10000 };
+        (self.prg_base_address..end_address).contains(&address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn address_to_prg_rom_offset(&self, address: usize) -> Option<usize> {
+        let offset = address.checked_sub(self.prg_base_address)?;
+        if self.is_mirrored_cartridge() && address < 0x10000 {
+            return Some(offset % self.prg_rom_contents.len());
+        }
+        if offset < self.prg_rom_contents.len() { Some(offset) } else { None }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns whether the cartridge's PRG ROM is too small to fill $8000-$FFFF, so it repeats,
+    /// as a 16 KiB NROM-128 ROM does at $C000.
+    fn is_mirrored_cartridge(&self) -> bool {
+        self.is_cartridge() && !self.prg_rom_contents.is_empty() && self.prg_rom_contents.len() < 0x10000 - self.prg_base_address
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn disassemble(&mut self) {
+        println!("------------------------------------------------------------------------------");
+
+        // Tracing has to know where the declared data is before it starts, so it stops there.
+        self.resolve_data_tables();
+
+        match self.mapper_id {
+            _ if self.music_entry_points.is_some() => self.disassemble_from_music_entry_points(),
+            0 => self.disassemble_from_cartridge_vectors(),
+            FDS_MAPPER_ID => self.disassemble_from_disk_vectors(),
+            _ if self.prg_bank_mapping.is_some() => self.disassemble_from_cartridge_vectors(),
+            mapper_id => panic!("[ERROR] Mapper is {mapper_id}, but only mapper 0 is supported right now, unless a project maps its PRG banks!"),
+        }
+
+        self.disassemble_from_project();
+        self.disassemble_from_data_tables();
+
+        if self.emulation_options.is_some() {
+            self.emulate_for_code_data_log();
+        }
+
+        if self.code_data_log.is_some() {
+            self.disassemble_from_code_data_log();
+        }
+
+        if self.should_sweep_for_code {
+            self.disassemble_from_linear_sweep();
+        }
+
+        let overlapping_instructions = self.overlapping_instructions();
+        if !overlapping_instructions.is_empty() {
+            println!("Overlapping instructions:");
+            for (address, inner_address) in overlapping_instructions {
+                println!(
+                    "    ${:04X} {} overlaps ${:04X} {}",
+                    address, self.text_lines[&address].instruction_text, inner_address, self.text_lines[&inner_address].instruction_text);
+            }
+            println!("------------------------------------------------------------------------------");
+        }
+
+        if !self.return_address_subroutines.is_empty() {
+            println!("Subroutines taking their own return address, not traced past their calls:");
+            for address in &self.return_address_subroutines {
+                println!("    ${:04X} {}", address, self.preferred_label_at(*address).unwrap_or_default());
+            }
+            println!("  annotate them as noreturn, inline-data or pointer-table to silence this");
+            println!("------------------------------------------------------------------------------");
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn disassemble_from_cartridge_vectors(&mut self) {
+        let nmi_vector = self.word_at(CARTRIDGE_VECTORS_ADDRESS).unwrap_or(0);
+        let reset_vector = self.word_at(CARTRIDGE_VECTORS_ADDRESS + 2).unwrap_or(0);
+        let irq_vector = self.word_at(CARTRIDGE_VECTORS_ADDRESS + 4).unwrap_or(0);
+
+        println!("  nmi vector: ${:04X}", nmi_vector);
+        println!("reset vector: ${:04X}", reset_vector);
+        println!("  irq vector: ${:04X}", irq_vector);
+        println!("------------------------------------------------------------------------------");
+
+        self.vectors = vec![("RESET", reset_vector), ("NMI", nmi_vector), ("IRQ", irq_vector)];
+
+        self.disassemble_from_entry_point(reset_vector, "RESET");
+        self.disassemble_from_entry_point(nmi_vector, "NMI");
+
+        //todo smb1 disables irq from the start, so let's not disassemble from irq for now
+        //self.disassemble_from_entry_point(irq_vector, "IRQ");
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn disassemble_from_music_entry_points(&mut self) {
+        let Some((init_address, play_address)) = self.music_entry_points else {
+            return;
+        };
+
+        println!("init address: ${:04X}", init_address);
+        println!("play address: ${:04X}", play_address);
+        println!("------------------------------------------------------------------------------");
+
+        self.vectors = vec![("INIT", init_address), ("PLAY", play_address)];
+
+        self.disassemble_from_entry_point(init_address, "INIT");
+        self.disassemble_from_entry_point(play_address, "PLAY");
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// The disk BIOS owns the real vectors and jumps through the ones at the end of the
+    /// $6000-$DFFF RAM instead, with three NMI vectors picked between by $0100.
+    fn disassemble_from_disk_vectors(&mut self) {
+        let vectors: Vec<(&'static str, usize)> = [("NMI1", 0xDFF6), ("NMI2", 0xDFF8), ("NMI3", 0xDFFA), ("RESET", 0xDFFC), ("IRQ", 0xDFFE)]
+            .into_iter()
+            .map(|(name, vector_address)| {
+                let (low_byte, high_byte, _) = self.instruction_bytes_at(vector_address);
+                (name, (high_byte as usize) << 8 | low_byte as usize)
+            })
+            .collect();
+
+        for (name, vector) in &vectors {
+            println!("{:>5} vector: ${:04X}", name.to_lowercase(), vector);
+        }
+        println!("------------------------------------------------------------------------------");
+
+        // RESET goes first so that it's traced before the handlers it might share code with.
+        self.vectors = vec![vectors[3], vectors[0], vectors[1], vectors[2], vectors[4]];
+
+        // Games often leave unused vectors pointing into the BIOS or at each other.
+        for (name, vector) in self.vectors.clone() {
+            if self.address_to_prg_rom_offset(vector).is_some() && !self.text_lines.contains_key(&vector) {
+                self.disassemble_from_entry_point(vector, name);
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Traces from the project's entry points, then from every byte of its code regions that
+    /// still isn't covered by an instruction.
+    fn disassemble_from_project(&mut self) {
+        if self.project_entry_points.is_empty() && self.forced_code.is_empty() && self.forced_data.is_empty() {
+            return;
+        }
+
+        println!("Project:");
+        println!("  entry points: {}", self.project_entry_points.len());
+        for (address, name) in &self.project_entry_points {
+            println!("    ${:04X} {}", address, name);
+        }
+        for (description, regions) in [("code regions", &self.forced_code), ("data regions", &self.forced_data)] {
+            println!("  {description}: {}", regions.len());
+            for (start_address, end_address) in regions {
+                println!("    ${:04X}-${:04X}", start_address, end_address - 1);
+            }
+        }
+        println!("------------------------------------------------------------------------------");
+
+        for (address, name) in self.project_entry_points.clone() {
+            if name.is_empty() {
+                self.labeller.request_label_for_entry_point(address);
+                self.trace_code_from(address);
+            } else {
+                self.disassemble_from_entry_point(address, &name);
+            }
+        }
+
+        for (start_address, end_address) in self.forced_code.clone() {
+            for address in start_address..end_address {
+                if self.address_to_prg_rom_offset(address).is_none() || self.is_traced(address) {
+                    continue;
+                }
+
+                self.labeller.request_label_for_entry_point(address);
+                self.trace_code_from(address);
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Lays out the data described by annotations, reading any pointers in it and labelling what
+    /// they point at. Split pointer tables become two tables, one for each half of the pointers.
+    fn resolve_data_tables(&mut self) {
+        let data_annotations = self.annotations.data().clone();
+
+        for (address, data_annotation) in data_annotations {
+            if self.address_to_prg_rom_offset(address).is_none() {
+                continue;
+            }
+
+            let byte_at = |address: usize| self.instruction_bytes_at(address).0 as usize;
+            match data_annotation {
+                DataAnnotation::Bytes { count, bytes_per_line } => {
+                    self.data_tables.insert(address, DataTable::Bytes { bytes_count: count, bytes_per_line });
+                },
+                DataAnnotation::Words(count) => {
+                    self.data_tables.insert(address, DataTable::Words(count));
+                },
+                DataAnnotation::Pointers { count, is_code } => {
+                    let pointers: Vec<usize> = (0..count)
+                        .map(|index| byte_at(address + index * 2 + 1) << 8 | byte_at(address + index * 2))
+                        .collect();
+                    self.request_labels_for_pointers(&pointers, is_code);
+                    self.data_tables.insert(address, DataTable::Pointers { pointers, is_code });
+                },
+                DataAnnotation::SplitPointers { count, high_address, is_code } => {
+                    let pointers: Vec<usize> = (0..count)
+                        .map(|index| byte_at(high_address + index) << 8 | byte_at(address + index))
+                        .collect();
+                    self.request_labels_for_pointers(&pointers, is_code);
+                    if self.address_to_prg_rom_offset(high_address).is_some() {
+                        self.data_tables.insert(high_address, DataTable::PointerBytes { pointers: pointers.clone(), is_high_byte: true, is_code });
+                    }
+                    self.data_tables.insert(address, DataTable::PointerBytes { pointers, is_high_byte: false, is_code });
+                },
+                DataAnnotation::Records { count, fields } => {
+                    self.data_tables.insert(address, DataTable::Records { count, fields });
+                },
+                DataAnnotation::Text(length) => {
+                    self.data_tables.insert(address, DataTable::Text(length));
+                },
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn request_labels_for_pointers(&mut self, pointers: &[usize], is_code: bool) {
+        for pointer in pointers {
+            if self.address_to_prg_rom_offset(*pointer).is_none() {
+                continue;
+            }
+
+            if is_code {
+                self.labeller.request_label_for_jump_target(*pointer);
+            } else {
+                self.labeller.request_label_for_data(*pointer);
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Lists the data tables, then traces from the pointers in the ones that point at code.
+    fn disassemble_from_data_tables(&mut self) {
+        if self.data_tables.is_empty() {
+            return;
+        }
+
+        println!("Data tables:");
+        for (address, data_table) in &self.data_tables {
+            println!("    ${:04X}-${:04X} {}", address, address + data_table.bytes_count() - 1, data_table.describe());
+        }
+        println!("------------------------------------------------------------------------------");
+
+        let code_pointers: Vec<usize> = self.data_tables.values()
+            .flat_map(|data_table| match data_table {
+                DataTable::Pointers { pointers, is_code: true } | DataTable::PointerBytes { pointers, is_code: true, .. } => pointers.clone(),
+                _ => Vec::new(),
+            })
+            .collect();
+        for pointer in code_pointers {
+            if self.address_to_prg_rom_offset(pointer).is_some() {
+                self.trace_code_from(pointer);
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Runs the ROM on the built-in CPU emulator and folds what it executed and read into the
+    /// code/data log, so it gets traced the same way as a logged one.
+    fn emulate_for_code_data_log(&mut self) {
+        let Some(emulation_options) = &self.emulation_options else {
+            return;
+        };
+
+        let result = emulate(&self.prg_rom_contents, emulation_options);
+
+        println!("Emulation:");
+        println!("  frames completed: {} of {}", result.frames_completed, emulation_options.frame_count);
+        println!("  stopped because of: {}", result.stop_reason.describe());
+        println!("  instructions executed: {}", result.instructions_executed);
+        println!("  unique instruction addresses: {}", result.executed_addresses.len());
+        println!("  indirect jump targets: {}", result.indirect_jump_targets.len());
+        for indirect_jump_target in &result.indirect_jump_targets {
+            println!("    ${:04X}", indirect_jump_target);
+        }
+        println!("------------------------------------------------------------------------------");
+
+        match &mut self.code_data_log {
+            Some(code_data_log) => code_data_log.merge(&result.code_data_log),
+            None => self.code_data_log = Some(result.code_data_log),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Reports where the static trace and the code/data log disagree, then traces from every
+    /// logged opcode that the static trace didn't reach.
+    fn disassemble_from_code_data_log(&mut self) {
+        let Some(code_data_log) = &self.code_data_log else {
+            return;
+        };
+
+        let traced_offsets = self.traced_prg_rom_offsets();
+        let mut missed_code_offsets = Vec::new();
+        let mut traced_data_offsets = Vec::new();
+        for (offset, is_traced) in traced_offsets.into_iter().enumerate() {
+            if code_data_log.is_code(offset) && !is_traced {
+                missed_code_offsets.push(offset);
+            } else if is_traced && code_data_log.is_data(offset) && !code_data_log.is_code(offset) {
+                traced_data_offsets.push(offset);
+            }
+        }
+
+        println!("Code/data log:");
+        println!("  logged code missed by static tracing: {} bytes", missed_code_offsets.len());
+        for (start_offset, end_offset) in coalesce_offsets(&missed_code_offsets) {
+            println!("    ${:04X}-${:04X}", self.prg_rom_offset_to_address(start_offset), self.prg_rom_offset_to_address(end_offset));
+        }
+        println!("  logged data decoded as code by static tracing: {} bytes", traced_data_offsets.len());
+        for (start_offset, end_offset) in coalesce_offsets(&traced_data_offsets) {
+            println!("    ${:04X}-${:04X}", self.prg_rom_offset_to_address(start_offset), self.prg_rom_offset_to_address(end_offset));
+        }
+        println!("------------------------------------------------------------------------------");
+
+        // Any logged code byte that still isn't covered by a traced instruction has to be the
+        // start of one, since tracing from an opcode covers its operands too.
+        for offset in missed_code_offsets {
+            let address = self.prg_rom_offset_to_address(offset);
+            if self.is_traced(address) {
+                continue;
+            }
+
+            self.labeller.request_label_for_entry_point(address);
+            self.trace_code_from(address);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Traces the runs of probable code found by sweeping over the PRG ROM bytes that nothing
+    /// else explained, and keeps whatever is still unexplained to be printed as data.
+    fn disassemble_from_linear_sweep(&mut self) {
+        let explored_offsets = self.explored_prg_rom_offsets();
+        let is_known_code = |address: usize| self.text_lines.contains_key(&address);
+        let address_to_offset = |address: usize| self.address_to_prg_rom_offset(address);
+        let probable_code = find_probable_code(&self.prg_rom_contents, &explored_offsets, self.prg_rom_offset_to_address(0), &address_to_offset, &is_known_code);
+
+        println!("Linear sweep:");
+        println!("  probable code: {} runs", probable_code.len());
+        for run in &probable_code {
+            let address = self.prg_rom_offset_to_address(run.offset);
+            println!("    ${:04X}-${:04X} (score {})", address, address + run.bytes - 1, run.score);
+        }
+
+        for run in probable_code {
+            let address = self.prg_rom_offset_to_address(run.offset);
+            if self.text_lines.contains_key(&address) {
+                continue;
+            }
+
+            self.labeller.request_label_for_probable_code(address);
+            self.trace_code_from(address);
+        }
+
+        let unexplored_offsets: Vec<usize> = self.explored_prg_rom_offsets().into_iter().enumerate()
+            .filter(|(_, is_explored)| !is_explored)
+            .map(|(offset, _)| offset)
+            .collect();
+        for (start_offset, end_offset) in coalesce_offsets(&unexplored_offsets) {
+            self.unexplored_data.insert(self.prg_rom_offset_to_address(start_offset), self.prg_rom_offset_to_address(end_offset) + 1);
+        }
+
+        println!("  left as data: {} bytes", unexplored_offsets.len());
+        println!("------------------------------------------------------------------------------");
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns which PRG ROM bytes are accounted for, whether as traced code, data following a
+    /// JSR, data declared by the project or annotations, or logged data.
+    fn explored_prg_rom_offsets(&self) -> Vec<bool> {
+        let mut explored_offsets = self.traced_prg_rom_offsets();
+
+        let data_tables = self.data_tables.iter().map(|(address, data_table)| (*address, address + data_table.bytes_count()));
+        for (start_address, end_address) in self.forced_data.iter().map(|(start_address, end_address)| (*start_address, *end_address)).chain(data_tables) {
+            for byte_address in start_address..end_address {
+                if let Some(offset) = self.address_to_prg_rom_offset(byte_address) {
+                    explored_offsets[offset] = true;
+                }
+            }
+        }
+
+        for (address, inline_data) in &self.inline_data {
+            for byte_address in *address..*address + inline_data.bytes_count() {
+                if let Some(offset) = self.address_to_prg_rom_offset(byte_address) {
+                    explored_offsets[offset] = true;
+                }
+            }
+        }
+
+        if let Some(code_data_log) = &self.code_data_log {
+            for (offset, is_explored) in explored_offsets.iter_mut().enumerate() {
+                *is_explored |= code_data_log.is_data(offset);
+            }
+        }
+
+        explored_offsets
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns whether `address` is inside a traced instruction, looking only at the instructions
+    /// that could reach it, so it stays cheap while tracing is still adding to them.
+    fn is_traced(&self, address: usize) -> bool {
+        (address.saturating_sub(2)..=address)
+            .any(|start_address| self.text_lines.get(&start_address).is_some_and(|text_line| address < start_address + text_line.bytes))
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn traced_prg_rom_offsets(&self) -> Vec<bool> {
+        let mut traced_offsets = vec![false; self.prg_rom_contents.len()];
+
+        for (address, text_line) in &self.text_lines {
+            for byte_address in *address..*address + text_line.bytes {
+                if let Some(offset) = self.address_to_prg_rom_offset(byte_address) {
+                    traced_offsets[offset] = true;
+                }
+            }
+        }
+
+        traced_offsets
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn disassemble_from_entry_point(&mut self, entry_point: usize, entry_point_label: &str) {
+        self.global_labels.insert(entry_point, entry_point_label.to_string());
+        self.trace_code_from(entry_point);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn trace_code_from(&mut self, entry_point: usize) {
+        self.control_flow_graph.add_entry_point(entry_point);
+
+        let mut entry_points: Vec<usize> = Vec::new();
+        entry_points.push(entry_point);
+
+        while !entry_points.is_empty() {
+            let mut current_address = match entry_points.pop() {
+                Some(address) => address,
+                None => panic!("[ERROR] Attempted to get a new entry point that didn't exist...this shouldn't happen!"),
+            };
+
+            let mut is_current_section_processing_complete = false;
+            while !is_current_section_processing_complete {
+                if self.text_lines.contains_key(&current_address) || self.is_declared_data(current_address) {
+                    is_current_section_processing_complete = true;
+                    continue;
+                }
+
+                if self.address_to_prg_rom_offset(current_address).is_none() {
+                    is_current_section_processing_complete = true;
+                    continue;
+                }
+
+                // The instruction can run past the end of PRG, or wrap around in a mirrored one.
+                let (opcode, operand1, operand2) = self.instruction_bytes_at(current_address);
+                let result = disassemble_instruction(&[opcode, operand1, operand2], 0, current_address, &mut self.labeller);
+                if result.is_none() {
+                    is_current_section_processing_complete = true;
+                    continue;
+                }
+
+                let result = result.unwrap();
+                if let Some(new_entry_point) = result.address_to_process_later && !self.text_lines.contains_key(&new_entry_point) {
+                    entry_points.push(new_entry_point);
+                }
+
+                let mut next_address = current_address + result.bytes_count;
+                let mut is_section_complete = result.is_section_complete;
+                if let Some(subroutine_address) = result.address_to_process_later && opcode == 0x20 {
+                    match self.subroutine_annotation(subroutine_address) {
+                        Some(SubroutineAnnotation::NoReturn) => is_section_complete = true,
+                        Some(SubroutineAnnotation::InlineData(byte_count)) => {
+                            self.inline_data.insert(next_address, InlineData::Bytes(byte_count));
+                            next_address += byte_count;
+                        },
+                        Some(SubroutineAnnotation::PointerTable(pointer_count)) => {
+                            let pointers = self.read_pointer_table(next_address, pointer_count);
+                            for pointer in &pointers {
+                                self.labeller.request_label_for_jump_target(*pointer);
+                                self.control_flow_graph.add_edge(current_address, *pointer, EdgeKind::Jump);
+                                entry_points.push(*pointer);
+                            }
+                            self.inline_data.insert(next_address, InlineData::Pointers(pointers));
+                            is_section_complete = true;
+                        },
+                        None => {},
+                    }
+                }
+
+                self.record_control_flow(current_address, &result, if is_section_complete { None } else { Some(next_address) });
+                self.text_lines.insert(
+                    current_address,
+                    TextLine {
+                        instruction_text: result.instruction_text,
+                        contents: result.text_line,
+                        bytes: result.bytes_count,
+                    }
+                );
+
+                is_current_section_processing_complete = is_section_complete;
+                current_address = next_address;
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns whether the project or an annotation says `address` holds data, so it's never
+    /// traced as code.
+    fn is_declared_data(&self, address: usize) -> bool {
+        self.forced_data.range(..=address).next_back().is_some_and(|(_, end_address)| address < *end_address)
+            || self.data_tables.range(..=address).next_back().is_some_and(|(start_address, data_table)| address < start_address + data_table.bytes_count())
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns how calls to the subroutine at `address` come back, if not simply to the next
+    /// instruction. Unannotated subroutines that take their own return address are treated as
+    /// never returning, since where they continue can't be worked out.
+    fn subroutine_annotation(&mut self, address: usize) -> Option<SubroutineAnnotation> {
+        if let Some(annotation) = self.annotations.get_subroutine(address) {
+            return Some(annotation);
+        }
+
+        let offset = self.address_to_prg_rom_offset(address)?;
+        if manipulates_return_address(&self.prg_rom_contents, offset) {
+            self.return_address_subroutines.insert(address);
+            return Some(SubroutineAnnotation::NoReturn);
+        }
+
+        None
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Reads the code pointers stored at `address`. Without a count, the table ends at the first
+    /// pointer outside PRG ROM, at code or a label, or where the earliest pointer leads.
+    fn read_pointer_table(&self, address: usize, pointer_count: Option<usize>) -> Vec<usize> {
+        const MAX_POINTERS: usize = 256;
+
+        let mut pointers = Vec::new();
+        let mut table_end_address = usize::MAX;
+
+        while pointers.len() < pointer_count.unwrap_or(MAX_POINTERS) {
+            let pointer_address = address + pointers.len() * 2;
+            if pointer_count.is_none() {
+                let is_table_over = pointer_address + 1 >= table_end_address
+                    || (!pointers.is_empty() && (self.text_lines.contains_key(&pointer_address) || self.has_label_at(pointer_address)));
+                if is_table_over {
+                    break;
+                }
+            }
+
+            let Some(pointer) = self.word_at(pointer_address) else {
+                break;
+            };
+            if self.address_to_prg_rom_offset(pointer).is_none() {
+                break;
+            }
+
+            if pointer > address {
+                table_end_address = table_end_address.min(pointer);
+            }
+            pointers.push(pointer);
+        }
+
+        pointers
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn record_control_flow(&mut self, address: usize, result: &DisassembledInstruction, next_address: Option<usize>) {
+        self.control_flow_graph.add_instruction(address, result.bytes_count);
+
+        if let Some(next_address) = next_address {
+            self.control_flow_graph.add_edge(address, next_address, EdgeKind::Fallthrough);
+        }
+
+        if let Some(target_address) = result.address_to_process_later {
+            let opcode = self.instruction_bytes_at(address).0;
+            let kind = match decode_opcode(opcode) {
+                Some(decoded_opcode) if decoded_opcode.addressing_mode == AddressingMode::Relative => EdgeKind::Branch,
+                Some(decoded_opcode) if decoded_opcode.mnemonic == "JSR" => EdgeKind::Call,
+                _ => EdgeKind::Jump,
+            };
+            self.control_flow_graph.add_edge(address, target_address, kind);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn print_disassembly(&self) {
+        print!("{}", self.format_disassembly(0, 0x10000));
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Writes the disassembly into `directory` as a set of files to keep under version control:
+    ///
+    ///     main.asm          includes everything else in order
+    ///     header.asm        the iNES header
+    ///     symbols.asm       the symbols outside of PRG, such as registers and RAM
+    ///     prg_bank_NN.asm   the listing of each 16 KiB PRG bank
+    ///     vectors.asm       the vectors
+    ///
+    /// Disk images and music rips aren't split into banks, so they get a single listing.
+    pub fn export_disassembly(&self, directory: &str, cartridge_filename: &str) {
+        if let Err(error) = fs::create_dir_all(directory) {
+            panic!("[ERROR] Could not create directory {directory}: {error}");
+        }
+
+        let mut main_file = format!("; {cartridge_filename}\n");
+        main_file.push_str(".include \"header.asm\"\n");
+        main_file.push_str(".include \"symbols.asm\"\n");
+
+        write_text_file(&format!("{directory}/header.asm"), &self.format_header_file());
+        write_text_file(&format!("{directory}/symbols.asm"), &self.format_symbols_file());
+        let bank_address_ranges = self.bank_address_ranges();
+        for (bank, (start_address, end_address)) in bank_address_ranges.iter().copied().enumerate() {
+            // The vectors file has a cartridge's vectors, so the last bank stops short of them.
+            let is_last_bank = bank + 1 == bank_address_ranges.len();
+            let end_address = if is_last_bank && self.is_cartridge() { CARTRIDGE_VECTORS_ADDRESS } else { end_address };
+
+            let bank_filename = format!("prg_bank_{bank:02}.asm");
+            let mut bank_file = format!("; PRG bank {bank}, ${:04X}-${:04X}\n", start_address, end_address - 1);
+            bank_file.push_str(&self.format_disassembly(start_address, end_address));
+
+            write_text_file(&format!("{directory}/{bank_filename}"), &bank_file);
+            main_file.push_str(&format!(".include \"{bank_filename}\"\n"));
+        }
+        write_text_file(&format!("{directory}/vectors.asm"), &self.format_vectors_file());
+
+        main_file.push_str(".include \"vectors.asm\"\n");
+        write_text_file(&format!("{directory}/main.asm"), &main_file);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn format_header_file(&self) -> String {
+        let Some(header) = &self.header else {
+            return String::from("; Disk images, music rips and UNIF files don't have an iNES header.\n");
+        };
+        let header_bytes = match header.to_bytes() {
+            Ok(header_bytes) => header_bytes,
+            Err(error) => panic!("[ERROR] Could not create header: {error}"),
+        };
+
+        let mut header_file = format!("; {} header, mapper {}\n", if header.is_nes2 { "NES 2.0" } else { "iNES" }, header.mapper_id);
+        for line_bytes in header_bytes.chunks(8) {
+            let bytes: Vec<String> = line_bytes.iter().map(|byte| format!("${byte:02X}")).collect();
+            header_file.push_str(&format!("    .byte {}\n", bytes.join(", ")));
+        }
+        header_file
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Formats an equate for every symbol outside of PRG, with its comment, along with the comments
+    /// the user left on unnamed addresses there, such as RAM entries in a Mesen label file.
+    fn format_symbols_file(&self) -> String {
+        let symbols = self.labeller.symbols();
+        let addresses: BTreeSet<usize> = symbols.symbols().map(|(address, _)| address)
+            .chain(symbols.comment_addresses())
+            .filter(|address| !self.is_prg_address(*address))
+            .collect();
+
+        let mut symbols_file = String::new();
+        for address in addresses {
+            let mut comment_lines = symbols.get_comment(address).map(|comment| comment.lines()).into_iter().flatten();
+            match (symbols.get_exact(address), comment_lines.next()) {
+                (Some(symbol), Some(comment_line)) => symbols_file.push_str(&format!("{} = ${:04X} ; {comment_line}\n", symbol.name, address)),
+                (Some(symbol), None) => symbols_file.push_str(&format!("{} = ${:04X}\n", symbol.name, address)),
+                (None, Some(comment_line)) => symbols_file.push_str(&format!("; ${address:04X}: {comment_line}\n")),
+                (None, None) => {},
+            }
+            for comment_line in comment_lines {
+                symbols_file.push_str(&format!("    ; {comment_line}\n"));
+            }
+        }
+        symbols_file
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn format_vectors_file(&self) -> String {
+        let mut vectors = self.vectors.clone();
+        vectors.sort_by_key(|(name, _)| VECTOR_ORDER.iter().position(|vector_name| vector_name == name));
+
+        let mut vectors_file = String::new();
+        for (name, address) in vectors {
+            let label = self.preferred_label_at(address).map_or_else(|| format!("${address:04X}"), String::from);
+            vectors_file.push_str(&format!("    .word {label}        ; {name}\n"));
+        }
+        vectors_file
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Writes everything the disassembly found as JSON, for tools that would otherwise have to
+    /// scrape the listing. The top level object has:
+    ///
+    ///     schema_version    JSON_SCHEMA_VERSION
+    ///     header            format, mapper, ROM sizes and so on
+    ///     vectors           name, address and label of each vector
+    ///     instructions      address, bank, bytes, mnemonic, mode, operand, target and target label
+    ///     data_regions      start and end (exclusive) of each data region, with its kind
+    ///     labels            address, name and kind of every label, generated or not
+    ///     cross_references  from, to and kind of every jump, call, branch and memory access
+    ///
+    /// Addresses are CPU addresses, and banks are 16 KiB PRG banks counted from $8000.
+    pub fn export_json(&self, filename: &str) {
+        write_text_file(filename, &self.json_model().format());
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn json_model(&self) -> JsonValue {
+        JsonValue::Object(vec![
+            ("schema_version", JsonValue::Number(JSON_SCHEMA_VERSION)),
+            ("header", self.header_json()),
+            ("vectors", JsonValue::Array(self.vectors.iter().map(|(name, address)| JsonValue::Object(vec![
+                ("name", JsonValue::String(name.to_string())),
+                ("address", JsonValue::Number(*address as i64)),
+                ("label", self.label_json(*address)),
+            ])).collect())),
+            ("instructions", JsonValue::Array(self.instructions_json())),
+            ("data_regions", JsonValue::Array(self.data_regions_json())),
+            ("labels", JsonValue::Array(self.labels_json())),
+            ("cross_references", JsonValue::Array(self.cross_references_json())),
+        ])
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn header_json(&self) -> JsonValue {
+        let format = match &self.header {
+            Some(header) if header.is_nes2 => "nes2",
+            Some(_) => "ines",
+            None if self.mapper_id == FDS_MAPPER_ID => "fds",
+            None if self.music_entry_points.is_some() => "nsf",
+            None => "unif",
+        };
+
+        JsonValue::Object(vec![
+            ("format", JsonValue::String(String::from(format))),
+            ("mapper", JsonValue::Number(self.mapper_id as i64)),
+            ("submapper", JsonValue::from_option(self.header.as_ref(), |header| JsonValue::Number(header.submapper_id as i64))),
+            ("mirroring", JsonValue::from_option(self.header.as_ref(), |header| JsonValue::String(header.mirroring.name().to_string()))),
+            ("has_battery", JsonValue::from_option(self.header.as_ref(), |header| JsonValue::Bool(header.has_battery))),
+            ("prg_rom_bytes", JsonValue::Number(self.prg_rom_contents.len() as i64)),
+            ("chr_rom_bytes", JsonValue::Number(self.chr_rom_contents.len() as i64)),
+            ("prg_base_address", JsonValue::Number(self.prg_base_address as i64)),
+        ])
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn instructions_json(&self) -> Vec<JsonValue> {
+        let mut addresses: Vec<usize> = self.text_lines.keys().copied().collect();
+        addresses.sort();
+
+        addresses.into_iter()
+            .map(|address| {
+                let text_line = &self.text_lines[&address];
+                let (opcode, operand1, operand2) = self.instruction_bytes_at(address);
+                let decoded_opcode = decode_opcode(opcode);
+                let bytes = [opcode, operand1, operand2][..text_line.bytes].iter().map(|byte| JsonValue::Number(*byte as i64)).collect();
+                let operand = match text_line.bytes {
+                    2 => Some(operand1 as usize),
+                    3 => Some((operand2 as usize) << 8 | operand1 as usize),
+                    _ => None,
+                };
+                let target_address = self.instruction_target_address(address);
+
+                JsonValue::Object(vec![
+                    ("address", JsonValue::Number(address as i64)),
+                    ("bank", JsonValue::Number(self.bank_at(address) as i64)),
+                    ("bytes", JsonValue::Array(bytes)),
+                    ("mnemonic", JsonValue::from_option(decoded_opcode, |decoded_opcode| JsonValue::String(decoded_opcode.mnemonic.to_string()))),
+                    ("mode", JsonValue::from_option(decoded_opcode, |decoded_opcode| JsonValue::String(decoded_opcode.addressing_mode.name().to_string()))),
+                    ("operand", JsonValue::from_option(operand, |operand| JsonValue::Number(operand as i64))),
+                    ("target", JsonValue::from_option(target_address, |target_address| JsonValue::Number(target_address as i64))),
+                    ("target_label", target_address.map_or(JsonValue::Null, |target_address| self.label_json(target_address))),
+                    ("text", JsonValue::String(text_line.instruction_text.clone())),
+                ])
+            })
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn data_regions_json(&self) -> Vec<JsonValue> {
+        let mut regions: Vec<(usize, usize, &str)> = Vec::new();
+
+        for (address, inline_data) in &self.inline_data {
+            let kind = match inline_data {
+                InlineData::Bytes(_) => "inline_bytes",
+                InlineData::Pointers(_) => "pointer_table",
+            };
+            regions.push((*address, address + inline_data.bytes_count(), kind));
+        }
+        for (address, data_table) in &self.data_tables {
+            regions.push((*address, address + data_table.bytes_count(), data_table.name()));
+        }
+        for (start_address, end_address) in &self.forced_data {
+            regions.push((*start_address, *end_address, "forced_data"));
+        }
+        for (start_address, end_address) in &self.unexplored_data {
+            regions.push((*start_address, *end_address, "unexplored"));
+        }
+        if let Some(code_data_log) = &self.code_data_log {
+            let data_offsets: Vec<usize> = (0..self.prg_rom_contents.len())
+                .filter(|offset| code_data_log.is_data(*offset) && !code_data_log.is_code(*offset))
+                .collect();
+            for (start_offset, end_offset) in coalesce_offsets(&data_offsets) {
+                let kind = if code_data_log.is_pcm_audio(start_offset) { "dmc_sample" } else { "logged" };
+                regions.push((self.prg_rom_offset_to_address(start_offset), self.prg_rom_offset_to_address(end_offset) + 1, kind));
+            }
+        }
+        regions.sort();
+
+        regions.into_iter()
+            .map(|(start_address, end_address, kind)| JsonValue::Object(vec![
+                ("start", JsonValue::Number(start_address as i64)),
+                ("end", JsonValue::Number(end_address as i64)),
+                ("kind", JsonValue::String(String::from(kind))),
+            ]))
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn labels_json(&self) -> Vec<JsonValue> {
+        let symbols = self.labeller.symbols();
+
+        let mut addresses: BTreeSet<usize> = self.labeller.labelled_addresses();
+        addresses.extend(self.global_labels.keys());
+        addresses.extend(symbols.symbols().map(|(address, _)| address));
+
+        let mut labels = Vec::new();
+        for address in addresses {
+            let kinds_and_names = [
+                ("symbol", symbols.get_exact(address).map(|symbol| &symbol.name)),
+                ("vector", self.global_labels.get(&address)),
+                ("subroutine", self.labeller.get_subroutine_label(address)),
+                ("entry_point", self.labeller.get_entry_point_label(address)),
+                ("probable_code", self.labeller.get_probable_code_label(address)),
+                ("data", self.labeller.get_data_label(address)),
+                ("jump_target", self.labeller.get_jump_target_label(address)),
+                ("branch_target", self.labeller.get_branch_target_label(address)),
+            ];
+
+            for (kind, name) in kinds_and_names {
+                let Some(name) = name else {
+                    continue;
+                };
+                labels.push(JsonValue::Object(vec![
+                    ("address", JsonValue::Number(address as i64)),
+                    ("name", JsonValue::String(name.clone())),
+                    ("kind", JsonValue::String(String::from(kind))),
+                    ("comment", JsonValue::from_option(symbols.get_comment(address), |comment| JsonValue::String(comment.clone()))),
+                ]));
+            }
+        }
+
+        labels
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn cross_references_json(&self) -> Vec<JsonValue> {
+        self.cross_references().into_iter()
+            .map(|(address, target_address, kind)| JsonValue::Object(vec![
+                ("from", JsonValue::Number(address as i64)),
+                ("to", JsonValue::Number(target_address as i64)),
+                ("kind", JsonValue::String(String::from(kind))),
+            ]))
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns every jump, call, branch and memory access made by the traced instructions, as
+    /// (from, to, kind).
+    fn cross_references(&self) -> Vec<(usize, usize, &'static str)> {
+        let mut addresses: Vec<usize> = self.text_lines.keys().copied().collect();
+        addresses.sort();
+
+        let mut cross_references = Vec::new();
+        for address in addresses {
+            for (target_address, kind) in self.control_flow_graph.instruction_edges(address) {
+                if *kind != EdgeKind::Fallthrough {
+                    cross_references.push((address, *target_address, kind.name()));
+                }
+            }
+            if let Some((target_address, kind)) = self.memory_reference_at(address) {
+                cross_references.push((address, target_address, kind));
+            }
+        }
+        for (address, data_table) in &self.data_tables {
+            match data_table {
+                DataTable::Pointers { pointers, .. } => {
+                    for (index, pointer) in pointers.iter().enumerate() {
+                        cross_references.push((address + index * 2, *pointer, "pointer"));
+                    }
+                },
+                DataTable::PointerBytes { pointers, is_high_byte: false, .. } => {
+                    for (index, pointer) in pointers.iter().enumerate() {
+                        cross_references.push((address + index, *pointer, "pointer"));
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        cross_references
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the address the instruction at `address` jumps, calls, branches to or accesses.
+    pub fn instruction_target_address(&self, address: usize) -> Option<usize> {
+        self.control_flow_graph.instruction_edges(address).iter()
+            .find(|(_, kind)| *kind != EdgeKind::Fallthrough)
+            .map(|(target_address, _)| *target_address)
+            .or_else(|| self.memory_reference_at(address).map(|(target_address, _)| target_address))
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the memory the instruction at `address` reads or writes through its operand, along
+    /// with whether it's a `read`, `write` or `read_write`. Jumps and calls aren't included, since
+    /// they're in the control flow graph, but the pointer of an indirect jump is.
+    fn memory_reference_at(&self, address: usize) -> Option<(usize, &'static str)> {
+        let (opcode, operand1, operand2) = self.instruction_bytes_at(address);
+        let decoded_opcode = decode_opcode(opcode)?;
+
+        let target_address = match decoded_opcode.addressing_mode {
+            AddressingMode::ZeroPage | AddressingMode::ZeroPageX | AddressingMode::ZeroPageY
+                | AddressingMode::IndexedIndirect | AddressingMode::IndirectIndexed => operand1 as usize,
+            AddressingMode::Absolute if ["JMP", "JSR"].contains(&decoded_opcode.mnemonic) => return None,
+            AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY
+                | AddressingMode::Indirect => (operand2 as usize) << 8 | operand1 as usize,
+            AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::Immediate | AddressingMode::Relative => return None,
+        };
+        let kind = match decoded_opcode.mnemonic {
+            "STA" | "STX" | "STY" => "write",
+            "INC" | "DEC" | "ASL" | "LSR" | "ROL" | "ROR" => "read_write",
+            _ => "read",
+        };
+
+        Some((target_address, kind))
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn label_json(&self, address: usize) -> JsonValue {
+        self.name_at(address).map_or(JsonValue::Null, JsonValue::String)
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the name `address` goes by in the listing, if any, whether it's a label, a user
+    /// symbol or a register.
+    pub fn name_at(&self, address: usize) -> Option<String> {
+        self.preferred_label_at(address).map(String::from)
+            .or_else(|| self.labeller.get_symbol_name(address))
+            .or_else(|| register_name(address).map(String::from))
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Finds the address of a label, user symbol or register by its name.
+    pub fn address_of_name(&self, name: &str) -> Option<usize> {
+        let mut addresses: BTreeSet<usize> = self.labeller.labelled_addresses();
+        addresses.extend(self.global_labels.keys());
+        addresses.extend(self.labeller.symbols().symbols().map(|(address, _)| address));
+
+        addresses.into_iter()
+            .find(|address| {
+                self.labels_at(*address).iter().any(|(label, _)| *label == name)
+                    || self.labeller.symbols().get_exact(*address).is_some_and(|symbol| symbol.name == name)
+            })
+            .or_else(|| (0x2000..=0x4017).find(|address| register_name(*address) == Some(name)))
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the instructions that jump, call, branch to or access `address`, with the kind of
+    /// each reference.
+    pub fn cross_references_to(&self, address: usize) -> Vec<(usize, &'static str)> {
+        self.cross_references().into_iter()
+            .filter(|(_, target_address, _)| *target_address == address)
+            .map(|(from_address, _, kind)| (from_address, kind))
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the lines of the listing along with the address each one is for, if any.
+    pub fn listing_lines(&self) -> Vec<(Option<usize>, String)> {
+        self.format_disassembly(0, 0x10000).lines()
+            .map(|line| (listing_line_address(line), String::from(line)))
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the start and end (exclusive) address of each 16 KiB PRG bank, with the last one
+    /// running to the end of memory. Disk images and music rips aren't split into banks, so they
+    /// get a single one.
+    fn bank_address_ranges(&self) -> Vec<(usize, usize)> {
+        if self.is_cartridge() {
+            (0..self.prg_rom_bank_count)
+                .map(|bank| {
+                    let start_address = self.prg_rom_offset_to_address(bank * PRG_ROM_BANK_BYTES);
+                    let is_last_bank = bank + 1 == self.prg_rom_bank_count;
+                    (start_address, if is_last_bank { 0x10000 } else { start_address + PRG_ROM_BANK_BYTES })
+                })
+                .collect()
+        } else {
+            vec![(self.prg_base_address, 0x10000)]
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Writes a hyperlinked copy of the disassembly into `directory`, as `index.html` with the
+    /// vectors, labels and the memory outside PRG, and a page per PRG bank. Every label lists what
+    /// refers to it, and everything that wasn't traced as code is shown as hex and ASCII.
+    pub fn export_html(&self, directory: &str, cartridge_filename: &str) {
+        if let Err(error) = fs::create_dir_all(directory) {
+            panic!("[ERROR] Could not create directory {directory}: {error}");
+        }
+
+        for (filename, page) in format_pages(&self.html_listing(cartridge_filename)) {
+            write_text_file(&format!("{directory}/{filename}"), &page);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Gathers what the HTML viewer shows: the header, the vectors, a listing of each PRG bank,
+    /// the memory outside PRG that's referred to or named, and the names of all of them.
+    fn html_listing(&self, cartridge_filename: &str) -> Listing {
+        let JsonValue::Object(header_json_fields) = self.header_json() else {
+            unreachable!();
+        };
+        let mut header_fields = Vec::new();
+        for (name, value) in header_json_fields {
+            let value = match value {
+                JsonValue::Null => continue,
+                JsonValue::String(value) => value,
+                JsonValue::Bool(value) => String::from(if value { "yes" } else { "no" }),
+                JsonValue::Number(value) if name == "prg_base_address" => format!("${value:04X}"),
+                JsonValue::Number(value) => value.to_string(),
+                JsonValue::Array(_) | JsonValue::Object(_) => continue,
+            };
+            header_fields.push((name.replace('_', " "), value));
+        }
+
+        let banks: Vec<ListingBank> = self.bank_address_ranges().into_iter()
+            .map(|(start_address, end_address)| ListingBank { start_address, end_address, lines: self.listing_lines_between(start_address, end_address) })
+            .collect();
+
+        let cross_references = self.cross_references();
+        let mut outside_addresses: BTreeSet<usize> = cross_references.iter()
+            .map(|(_, target_address, _)| *target_address)
+            .filter(|address| self.address_to_prg_rom_offset(*address).is_none())
+            .collect();
+        outside_addresses.extend(self.labeller.symbols().symbols().map(|(address, _)| address).filter(|address| !self.is_prg_address(*address)));
+        outside_addresses.extend(self.labeller.symbols().comment_addresses().filter(|address| !self.is_prg_address(*address)));
+
+        let bank_addresses = banks.iter().flat_map(|bank| bank.lines.iter().map(ListingLine::address));
+        let vector_addresses = self.vectors.iter().map(|(_, address)| *address);
+        let names: HashMap<usize, String> = outside_addresses.iter().copied().chain(bank_addresses).chain(vector_addresses)
+            .filter_map(|address| self.name_at(address).map(|name| (address, name)))
+            .collect();
+
+        Listing {
+            title: String::from(cartridge_filename),
+            header_fields,
+            vectors: self.vectors.clone(),
+            banks,
+            outside_addresses: outside_addresses.into_iter()
+                .map(|address| OutsideAddress {
+                    address,
+                    labels: self.labels_at(address).into_iter().map(|(label, _)| String::from(label)).collect(),
+                    comment: self.labeller.symbols().get_comment(address).map(String::from),
+                })
+                .collect(),
+            cross_references,
+            names,
+            symbols: self.labeller.symbols().symbols().map(|(address, symbol)| (address, symbol.name.clone())).collect(),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Splits the addresses from `start_address` up to `end_address` into labels, instructions
+    /// and rows of data for the HTML viewer.
+    fn listing_lines_between(&self, start_address: usize, end_address: usize) -> Vec<ListingLine> {
+        let mut lines = Vec::new();
+
+        let mut address = start_address;
+        while address < end_address {
+            if self.address_to_prg_rom_offset(address).is_none() {
+                address += 1;
+                continue;
+            }
+
+            let labels = self.labels_at(address);
+            if !labels.is_empty() {
+                lines.push(ListingLine::Labels {
+                    address,
+                    labels: labels.into_iter().map(|(label, is_section_start)| (String::from(label), is_section_start)).collect(),
+                    comment: self.labeller.symbols().get_comment(address).map(String::from),
+                });
+            }
+
+            if let Some(text_line) = self.text_lines.get(&address) {
+                let overlapped_address = self.overlapped_instruction_in(address);
+                lines.push(ListingLine::Instruction {
+                    address,
+                    bytes: self.prg_rom_bytes(address, text_line.bytes).to_vec(),
+                    text: text_line.instruction_text.clone(),
+                    overlapped_address,
+                });
+                address = overlapped_address.unwrap_or(address + text_line.bytes);
+                continue;
+            }
+
+            let kind = self.data_kind_at(address);
+            let mut bytes_count = 1;
+            while bytes_count < HEX_ROW_BYTES {
+                let next_address = address + bytes_count;
+                let is_same_row = next_address < end_address
+                    && !next_address.is_multiple_of(HEX_ROW_BYTES)
+                    && self.address_to_prg_rom_offset(next_address).is_some()
+                    && !self.text_lines.contains_key(&next_address)
+                    && self.labels_at(next_address).is_empty()
+                    && self.data_kind_at(next_address) == kind;
+                if !is_same_row {
+                    break;
+                }
+                bytes_count += 1;
+            }
+
+            lines.push(ListingLine::Data { address, bytes: self.prg_rom_bytes(address, bytes_count).to_vec(), kind });
+            address += bytes_count;
+        }
+
+        lines
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Describes what's known about a byte that wasn't traced as code.
+    fn data_kind_at(&self, address: usize) -> &'static str {
+        if let Some((start_address, data_table)) = self.data_tables.range(..=address).next_back()
+            && address < start_address + data_table.bytes_count() {
+            return data_table.describe();
+        }
+        if self.is_declared_data(address) {
+            return "forced data";
+        }
+        if let Some((start_address, inline_data)) = self.inline_data.range(..=address).next_back()
+            && address < start_address + inline_data.bytes_count() {
+            return "inline data";
+        }
+        if let Some((_, end_address)) = self.unexplored_data.range(..=address).next_back() && address < *end_address {
+            return "unexplored";
+        }
+        if let Some(code_data_log) = &self.code_data_log
+            && let Some(offset) = self.address_to_prg_rom_offset(address)
+            && code_data_log.is_data(offset) {
+            return if code_data_log.is_pcm_audio(offset) { "DMC sample" } else { "logged data" };
+        }
+        "untraced"
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the 16 KiB PRG bank `address` is in, counted from $8000 unless the project mapped
+    /// the banks. Disk images and music rips aren't split into banks, so they're all bank 0.
+    fn bank_at(&self, address: usize) -> usize {
+        if !self.is_cartridge() || address < 0x8000 {
+            return 0;
+        }
+
+        let window = (address - 0x8000) / PRG_ROM_BANK_BYTES;
+        self.prg_bank_mapping.map_or(window, |mapping| mapping[window.min(1)])
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the listing for the addresses from `start_address` up to `end_address`.
+    fn format_disassembly(&self, start_address: usize, end_address: usize) -> String {
+        let mut listing = String::new();
+        let mut register_write_tracker = RegisterWriteTracker::new(self.music_entry_points.is_some());
+        let mut basic_block_timing: Option<BasicBlockTiming> = None;
+
+        let mut address = start_address;
+        while address < end_address {
+            // Labelled addresses can be jumped to from elsewhere, so they start a new block.
+            if self.has_label_at(address) || !self.text_lines.contains_key(&address) {
+                self.format_basic_block_timing(&mut listing, basic_block_timing.take());
+            }
+
+            let is_labelled = self.format_labels_at(&mut listing, address);
+
+            // Labelled addresses can be reached from elsewhere, so register values loaded
+            // before them can't be trusted anymore.
+            if is_labelled {
+                register_write_tracker.reset();
+            }
+
+            if let Some(text_line) = self.text_lines.get(&address) && let Some(inner_address) = self.overlapped_instruction_in(address) {
+                // Only the bytes up to the instruction starting inside this one can be emitted,
+                // so they become data, with this instruction's decoding kept as a comment.
+                register_write_tracker.reset();
+                self.format_basic_block_timing(&mut listing, basic_block_timing.take());
+                self.format_labels_inside(&mut listing, address, inner_address - address);
+                self.format_overlapping_instruction(&mut listing, address, text_line, inner_address);
+                address = inner_address;
+            } else if let Some(text_line) = self.text_lines.get(&address) {
+                let (opcode, operand1, operand2) = self.instruction_bytes_at(address);
+                self.format_labels_inside(&mut listing, address, text_line.bytes);
+
+                let mut annotations = Vec::new();
+                if self.should_show_cycle_counts && let Some(timing) = calculate_instruction_timing(opcode, operand1, address) {
+                    annotations.push(timing.describe());
+                    basic_block_timing.get_or_insert_with(|| BasicBlockTiming::new(address)).add_instruction(address, &timing);
+                }
+                if let Some(description) = register_write_tracker.process_instruction(opcode, operand1, operand2) {
+                    annotations.push(description);
+                }
+                if let Some(source_location) = self.debug_info.as_ref().and_then(|debug_info| debug_info.source_locations.get(&address)) {
+                    annotations.push(format!("{}:{}", source_location.filename, source_location.line));
+                }
+
+                if annotations.is_empty() {
+                    listing.push_str(&format!("{}\n", text_line.contents));
+                } else {
+                    listing.push_str(&format!("{} ; {}\n", text_line.contents, annotations.join("; ")));
+                }
+
+                if ends_basic_block(opcode) {
+                    self.format_basic_block_timing(&mut listing, basic_block_timing.take());
+                }
+                address += text_line.bytes;
+            } else if let Some(inline_data) = self.inline_data.get(&address) {
+                register_write_tracker.reset();
+                address += self.format_inline_data(&mut listing, address, inline_data);
+            } else if let Some(data_table) = self.data_tables.get(&address) {
+                register_write_tracker.reset();
+                self.format_labels_inside(&mut listing, address, data_table.bytes_count());
+                address += self.format_data_table(&mut listing, address, data_table);
+            } else {
+                register_write_tracker.reset();
+                let mut byte_count = self.format_data_range_at(&mut listing, address, &self.forced_data);
+                if byte_count == 0 {
+                    byte_count = self.format_logged_data_at(&mut listing, address);
+                }
+                if byte_count == 0 {
+                    byte_count = self.format_data_range_at(&mut listing, address, &self.unexplored_data);
+                }
+                address += byte_count.max(1);
+            }
+        }
+
+        listing
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the first traced instruction that starts inside the instruction at `address`, as
+    /// happens when code jumps over a byte, e.g. with a `BIT` opcode hiding the next instruction.
+    fn overlapped_instruction_in(&self, address: usize) -> Option<usize> {
+        let text_line = self.text_lines.get(&address)?;
+        (address + 1..address + text_line.bytes).find(|inner_address| self.text_lines.contains_key(inner_address))
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn format_overlapping_instruction(&self, listing: &mut String, address: usize, text_line: &TextLine, inner_address: usize) {
+        let bytes: Vec<String> = self.prg_rom_bytes(address, inner_address - address).iter()
+            .map(|byte| format!("${byte:02X}"))
+            .collect();
+        let all_bytes: Vec<String> = (address..address + text_line.bytes)
+            .filter_map(|byte_address| self.address_to_prg_rom_offset(byte_address))
+            .map(|offset| format!("{:02X}", self.prg_rom_contents[offset]))
+            .collect();
+
+        listing.push_str(&format!(
+            "    .byte {}        # {:04X} | {} ; {}, overlapping the instruction at {:04X}\n",
+            bytes.join(", "), address, all_bytes.join(" "), text_line.instruction_text, inner_address));
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Defines the labels that point into the middle of the instruction at `address` relative to
+    /// it, since they can't be placed on a line of their own.
+    fn format_labels_inside(&self, listing: &mut String, address: usize, bytes_count: usize) {
+        for inner_address in address + 1..address + bytes_count {
+            let Some(label) = self.preferred_label_at(inner_address) else {
+                continue;
+            };
+
+            match self.preferred_label_at(address) {
+                Some(base_label) => listing.push_str(&format!("{label} = {base_label}+{}\n", inner_address - address)),
+                None => listing.push_str(&format!("{label} = *+{}\n", inner_address - address)),
+            }
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns every pair of traced instructions where the second starts inside the first.
+    fn overlapping_instructions(&self) -> Vec<(usize, usize)> {
+        let mut addresses: Vec<usize> = self.text_lines.keys().copied().collect();
+        addresses.sort();
+
+        addresses.into_iter()
+            .filter_map(|address| self.overlapped_instruction_in(address).map(|inner_address| (address, inner_address)))
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn format_basic_block_timing(&self, listing: &mut String, basic_block_timing: Option<BasicBlockTiming>) {
+        if let Some(basic_block_timing) = basic_block_timing {
+            listing.push_str(&format!("    ; {}\n", basic_block_timing.describe()));
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Compares the traced code against the segments in the loaded debug info, flagging segments
+    /// that ended up somewhere other than where they were linked for and code that was traced
+    /// outside of any code segment.
+    pub fn print_segment_report(&self) {
+        let Some(debug_info) = &self.debug_info else {
+            return;
+        };
+
+        println!("------------------------------------------------------------------------------");
+        println!("Segment report:");
+
+        let mut problem_count = 0;
+
+        for segment in &debug_info.segments {
+            let Some(output_offset) = segment.output_offset else {
+                continue;
+            };
+            if output_offset < NES_HEADER_BYTES || segment.size == 0 {
+                continue;
+            }
+
+            let prg_rom_offset = output_offset - NES_HEADER_BYTES;
+            if prg_rom_offset >= self.prg_rom_contents.len() {
+                println!("  segment {} lies outside of PRG ROM (file offset ${:X})", segment.name, output_offset);
+                problem_count += 1;
+                continue;
+            }
+
+            let placed_address = self.prg_rom_offset_to_address(prg_rom_offset);
+            if placed_address != segment.start {
+                println!(
+                    "  segment {} was linked for ${:04X} but sits at PRG offset ${:X}, which maps to ${:04X}",
+                    segment.name, segment.start, prg_rom_offset, placed_address);
+                problem_count += 1;
+            }
+        }
+
+        for (start_address, end_address) in self.traced_code_ranges() {
+            let start_segment = debug_info.find_segment(start_address);
+            let end_segment = debug_info.find_segment(end_address);
+
+            match start_segment {
+                None => {
+                    println!("  code at ${:04X}-${:04X} is outside of every segment", start_address, end_address);
+                    problem_count += 1;
+                },
+                Some(segment) if !is_code_segment(&segment.name, &segment.segment_type) => {
+                    println!("  code at ${:04X}-${:04X} landed in {} segment {}", start_address, end_address, segment.segment_type, segment.name);
+                    problem_count += 1;
+                },
+                Some(segment) if end_segment.is_none_or(|end_segment| end_segment.name != segment.name) => {
+                    println!("  code at ${:04X}-${:04X} runs off the end of segment {}", start_address, end_address, segment.name);
+                    problem_count += 1;
+                },
+                Some(_) => {},
+            }
+        }
+
+        if problem_count == 0 {
+            println!("  all traced code is where the linker put it");
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the inclusive address ranges covered by contiguous runs of traced instructions.
+    fn traced_code_ranges(&self) -> Vec<(usize, usize)> {
+        let mut addresses: Vec<usize> = self.text_lines.keys().copied().collect();
+        addresses.sort();
+
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        for address in addresses {
+            let end_address = address + self.text_lines[&address].bytes - 1;
+            match ranges.last_mut() {
+                Some((_, previous_end_address)) if *previous_end_address + 1 == address => *previous_end_address = end_address,
+                _ => ranges.push((address, end_address)),
+            }
+        }
+
+        ranges
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Formats a `.byte` line for the run of logged data starting at `address`, returning how many
+    /// bytes it covered.
+    fn format_logged_data_at(&self, listing: &mut String, address: usize) -> usize {
+        const MAX_BYTES_PER_LINE: usize = 8;
+
+        let Some(code_data_log) = &self.code_data_log else {
+            return 0;
+        };
+
+        let is_logged_data = |address: usize| {
+            self.address_to_prg_rom_offset(address).is_some_and(|offset| code_data_log.is_data(offset) && !code_data_log.is_code(offset))
+        };
+        if !is_logged_data(address) {
+            return 0;
+        }
+
+        let Some(start_offset) = self.address_to_prg_rom_offset(address) else {
+            return 0;
+        };
+        let is_pcm_audio = code_data_log.is_pcm_audio(start_offset);
+
+        let mut byte_count = 1;
+        while byte_count < MAX_BYTES_PER_LINE {
+            let next_address = address + byte_count;
+            let is_same_kind = is_logged_data(next_address)
+                && self.address_to_prg_rom_offset(next_address).is_some_and(|offset| code_data_log.is_pcm_audio(offset) == is_pcm_audio);
+            if !is_same_kind || self.text_lines.contains_key(&next_address) || self.starts_data_line_at(next_address) {
+                break;
+            }
+            byte_count += 1;
+        }
+
+        let bytes: Vec<String> = self.prg_rom_bytes(address, byte_count).iter()
+            .map(|byte| format!("${byte:02X}"))
+            .collect();
+        let text_line = format!("    .byte {}        # {:04X}", bytes.join(", "), address);
+
+        if is_pcm_audio {
+            listing.push_str(&format!("{text_line} ; DMC sample data\n"));
+        } else {
+            listing.push_str(&format!("{text_line}\n"));
+        }
+
+        byte_count
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Formats the data following a JSR and returns how many bytes it took up.
+    fn format_inline_data(&self, listing: &mut String, address: usize, inline_data: &InlineData) -> usize {
+        const MAX_BYTES_PER_LINE: usize = 8;
+
+        match inline_data {
+            InlineData::Bytes(byte_count) => {
+                for (line_index, line_bytes) in self.prg_rom_bytes(address, *byte_count).chunks(MAX_BYTES_PER_LINE).enumerate() {
+                    let bytes: Vec<String> = line_bytes.iter().map(|byte| format!("${byte:02X}")).collect();
+                    listing.push_str(&format!("    .byte {}        # {:04X}\n", bytes.join(", "), address + line_index * MAX_BYTES_PER_LINE));
+                }
+            },
+            InlineData::Pointers(pointers) => {
+                for (pointer_index, pointer) in pointers.iter().enumerate() {
+                    let label = self.preferred_label_at(*pointer).map_or_else(|| format!("${pointer:04X}"), String::from);
+                    listing.push_str(&format!("    .word {label}        # {:04X}\n", address + pointer_index * 2));
+                }
+            },
+        }
+
+        inline_data.bytes_count().max(1)
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Formats a data table the way its annotation described it and returns how many bytes it
+    /// took up.
+    fn format_data_table(&self, listing: &mut String, address: usize, data_table: &DataTable) -> usize {
+        const WORDS_PER_LINE: usize = 4;
+        const TEXT_BYTES_PER_LINE: usize = 32;
+
+        let name_of = |pointer: usize| self.name_at(pointer).unwrap_or_else(|| format!("${pointer:04X}"));
+        let word_at = |address: usize| {
+            let (low_byte, high_byte, _) = self.instruction_bytes_at(address);
+            (high_byte as usize) << 8 | low_byte as usize
+        };
+
+        match data_table {
+            DataTable::Bytes { bytes_count, bytes_per_line } => {
+                for (line_index, line_bytes) in self.prg_rom_bytes(address, *bytes_count).chunks(*bytes_per_line).enumerate() {
+                    let bytes: Vec<String> = line_bytes.iter().map(|byte| format!("${byte:02X}")).collect();
+                    listing.push_str(&format!("    .byte {}        # {:04X}\n", bytes.join(", "), address + line_index * bytes_per_line));
+                }
+            },
+            DataTable::Words(count) => {
+                for line_start in (0..*count).step_by(WORDS_PER_LINE) {
+                    let words: Vec<String> = (line_start..(line_start + WORDS_PER_LINE).min(*count))
+                        .map(|index| format!("${:04X}", word_at(address + index * 2)))
+                        .collect();
+                    listing.push_str(&format!("    .word {}        # {:04X}\n", words.join(", "), address + line_start * 2));
+                }
+            },
+            DataTable::Pointers { pointers, .. } => {
+                for (index, pointer) in pointers.iter().enumerate() {
+                    listing.push_str(&format!("    .word {}        # {:04X}\n", name_of(*pointer), address + index * 2));
+                }
+            },
+            DataTable::PointerBytes { pointers, is_high_byte, .. } => {
+                let operator = if *is_high_byte { '>' } else { '<' };
+                for (index, pointer) in pointers.iter().enumerate() {
+                    listing.push_str(&format!("    .byte {operator}{}        # {:04X}\n", name_of(*pointer), address + index));
+                }
+            },
+            DataTable::Records { count, fields } => {
+                let record_bytes: usize = fields.iter().map(|field| field.bytes).sum();
+                for record_index in 0..*count {
+                    let record_address = address + record_index * record_bytes;
+                    let bytes: Vec<String> = self.prg_rom_bytes(record_address, record_bytes).iter().map(|byte| format!("${byte:02X}")).collect();
+
+                    let mut field_address = record_address;
+                    let mut values = Vec::new();
+                    for field in fields {
+                        match field.bytes {
+                            2 => values.push(format!("{}=${:04X}", field.name, word_at(field_address))),
+                            _ => values.push(format!("{}=${:02X}", field.name, self.instruction_bytes_at(field_address).0)),
+                        }
+                        field_address += field.bytes;
+                    }
+
+                    listing.push_str(&format!("    .byte {}        # {:04X} ; {}\n", bytes.join(", "), record_address, values.join(" ")));
+                }
+            },
+            DataTable::Text(length) => {
+                for (line_index, line_bytes) in self.prg_rom_bytes(address, *length).chunks(TEXT_BYTES_PER_LINE).enumerate() {
+                    listing.push_str(&format!("    .byte {}        # {:04X}\n", self.format_text(line_bytes), address + line_index * TEXT_BYTES_PER_LINE));
+                }
+            },
+        }
+
+        data_table.bytes_count().max(1)
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Writes text bytes as quoted runs of characters, with the bytes the character map doesn't
+    /// cover, or that can't go inside quotes, written as numbers between them.
+    fn format_text(&self, bytes: &[u8]) -> String {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+
+        for byte in bytes {
+            match self.annotations.get_character(*byte) {
+                Some(character) if character != '"' && character != '\\' => text.push(character),
+                _ => {
+                    if !text.is_empty() {
+                        parts.push(format!("\"{text}\""));
+                        text.clear();
+                    }
+                    parts.push(format!("${byte:02X}"));
+                },
+            }
+        }
+        if !text.is_empty() {
+            parts.push(format!("\"{text}\""));
+        }
+
+        parts.join(", ")
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns up to `bytes_count` PRG ROM bytes from `address`, stopping at the end of the ROM.
+    fn prg_rom_bytes(&self, address: usize, bytes_count: usize) -> &[u8] {
+        let Some(start_offset) = self.address_to_prg_rom_offset(address) else {
+            return &[];
+        };
+        &self.prg_rom_contents[start_offset..(start_offset + bytes_count).min(self.prg_rom_contents.len())]
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Formats a `.byte` line for bytes in one of `data_ranges`, such as the ones the linear sweep
+    /// left as data, returning how many bytes it covered.
+    fn format_data_range_at(&self, listing: &mut String, address: usize, data_ranges: &BTreeMap<usize, usize>) -> usize {
+        const MAX_BYTES_PER_LINE: usize = 8;
+
+        let Some((_, end_address)) = data_ranges.range(..=address).next_back() else {
+            return 0;
+        };
+        let Some(start_offset) = self.address_to_prg_rom_offset(address) else {
+            return 0;
+        };
+        if *end_address <= address {
+            return 0;
+        }
+
+        let mut byte_count = 1;
+        while byte_count < MAX_BYTES_PER_LINE
+            && address + byte_count < *end_address
+            && start_offset + byte_count < self.prg_rom_contents.len()
+            && !self.starts_data_line_at(address + byte_count) {
+            byte_count += 1;
+        }
+
+        let bytes: Vec<String> = self.prg_rom_contents[start_offset..start_offset + byte_count].iter()
+            .map(|byte| format!("${byte:02X}"))
+            .collect();
+        listing.push_str(&format!("    .byte {}        # {:04X}\n", bytes.join(", "), address));
+
+        byte_count
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns whether a `.byte` line has to start at `address`, because it's labelled or it's where
+    /// a cartridge's vectors start, which are exported in a file of their own.
+    fn starts_data_line_at(&self, address: usize) -> bool {
+        self.has_label_at(address) || (self.is_cartridge() && address == CARTRIDGE_VECTORS_ADDRESS)
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn has_label_at(&self, address: usize) -> bool {
+        self.global_labels.contains_key(&address)
+            || self.labeller.has_label(address)
+            || self.labeller.symbols().get_exact(address).is_some()
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn format_labels_at(&self, listing: &mut String, address: usize) -> bool {
+        let labels = self.labels_at(address);
+        for (label, is_section_start) in &labels {
+            let spacing = if *is_section_start { "\n\n\n" } else { "" };
+            listing.push_str(&format!("{spacing}{label}: [{:04X}]\n", address));
+        }
+
+        if let Some(comment) = self.labeller.symbols().get_comment(address) && self.is_prg_address(address) {
+            for comment_line in comment.lines() {
+                listing.push_str(&format!("    ; {comment_line}\n"));
+            }
+        }
+
+        !labels.is_empty()
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns every distinct label at `address`, along with whether it starts a new section.
+    fn labels_at(&self, address: usize) -> Vec<(&str, bool)> {
+        let mut labels: Vec<(&str, bool)> = Vec::new();
+
+        if let Some(global_label) = self.global_labels.get(&address) {
+            labels.push((global_label, true));
+        }
+
+        if let Some(branch_label) = self.labeller.get_branch_target_label(address) {
+            labels.push((branch_label, false));
+        }
+
+        if let Some(jump_label) = self.labeller.get_jump_target_label(address) {
+            labels.push((jump_label, false));
+        }
+
+        if let Some(subroutine_label) = self.labeller.get_subroutine_label(address) {
+            labels.push((subroutine_label, true));
+        }
+
+        if let Some(entry_point_label) = self.labeller.get_entry_point_label(address) {
+            labels.push((entry_point_label, true));
+        }
+
+        if let Some(probable_code_label) = self.labeller.get_probable_code_label(address) {
+            labels.push((probable_code_label, true));
+        }
+
+        if let Some(data_label) = self.labeller.get_data_label(address) {
+            labels.push((data_label, true));
+        }
+
+        if let Some(symbol) = self.labeller.symbols().get_exact(address) && self.is_prg_address(address) {
+            labels.push((&symbol.name, false));
+        }
+
+        let mut distinct_labels: Vec<(&str, bool)> = Vec::new();
+        for (label, is_section_start) in labels {
+            if !distinct_labels.iter().any(|(distinct_label, _)| *distinct_label == label) {
+                distinct_labels.push((label, is_section_start));
+            }
+        }
+        distinct_labels
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the little-endian word at `address`, or None if either byte is outside PRG ROM.
+    fn word_at(&self, address: usize) -> Option<usize> {
+        let low_byte = self.prg_rom_contents[self.address_to_prg_rom_offset(address)?];
+        let high_byte = self.prg_rom_contents[self.address_to_prg_rom_offset(address + 1)?];
+        Some(((high_byte as usize) << 8) | low_byte as usize)
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn instruction_bytes_at(&self, address: usize) -> (u8, u8, u8) {
+        let byte_at = |offset: usize| {
+            self.address_to_prg_rom_offset(address + offset).map_or(0, |offset| self.prg_rom_contents[offset])
+        };
+
+        (byte_at(0), byte_at(1), byte_at(2))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+struct TextLine {
+    instruction_text: String,
+    contents: String,
+    bytes: usize,
+}
+
+/// Data placed right after a JSR, which the called subroutine reads through its return address.
+enum InlineData {
+    Bytes(usize),
+    Pointers(Vec<usize>),
+}
+
+/// Data laid out the way an annotation described it, with any pointers in it already read.
+enum DataTable {
+    Bytes { bytes_count: usize, bytes_per_line: usize },
+    Words(usize),
+    Pointers { pointers: Vec<usize>, is_code: bool },
+    PointerBytes { pointers: Vec<usize>, is_high_byte: bool, is_code: bool },
+    Records { count: usize, fields: Vec<RecordField> },
+    Text(usize),
+}
+
+// ---------------------------------------------------------------------------
+
+impl InlineData {
+    fn bytes_count(&self) -> usize {
+        match self {
+            InlineData::Bytes(byte_count) => *byte_count,
+            InlineData::Pointers(pointers) => pointers.len() * 2,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl DataTable {
+    fn bytes_count(&self) -> usize {
+        match self {
+            DataTable::Bytes { bytes_count, .. } => *bytes_count,
+            DataTable::Words(count) => count * 2,
+            DataTable::Pointers { pointers, .. } => pointers.len() * 2,
+            DataTable::PointerBytes { pointers, .. } => pointers.len(),
+            DataTable::Records { count, fields } => count * fields.iter().map(|field| field.bytes).sum::<usize>(),
+            DataTable::Text(length) => *length,
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn describe(&self) -> &'static str {
+        match self {
+            DataTable::Bytes { .. } => "byte table",
+            DataTable::Words(_) => "word table",
+            DataTable::Pointers { .. } => "pointer table",
+            DataTable::PointerBytes { is_high_byte: false, .. } => "pointer low bytes",
+            DataTable::PointerBytes { is_high_byte: true, .. } => "pointer high bytes",
+            DataTable::Records { .. } => "records",
+            DataTable::Text(_) => "text",
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the kind of data region the JSON export calls this.
+    fn name(&self) -> &'static str {
+        match self {
+            DataTable::Bytes { .. } => "byte_table",
+            DataTable::Words(_) => "word_table",
+            DataTable::Pointers { .. } => "pointer_table",
+            DataTable::PointerBytes { is_high_byte: false, .. } => "pointer_low_bytes",
+            DataTable::PointerBytes { is_high_byte: true, .. } => "pointer_high_bytes",
+            DataTable::Records { .. } => "records",
+            DataTable::Text(_) => "text",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns whether a board for `mapper_id` can hold `prg_rom_bytes` of PRG ROM. Only NROM is
+/// checked, since it's the only mapper that's disassembled without a project mapping its banks.
+fn fits_mapper(mapper_id: u16, prg_rom_bytes: usize) -> bool {
+    mapper_id != 0 || NROM_PRG_ROM_SIZES.contains(&prg_rom_bytes)
+}
+
+// ---------------------------------------------------------------------------
+
+/// Groups sorted offsets into inclusive ranges of consecutive offsets.
+fn coalesce_offsets(offsets: &[usize]) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+
+    for offset in offsets {
+        match ranges.last_mut() {
+            Some((_, end_offset)) if *end_offset + 1 == *offset => *end_offset = *offset,
+            _ => ranges.push((*offset, *offset)),
+        }
+    }
+
+    ranges
+}
+
+// ---------------------------------------------------------------------------
+
+fn is_code_segment(name: &str, segment_type: &str) -> bool {
+    let is_data_name = name.contains("DATA") || ["HEADER", "VECTORS", "CHARS"].contains(&name);
+    segment_type == "ro" && !is_data_name
+}
+
+// ---------------------------------------------------------------------------
+
+fn write_name_list(filename: &str, names: &[FceuxName]) {
+    write_text_file(filename, &format_nl(names));
+}
+
+// ---------------------------------------------------------------------------
+
+fn write_text_file(filename: &str, contents: &str) {
+    if let Err(error) = fs::write(filename, contents) {
+        panic!("[ERROR] Could not write {filename}: {error}");
+    }
+    println!("Wrote {filename}");
+}
+
+// ---------------------------------------------------------------------------
+
+/// Picks the address out of an instruction or data line (`... # 8000 | ...`) or a label line
+/// (`name: [8000]`).
+fn listing_line_address(line: &str) -> Option<usize> {
+    let address_text = match line.split_once("        # ") {
+        Some((_, rest)) => rest.get(..4)?,
+        None => line.strip_suffix(']')?.rsplit_once(": [")?.1,
+    };
+    usize::from_str_radix(address_text, 16).ok()
+}
+
+// ---------------------------------------------------------------------------
+
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .map(|character| if character.is_ascii_alphanumeric() || character == '_' || character == '-' { character } else { '_' })
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nrom_256_cartridge(code: &[u8], reset_vector: usize) -> Cartridge {
+        let mut prg_rom_contents = vec![0; 0x8000];
+        prg_rom_contents[..code.len()].copy_from_slice(code);
+        for vector_offset in [0x7FFA, 0x7FFC, 0x7FFE] {
+            prg_rom_contents[vector_offset] = reset_vector as u8;
+            prg_rom_contents[vector_offset + 1] = (reset_vector >> 8) as u8;
+        }
+        Cartridge::new(0, 2, 0, prg_rom_contents, Vec::new(), 0x8000)
+    }
+
+    fn nrom_128_cartridge(code: &[u8], reset_vector: usize) -> Cartridge {
+        let mut prg_rom_contents = vec![0; PRG_ROM_BANK_BYTES];
+        prg_rom_contents[..code.len()].copy_from_slice(code);
+        for vector_offset in [0x3FFA, 0x3FFC, 0x3FFE] {
+            prg_rom_contents[vector_offset] = reset_vector as u8;
+            prg_rom_contents[vector_offset + 1] = (reset_vector >> 8) as u8;
+        }
+        Cartridge::new(0, 1, 0, prg_rom_contents, Vec::new(), 0x8000)
+    }
+
+    #[test]
+    fn formats_labels_inside_instructions_and_overlapping_instructions() {
+        let mut cartridge = nrom_256_cartridge(&[
+            0x20, 0x08, 0x80, // JSR $8008
+            0xA9, 0x00,       // LDA #$00
+            0x2C, 0xA9, 0x01, // BIT $01A9, hiding LDA #$01
+            0x4C, 0x06, 0x80, // JMP $8006
+        ], 0x8000);
+        cartridge.global_labels.insert(0x8009, String::from("jump_operand"));
+        cartridge.disassemble();
+
+        let listing = cartridge.format_disassembly(0x8000, 0x800B);
+        assert!(listing.contains(concat!(
+            "    LDA #$00        # 8003 | A9 00\n",
+            "    .byte $2C        # 8005 | 2C A9 01 ; BIT $01A9, overlapping the instruction at 8006\n",
+            "jump_target_0: [8006]\n",
+            "    LDA #$01        # 8006 | A9 01\n",
+        )));
+        assert!(listing.contains(concat!(
+            "jump_operand = subroutine_0+1\n",
+            "    JMP jump_target_0        # 8008 | 4C 06 80\n",
+        )));
+    }
+
+    #[test]
+    fn formats_the_exported_header_symbols_and_vectors() {
+        let mut cartridge = nrom_256_cartridge(&[
+            0x8D, 0x00, 0x03, // STA $0300
+            0x4C, 0x00, 0x80, // JMP $8000
+            0x40,             // RTI
+        ], 0x8000);
+        cartridge.prg_rom_contents[0x7FFA] = 0x06;
+        cartridge.header = Some(NesHeader::parse(b"NES\x1A\x02\x00\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00").unwrap());
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0x0300, Symbol { name: String::from("buffer"), size: 1, comment: Some(String::from("scratch")) });
+        cartridge.load_symbols(symbols);
+        cartridge.disassemble();
+
+        assert_eq!(cartridge.format_header_file(), concat!(
+            "; iNES header, mapper 0\n",
+            "    .byte $4E, $45, $53, $1A, $02, $00, $01, $00\n",
+            "    .byte $00, $00, $00, $00, $00, $00, $00, $00\n",
+        ));
+        assert_eq!(cartridge.format_symbols_file(), "buffer = $0300 ; scratch\n");
+        assert_eq!(cartridge.format_vectors_file(), concat!(
+            "    .word NMI        ; NMI\n",
+            "    .word RESET        ; RESET\n",
+            "    .word RESET        ; IRQ\n",
+        ));
+    }
+
+    #[test]
+    fn traces_16_kib_prg_rom_from_its_mirror_at_c000() {
+        // LDA #$01 / JMP $C000
+        let mut cartridge = nrom_128_cartridge(&[0xA9, 0x01, 0x4C, 0x00, 0xC0], 0xC000);
+        cartridge.disassemble();
+
+        let listing = cartridge.format_disassembly(0x8000, 0x10000);
+        assert!(listing.contains("    LDA #$01        # C000 | A9 01\n"));
+        assert!(listing.contains("    JMP jump_target_0        # C002 | 4C 00 C0\n"));
+    }
+
+    #[test]
+    fn lists_instructions_running_past_the_end_of_prg_rom() {
+        // The vectors all point at $FFFE, which is INC $xxFF,X with its last byte past PRG ROM.
+        let mut cartridge = nrom_256_cartridge(&[], 0xFFFE);
+        cartridge.disassemble();
+        assert!(cartridge.format_disassembly(0xFFF0, 0x10000).contains("    INC $00FF,X        # FFFE | FE FF 00\n"));
+
+        // JSR $60BF at $BFFE takes its last byte from $C000, where the 16 KiB PRG ROM repeats.
+        let mut cartridge = nrom_128_cartridge(&[0x60], 0xBFFE);
+        cartridge.prg_rom_contents[0x3FFE] = 0x20;
+        cartridge.disassemble();
+        assert!(cartridge.format_disassembly(0xBFF0, 0xC000).contains("    JSR subroutine_0        # BFFE | 20 BF 60\n"));
+    }
+
+    #[test]
+    fn reads_the_vectors_of_8_kib_prg_rom() {
+        let mut prg_rom_contents = vec![0; 0x2000];
+        prg_rom_contents[0] = 0x40; // RTI
+        prg_rom_contents[0x1FFA..].copy_from_slice(&[0x00, 0xE0, 0x00, 0xE0, 0x00, 0xE0]);
+        let mut cartridge = Cartridge::new(0, 1, 0, prg_rom_contents, Vec::new(), 0x8000);
+        cartridge.disassemble();
+
+        assert_eq!(cartridge.vectors, vec![("RESET", 0xE000), ("NMI", 0xE000), ("IRQ", 0xE000)]);
+        assert_eq!(cartridge.bank_address_ranges(), vec![(0xE000, 0x10000)]);
+        assert!(cartridge.format_disassembly(0xE000, 0xE001).contains("    RTI        # E000 | 40\n"));
+    }
+
+    #[test]
+    fn checks_that_nrom_can_hold_the_prg_rom() {
+        assert!(fits_mapper(0, 0x2000));
+        assert!(fits_mapper(0, 0x8000));
+        assert!(!fits_mapper(0, 0x6000));
+        assert!(!fits_mapper(0, 0x10000));
+        assert!(fits_mapper(4, 0x6000));
+    }
+
+    #[test]
+    fn maps_both_halves_of_16_kib_prg_rom_to_the_same_bytes() {
+        let cartridge = nrom_128_cartridge(&[0xEA], 0xC000);
+
+        assert_eq!(cartridge.address_to_prg_rom_offset(0x8000), Some(0));
+        assert_eq!(cartridge.address_to_prg_rom_offset(0xC000), Some(0));
+        assert_eq!(cartridge.address_to_prg_rom_offset(0xFFFC), Some(0x3FFC));
+        assert_eq!(cartridge.address_to_prg_rom_offset(0x7FFF), None);
+        assert_eq!(cartridge.prg_rom_offset_to_address(0), 0xC000);
+        assert_eq!(cartridge.bank_address_ranges(), vec![(0xC000, 0x10000)]);
+    }
+
+    #[test]
+    fn formats_data_tables() {
+        let mut code = vec![0; 0x43];
+        code[0x00..0x03].copy_from_slice(&[0x4C, 0x00, 0xC0]); // JMP $C000
+        code[0x10..0x14].copy_from_slice(&[0x20, 0xC0, 0x23, 0xC0]);
+        code[0x14..0x18].copy_from_slice(&[0x20, 0x23, 0xC0, 0xC0]);
+        code[0x18..0x1C].copy_from_slice(&[0x20, 0xC0, 0x30, 0xC0]);
+        code[0x20] = 0x60; // RTS
+        code[0x23] = 0x60; // RTS
+        code[0x30..0x36].copy_from_slice(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        code[0x40..0x43].copy_from_slice(&[0x0A, 0x0B, 0x0C]);
+        let mut cartridge = nrom_128_cartridge(&code, 0xC000);
+        cartridge.annotations.extend(Annotations::parse("\
+C010 pointers 2 code
+C014 split-pointers 2 C016 code
+C018 pointers 2
+C030 records 2 x y:2
+C040 text 3
+charmap 0A \"ABC\"
+").unwrap());
+        cartridge.disassemble();
+
+        let listing = cartridge.format_disassembly(0xC010, 0xC043);
+        assert!(listing.contains(concat!(
+            "    .word jump_target_0        # C010\n",
+            "    .word jump_target_1        # C012\n",
+            "    .byte <jump_target_0        # C014\n",
+            "    .byte <jump_target_1        # C015\n",
+            "    .byte >jump_target_0        # C016\n",
+            "    .byte >jump_target_1        # C017\n",
+            "    .word jump_target_0        # C018\n",
+            "    .word data_1        # C01A\n",
+        )));
+        assert!(listing.contains(concat!(
+            "data_1: [C030]\n",
+            "    .byte $01, $02, $03        # C030 ; x=$01 y=$0302\n",
+            "    .byte $04, $05, $06        # C033 ; x=$04 y=$0605\n",
+            "    .byte \"ABC\"        # C040\n",
+        )));
+    }
+
+    fn json_keys(value: &JsonValue) -> Vec<&str> {
+        let JsonValue::Object(members) = value else {
+            panic!("not an object");
+        };
+        members.iter().map(|(key, _)| *key).collect()
+    }
+
+    fn json_member<'a>(value: &'a JsonValue, key: &str) -> &'a JsonValue {
+        let JsonValue::Object(members) = value else {
+            panic!("not an object");
+        };
+        members.iter().find(|(member_key, _)| *member_key == key).map(|(_, member)| member).unwrap()
+    }
+
+    fn json_first_element(value: &JsonValue) -> &JsonValue {
+        let JsonValue::Array(elements) = value else {
+            panic!("not an array");
+        };
+        &elements[0]
+    }
+
+    #[test]
+    fn exports_the_json_schema() {
+        let mut cartridge = nrom_128_cartridge(&[
+            0x8D, 0x00, 0x20, // STA $2000
+            0x4C, 0x00, 0xC0, // JMP $C000
+        ], 0xC000);
+        cartridge.disassemble();
+
+        let model = cartridge.json_model();
+        assert_eq!(json_keys(&model), vec!["schema_version", "header", "vectors", "instructions", "data_regions", "labels", "cross_references"]);
+        assert!(matches!(json_member(&model, "schema_version"), JsonValue::Number(JSON_SCHEMA_VERSION)));
+
+        let instruction = json_first_element(json_member(&model, "instructions"));
+        assert_eq!(json_keys(instruction), vec!["address", "bank", "bytes", "mnemonic", "mode", "operand", "target", "target_label", "text"]);
+        assert!(matches!(json_member(instruction, "address"), JsonValue::Number(0xC000)));
+
+        let label = json_first_element(json_member(&model, "labels"));
+        assert_eq!(json_keys(label), vec!["address", "name", "kind", "comment"]);
+
+        let cross_reference = json_first_element(json_member(&model, "cross_references"));
+        assert_eq!(json_keys(cross_reference), vec!["from", "to", "kind"]);
+    }
+
+    #[test]
+    fn moves_names_from_bank_name_lists_to_their_bank() {
+        let fceux_name = |address: usize, name: &str| FceuxName { address, size: 1, name: Some(String::from(name)), comment: None };
+
+        let mut cartridge = nrom_128_cartridge(&[], 0xC000);
+        cartridge.add_fceux_names(vec![fceux_name(0x8004, "mirrored"), fceux_name(0x0300, "buffer")], Some(0));
+        let symbols = cartridge.labeller.symbols();
+        assert_eq!(symbols.get_exact(0xC004).unwrap().name, "mirrored");
+        assert_eq!(symbols.get_exact(0x0300).unwrap().name, "buffer");
+        assert!(symbols.get_exact(0x8004).is_none());
+
+        let mut cartridge = nrom_256_cartridge(&[], 0x8000);
+        cartridge.add_fceux_names(vec![fceux_name(0x8010, "bank_0")], Some(0));
+        cartridge.add_fceux_names(vec![fceux_name(0x8010, "bank_1")], Some(1));
+        cartridge.add_fceux_names(vec![fceux_name(0x8020, "anywhere")], None);
+        let symbols = cartridge.labeller.symbols();
+        assert_eq!(symbols.get_exact(0x8010).unwrap().name, "bank_0");
+        assert_eq!(symbols.get_exact(0xC010).unwrap().name, "bank_1");
+        assert_eq!(symbols.get_exact(0x8020).unwrap().name, "anywhere");
+    }
+
+    #[test]
+    fn puts_names_in_the_mirror_of_16_kib_prg_rom_in_bank_0() {
+        // JSR $C004 / NOP / RTS
+        let mut cartridge = nrom_128_cartridge(&[0x20, 0x04, 0xC0, 0xEA, 0x60], 0xC000);
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0x0300, Symbol { name: String::from("buffer"), size: 1, comment: None });
+        cartridge.load_symbols(symbols);
+        cartridge.disassemble();
+
+        let (ram_names, bank_names) = cartridge.fceux_names();
+        assert_eq!(ram_names.iter().map(|name| name.address).collect::<Vec<usize>>(), vec![0x0300]);
+        assert_eq!(bank_names.len(), 1);
+        assert_eq!(bank_names[0].iter().map(|name| name.address).collect::<Vec<usize>>(), vec![0xC000, 0xC004]);
+    }
+
+    #[test]
+    fn formats_symbols_and_comments_outside_prg() {
+        let mut cartridge = nrom_128_cartridge(&[], 0xC000);
+        let mut symbols = SymbolTable::new();
+        symbols.insert(0x0012, Symbol { name: String::from("lvl_ptr"), size: 2, comment: Some(String::from("Level data\npointer")) });
+        symbols.insert(0x6000, Symbol { name: String::from("save"), size: 1, comment: None });
+        symbols.insert_comment(0x0040, String::from("scratch byte"));
+        symbols.insert_comment(0xC000, String::from("reset handler"));
+        cartridge.load_symbols(symbols);
+
+        assert_eq!(cartridge.format_symbols_file(), concat!(
+            "lvl_ptr = $0012 ; Level data\n",
+            "    ; pointer\n",
+            "; $0040: scratch byte\n",
+            "save = $6000\n",
+        ));
+    }
+
+    #[test]
+    fn stops_data_lines_at_the_vectors() {
+        let mut cartridge = nrom_128_cartridge(&[], 0xC000);
+        cartridge.forced_data.insert(0xFFF0, 0x10000);
+
+        let listing = cartridge.format_disassembly(0xFFF0, CARTRIDGE_VECTORS_ADDRESS);
+        assert_eq!(listing, concat!(
+            "    .byte $00, $00, $00, $00, $00, $00, $00, $00        # FFF0\n",
+            "    .byte $00, $00        # FFF8\n",
+        ));
+    }
 }
@@ -0,0 +1,113 @@
+// Checksums used to identify ROMs, computed the same way as the No-Intro and NES 2.0 header
+// databases do, over the raw PRG and CHR contents without any header.
+
+const CRC32_POLYNOMIAL: u32 = 0xEDB88320;
+
+const CRC32_TABLE: [u32; 256] = create_crc32_table();
+
+const SHA1_INITIAL_STATE: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+// ---------------------------------------------------------------------------
+
+pub fn crc32(contents: &[u8]) -> u32 {
+    let crc = contents.iter().fold(0xFFFFFFFF, |crc: u32, byte| {
+        CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8)
+    });
+
+    !crc
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn sha1(contents: &[u8]) -> [u8; 20] {
+    // Pad to a whole number of 64 byte blocks, ending with the length in bits.
+    let mut message = contents.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((contents.len() as u64) * 8).to_be_bytes());
+
+    let mut state = SHA1_INITIAL_STATE;
+    for block in message.chunks(64) {
+        let mut words = [0u32; 80];
+        for (index, word) in block.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, new_value) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(new_value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, value) in digest.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn format_sha1(digest: &[u8; 20]) -> String {
+    digest.iter().map(|byte| format!("{byte:02X}")).collect()
+}
+
+// ---------------------------------------------------------------------------
+
+const fn create_crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+
+    let mut index = 0;
+    while index < 256 {
+        let mut value = index as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            value = if value & 1 != 0 { (value >> 1) ^ CRC32_POLYNOMIAL } else { value >> 1 };
+            bit += 1;
+        }
+        table[index] = value;
+        index += 1;
+    }
+
+    table
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_matches_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn sha1_matches_known_digests() {
+        assert_eq!(format_sha1(&sha1(b"")), "DA39A3EE5E6B4B0D3255BFEF95601890AFD80709");
+        assert_eq!(format_sha1(&sha1(b"abc")), "A9993E364706816ABA3E25717850C26C9CD0D89D");
+        assert_eq!(format_sha1(&sha1(&[b'a'; 1000])), "291E9A6C66994949B57BA5E650361E98FC36B1BA");
+    }
+}
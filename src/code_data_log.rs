@@ -0,0 +1,145 @@
+use std::fs;
+
+// Code/Data Logger files, as written by FCEUX and Mesen, hold one flag byte per PRG ROM byte
+// followed by one per CHR ROM byte. Only the PRG ROM half is used here. The PRG flags are:
+//
+//     0x01  executed as code
+//     0x02  read as data
+//     0x0C  CPU window the byte was mapped into ($8000/$A000/$C000/$E000)
+//     0x10  code reached through an indirect jump
+//     0x20  data read through an indirect access
+//     0x40  data played back as DMC (PCM) audio
+//
+// https://fceux.com/web/help/CodeDataLogger.html
+
+pub const CODE: u8 = 0x01;
+pub const DATA: u8 = 0x02;
+pub const INDIRECT_CODE: u8 = 0x10;
+pub const INDIRECT_DATA: u8 = 0x20;
+pub const PCM_AUDIO: u8 = 0x40;
+
+pub struct CodeDataLog {
+    prg_rom_flags: Vec<u8>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl CodeDataLog {
+    pub fn new(prg_rom_size: usize) -> Self {
+        Self {
+            prg_rom_flags: vec![0u8; prg_rom_size],
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_from_file(filename: &str, prg_rom_size: usize) -> Self {
+        let contents = match fs::read(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read code/data log file: {error}"),
+        };
+
+        if contents.len() < prg_rom_size {
+            panic!(
+                "[ERROR] Code/data log file {filename} is {} bytes, but PRG ROM alone is {prg_rom_size} bytes!",
+                contents.len());
+        }
+
+        Self {
+            prg_rom_flags: contents[..prg_rom_size].to_vec(),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn save_to_file(&self, filename: &str, chr_rom_size: usize) {
+        let mut contents = self.prg_rom_flags.clone();
+        contents.resize(self.prg_rom_flags.len() + chr_rom_size, 0);
+
+        if let Err(error) = fs::write(filename, contents) {
+            panic!("[ERROR] Could not write code/data log file {filename}: {error}");
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn flags(&self, prg_rom_offset: usize) -> u8 {
+        self.prg_rom_flags.get(prg_rom_offset).copied().unwrap_or(0)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn mark(&mut self, prg_rom_offset: usize, flags: u8) {
+        if let Some(existing_flags) = self.prg_rom_flags.get_mut(prg_rom_offset) {
+            *existing_flags |= flags;
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn merge(&mut self, other: &CodeDataLog) {
+        for (offset, flags) in other.prg_rom_flags.iter().enumerate() {
+            self.mark(offset, *flags);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn is_code(&self, prg_rom_offset: usize) -> bool {
+        self.flags(prg_rom_offset) & (CODE | INDIRECT_CODE) != 0
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn is_data(&self, prg_rom_offset: usize) -> bool {
+        self.flags(prg_rom_offset) & (DATA | INDIRECT_DATA | PCM_AUDIO) != 0
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn is_pcm_audio(&self, prg_rom_offset: usize) -> bool {
+        self.flags(prg_rom_offset) & PCM_AUDIO != 0
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_flags() {
+        let code_data_log = CodeDataLog {
+            prg_rom_flags: vec![CODE, DATA | 0x0C, INDIRECT_CODE | INDIRECT_DATA, PCM_AUDIO],
+        };
+
+        assert!(code_data_log.is_code(0) && !code_data_log.is_data(0));
+        assert!(!code_data_log.is_code(1) && code_data_log.is_data(1));
+        assert!(code_data_log.is_code(2) && code_data_log.is_data(2));
+        assert!(code_data_log.is_pcm_audio(3));
+    }
+
+    #[test]
+    fn out_of_range_offsets_are_unlogged() {
+        let mut code_data_log = CodeDataLog::new(1);
+        code_data_log.mark(5, CODE);
+
+        assert_eq!(code_data_log.flags(5), 0);
+        assert!(!code_data_log.is_code(5));
+    }
+
+    #[test]
+    fn merge_combines_flags() {
+        let mut code_data_log = CodeDataLog::new(2);
+        code_data_log.mark(0, DATA);
+
+        let mut other = CodeDataLog::new(2);
+        other.mark(0, CODE);
+        other.mark(1, PCM_AUDIO);
+        code_data_log.merge(&other);
+
+        assert_eq!(code_data_log.flags(0), CODE | DATA);
+        assert!(code_data_log.is_pcm_audio(1));
+    }
+}
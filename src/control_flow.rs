@@ -0,0 +1,288 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+// The control flow graph recorded while tracing. Edges are kept per instruction as they're
+// discovered and grouped into basic blocks on demand, since later tracing can split a block
+// that was already built by branching into the middle of it.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EdgeKind {
+    Fallthrough,
+    Branch,
+    Jump,
+    Call,
+}
+
+pub struct BasicBlock {
+    pub instruction_addresses: Vec<usize>,
+    pub successors: Vec<(usize, EdgeKind)>,
+}
+
+pub struct ControlFlowGraph {
+    entry_points: BTreeSet<usize>,
+    instruction_sizes: BTreeMap<usize, usize>,
+    instruction_edges: BTreeMap<usize, Vec<(usize, EdgeKind)>>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl EdgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Branch => "branch",
+            EdgeKind::Jump => "jump",
+            EdgeKind::Call => "call",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl ControlFlowGraph {
+    pub fn new() -> Self {
+        Self {
+            entry_points: BTreeSet::new(),
+            instruction_sizes: BTreeMap::new(),
+            instruction_edges: BTreeMap::new(),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn add_entry_point(&mut self, address: usize) {
+        self.entry_points.insert(address);
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn add_instruction(&mut self, address: usize, bytes_count: usize) {
+        self.instruction_sizes.insert(address, bytes_count);
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn add_edge(&mut self, from_address: usize, to_address: usize, kind: EdgeKind) {
+        let edges = self.instruction_edges.entry(from_address).or_default();
+        if !edges.contains(&(to_address, kind)) {
+            edges.push((to_address, kind));
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn instruction_edges(&self, address: usize) -> &[(usize, EdgeKind)] {
+        self.instruction_edges.get(&address).map_or(&[], Vec::as_slice)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn basic_blocks(&self) -> BTreeMap<usize, BasicBlock> {
+        let leaders = self.find_leaders();
+        let mut basic_blocks = BTreeMap::new();
+
+        for leader in &leaders {
+            if !self.instruction_sizes.contains_key(leader) {
+                continue;
+            }
+
+            let mut instruction_addresses = Vec::new();
+            let mut address = *leader;
+            let successors = loop {
+                instruction_addresses.push(address);
+
+                let edges = self.instruction_edges(address);
+                let next_address = address + self.instruction_sizes[&address];
+                let falls_through = edges.contains(&(next_address, EdgeKind::Fallthrough));
+                let has_other_edges = edges.iter().any(|(_, kind)| *kind != EdgeKind::Fallthrough);
+
+                let is_block_complete = !falls_through
+                    || has_other_edges
+                    || leaders.contains(&next_address)
+                    || !self.instruction_sizes.contains_key(&next_address);
+                if is_block_complete {
+                    break edges.to_vec();
+                }
+
+                address = next_address;
+            };
+
+            basic_blocks.insert(*leader, BasicBlock {
+                instruction_addresses,
+                successors,
+            });
+        }
+
+        basic_blocks
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the start addresses of the blocks belonging to the routine entered at
+    /// `entry_point`: everything reachable without following calls or entering another routine.
+    pub fn routine_blocks(
+        &self,
+        basic_blocks: &BTreeMap<usize, BasicBlock>,
+        entry_point: usize,
+        routine_entry_points: &BTreeSet<usize>,
+    ) -> BTreeSet<usize> {
+        let mut routine_blocks = BTreeSet::new();
+        let mut pending_blocks = vec![entry_point];
+
+        while let Some(block_address) = pending_blocks.pop() {
+            let Some(basic_block) = basic_blocks.get(&block_address) else {
+                continue;
+            };
+            if !routine_blocks.insert(block_address) {
+                continue;
+            }
+
+            for (target_address, kind) in &basic_block.successors {
+                let is_other_routine = *target_address != entry_point && routine_entry_points.contains(target_address);
+                if *kind != EdgeKind::Call && !is_other_routine {
+                    pending_blocks.push(*target_address);
+                }
+            }
+        }
+
+        routine_blocks
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn find_leaders(&self) -> BTreeSet<usize> {
+        let mut leaders = self.entry_points.clone();
+
+        for (from_address, edges) in &self.instruction_edges {
+            let next_address = from_address + self.instruction_sizes.get(from_address).copied().unwrap_or(0);
+            let has_other_edges = edges.iter().any(|(_, kind)| *kind != EdgeKind::Fallthrough);
+
+            for (to_address, kind) in edges {
+                if *kind != EdgeKind::Fallthrough || has_other_edges {
+                    leaders.insert(*to_address);
+                }
+            }
+            if has_other_edges {
+                leaders.insert(next_address);
+            }
+        }
+
+        // Instructions that nothing falls into start a block too, e.g. code after an RTS that's
+        // only reached through a jump table.
+        for address in self.instruction_sizes.keys() {
+            let is_fallen_into = self.instruction_edges.range(..*address).next_back()
+                .is_some_and(|(from_address, edges)| edges.contains(&(*address, EdgeKind::Fallthrough)) && from_address + self.instruction_sizes[from_address] == *address);
+            if !is_fallen_into {
+                leaders.insert(*address);
+            }
+        }
+
+        leaders
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Renders the blocks of one routine as a Graphviz digraph, with each node listing its
+/// instructions.
+pub fn format_routine_dot(
+    routine_name: &str,
+    basic_blocks: &BTreeMap<usize, BasicBlock>,
+    routine_blocks: &BTreeSet<usize>,
+    name_of: &dyn Fn(usize) -> String,
+    instruction_text_of: &dyn Fn(usize) -> String,
+) -> String {
+    let mut dot = format!("digraph \"{}\" {{\n", escape_dot(routine_name));
+    dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+    for block_address in routine_blocks {
+        let basic_block = &basic_blocks[block_address];
+
+        let mut node_label = format!("{}:\\l", escape_dot(&name_of(*block_address)));
+        for instruction_address in &basic_block.instruction_addresses {
+            node_label.push_str(&format!("{:04X}  {}\\l", instruction_address, escape_dot(&instruction_text_of(*instruction_address))));
+        }
+        dot.push_str(&format!("    \"{:04X}\" [label=\"{node_label}\"];\n", block_address));
+
+        for (target_address, kind) in &basic_block.successors {
+            if *kind == EdgeKind::Call {
+                let callee_name = escape_dot(&name_of(*target_address));
+                dot.push_str(&format!("    \"call_{:04X}\" [label=\"{callee_name}\", shape=ellipse];\n", target_address));
+                dot.push_str(&format!("    \"{:04X}\" -> \"call_{:04X}\" [label=\"call\", style=dashed];\n", block_address, target_address));
+            } else if routine_blocks.contains(target_address) {
+                dot.push_str(&format!("    \"{:04X}\" -> \"{:04X}\" [label=\"{}\"];\n", block_address, target_address, kind.name()));
+            } else {
+                // Control passing into another routine, such as a tail call.
+                let target_name = escape_dot(&name_of(*target_address));
+                dot.push_str(&format!("    \"other_{:04X}\" [label=\"{target_name}\", shape=ellipse];\n", target_address));
+                dot.push_str(&format!("    \"{:04X}\" -> \"other_{:04X}\" [label=\"{}\", style=dashed];\n", block_address, target_address, kind.name()));
+            }
+        }
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // $8000: LDX #$00      $8002: INX           $8003: BNE $8002
+    // $8005: JSR $9000     $8008: RTS           $9000: RTS
+    fn create_graph() -> ControlFlowGraph {
+        let mut control_flow_graph = ControlFlowGraph::new();
+        control_flow_graph.add_entry_point(0x8000);
+        for (address, bytes_count) in [(0x8000, 2), (0x8002, 1), (0x8003, 2), (0x8005, 3), (0x8008, 1), (0x9000, 1)] {
+            control_flow_graph.add_instruction(address, bytes_count);
+        }
+        control_flow_graph.add_edge(0x8000, 0x8002, EdgeKind::Fallthrough);
+        control_flow_graph.add_edge(0x8002, 0x8003, EdgeKind::Fallthrough);
+        control_flow_graph.add_edge(0x8003, 0x8002, EdgeKind::Branch);
+        control_flow_graph.add_edge(0x8003, 0x8005, EdgeKind::Fallthrough);
+        control_flow_graph.add_edge(0x8005, 0x9000, EdgeKind::Call);
+        control_flow_graph.add_edge(0x8005, 0x8008, EdgeKind::Fallthrough);
+        control_flow_graph
+    }
+
+    #[test]
+    fn splits_basic_blocks_at_branches_and_targets() {
+        let basic_blocks = create_graph().basic_blocks();
+
+        assert_eq!(basic_blocks.keys().copied().collect::<Vec<_>>(), vec![0x8000, 0x8002, 0x8005, 0x8008, 0x9000]);
+        assert_eq!(basic_blocks[&0x8002].instruction_addresses, vec![0x8002, 0x8003]);
+        assert_eq!(basic_blocks[&0x8000].successors, vec![(0x8002, EdgeKind::Fallthrough)]);
+        assert_eq!(basic_blocks[&0x8005].successors.len(), 2);
+    }
+
+    #[test]
+    fn routine_blocks_do_not_follow_calls() {
+        let control_flow_graph = create_graph();
+        let basic_blocks = control_flow_graph.basic_blocks();
+        let routine_blocks = control_flow_graph.routine_blocks(&basic_blocks, 0x8000, &BTreeSet::from([0x8000, 0x9000]));
+
+        assert_eq!(routine_blocks.into_iter().collect::<Vec<_>>(), vec![0x8000, 0x8002, 0x8005, 0x8008]);
+    }
+
+    #[test]
+    fn formats_dot() {
+        let control_flow_graph = create_graph();
+        let basic_blocks = control_flow_graph.basic_blocks();
+        let routine_blocks = control_flow_graph.routine_blocks(&basic_blocks, 0x8000, &BTreeSet::from([0x8000, 0x9000]));
+        let name_of = |address: usize| format!("L{address:04X}");
+        let instruction_text_of = |_address: usize| String::from("LDA \"x\"");
+
+        let dot = format_routine_dot("RESET", &basic_blocks, &routine_blocks, &name_of, &instruction_text_of);
+        assert!(dot.starts_with("digraph \"RESET\" {"));
+        assert!(dot.contains("\"8002\" -> \"8002\" [label=\"branch\"];"));
+        assert!(dot.contains("\"8005\" -> \"call_9000\""));
+        assert!(dot.contains("LDA \\\"x\\\""));
+    }
+}
@@ -0,0 +1,245 @@
+use std::fs;
+
+use crate::checksums::crc32;
+use crate::header_database::HeaderDatabase;
+use crate::nes_header::{parse_nes_file, NesHeader};
+
+// The `convert` subcommand, for fixing up ROM files rather than disassembling them:
+//
+//     nes2   rewrite an iNES header as NES 2.0, taking what it can't say from the header database
+//     split  write the PRG and CHR ROM out as raw .prg and .chr files
+//     join   put raw PRG and CHR binaries back together behind a header built from options
+
+// ---------------------------------------------------------------------------
+
+pub fn upgrade_to_nes2(input_filename: &str, output_filename: &str) {
+    let contents = read_file(input_filename);
+    match upgrade_contents_to_nes2(&contents, &HeaderDatabase::built_in()) {
+        Ok(nes2_contents) => write_file(output_filename, &nes2_contents),
+        Err(error) => panic!("[ERROR] Could not convert {input_filename}: {error}"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Writes `<prefix>.prg` and, if there's any CHR ROM, `<prefix>.chr`. A trainer is written to
+/// `<prefix>.trainer`.
+pub fn split_rom(input_filename: &str, output_prefix: &str) {
+    let contents = read_file(input_filename);
+    let parts = match split_contents(&contents) {
+        Ok(parts) => parts,
+        Err(error) => panic!("[ERROR] Could not parse {input_filename}: {error}"),
+    };
+
+    for (extension, part_contents) in parts {
+        write_file(&format!("{output_prefix}.{extension}"), part_contents);
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Writes a .nes file from raw binaries, with the ROM sizes in `header` taken from the files.
+pub fn join_rom(prg_filename: &str, chr_filename: Option<&str>, header: &NesHeader, output_filename: &str) {
+    let prg_rom_contents = read_file(prg_filename);
+    let chr_rom_contents = chr_filename.map(read_file).unwrap_or_default();
+
+    match join_contents(&prg_rom_contents, &chr_rom_contents, header) {
+        Ok(contents) => write_file(output_filename, &contents),
+        Err(error) => panic!("[ERROR] Could not create header for {output_filename}: {error}"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the .nes file with its header rewritten as NES 2.0, from `database` if the ROM is in it.
+fn upgrade_contents_to_nes2(contents: &[u8], database: &HeaderDatabase) -> Result<Vec<u8>, String> {
+    let nes_file = parse_nes_file(contents)?;
+
+    let header = match database.find(nes_file.prg_rom_contents, nes_file.chr_rom_contents) {
+        Some(entry) => {
+            println!("Found in header database: {}", entry.title);
+            NesHeader {
+                has_trainer: nes_file.header.has_trainer,
+                ..entry.header.clone()
+            }
+        },
+        None if nes_file.header.is_nes2 => {
+            println!("Not in header database, and already NES 2.0; keeping the header as it is");
+            nes_file.header.clone()
+        },
+        None => {
+            println!("Not in header database; RAM sizes are guessed from the iNES header");
+            NesHeader {
+                is_nes2: true,
+                ..nes_file.header.clone()
+            }
+        },
+    };
+
+    print_header(&header);
+    format_nes_file(&header, nes_file.trainer, nes_file.prg_rom_contents, nes_file.chr_rom_contents)
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the extension and contents of each file the .nes file splits into.
+fn split_contents(contents: &[u8]) -> Result<Vec<(&'static str, &[u8])>, String> {
+    let nes_file = parse_nes_file(contents)?;
+    print_header(&nes_file.header);
+
+    let mut parts = Vec::new();
+    if !nes_file.trainer.is_empty() {
+        parts.push(("trainer", nes_file.trainer));
+    }
+    parts.push(("prg", nes_file.prg_rom_contents));
+    if !nes_file.chr_rom_contents.is_empty() {
+        parts.push(("chr", nes_file.chr_rom_contents));
+    }
+    Ok(parts)
+}
+
+// ---------------------------------------------------------------------------
+
+fn join_contents(prg_rom_contents: &[u8], chr_rom_contents: &[u8], header: &NesHeader) -> Result<Vec<u8>, String> {
+    let header = NesHeader {
+        prg_rom_bytes: prg_rom_contents.len(),
+        chr_rom_bytes: chr_rom_contents.len(),
+        has_trainer: false,
+        ..header.clone()
+    };
+
+    print_header(&header);
+    format_nes_file(&header, &[], prg_rom_contents, chr_rom_contents)
+}
+
+// ---------------------------------------------------------------------------
+
+fn print_header(header: &NesHeader) {
+    println!("Format: {}", if header.is_nes2 { "NES 2.0" } else { "iNES" });
+    println!("Mapper: {}", header.mapper_id);
+    if header.is_nes2 {
+        println!("Submapper: {}", header.submapper_id);
+    }
+    println!("PRG ROM: {} bytes", header.prg_rom_bytes);
+    println!("CHR ROM: {} bytes", header.chr_rom_bytes);
+    println!("Mirroring: {}", header.mirroring.name());
+    println!("Battery: {}", if header.has_battery { "yes" } else { "no" });
+    if header.is_nes2 {
+        println!("PRG RAM: {} bytes, PRG NVRAM: {} bytes", header.prg_ram_bytes, header.prg_nvram_bytes);
+        println!("CHR RAM: {} bytes, CHR NVRAM: {} bytes", header.chr_ram_bytes, header.chr_nvram_bytes);
+    }
+    println!("Timing: {}", header.timing.name());
+}
+
+// ---------------------------------------------------------------------------
+
+fn format_nes_file(header: &NesHeader, trainer: &[u8], prg_rom_contents: &[u8], chr_rom_contents: &[u8]) -> Result<Vec<u8>, String> {
+    let header_bytes = header.to_bytes()?;
+
+    println!("PRG ROM CRC32: {:08X}", crc32(prg_rom_contents));
+    if !chr_rom_contents.is_empty() {
+        println!("CHR ROM CRC32: {:08X}", crc32(chr_rom_contents));
+    }
+    Ok([&header_bytes, trainer, prg_rom_contents, chr_rom_contents].concat())
+}
+
+// ---------------------------------------------------------------------------
+
+fn read_file(filename: &str) -> Vec<u8> {
+    match fs::read(filename) {
+        Ok(contents) => contents,
+        Err(error) => panic!("[ERROR] Could not read {filename}: {error}"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn write_file(filename: &str, contents: &[u8]) {
+    if let Err(error) = fs::write(filename, contents) {
+        panic!("[ERROR] Could not write {filename}: {error}");
+    }
+    println!("Wrote {filename}");
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use crate::nes_header::{Mirroring, Timing};
+
+    use super::*;
+
+    fn ines_header(mapper_id: u16) -> NesHeader {
+        NesHeader {
+            is_nes2: false,
+            mapper_id,
+            submapper_id: 0,
+            prg_rom_bytes: 0x4000,
+            chr_rom_bytes: 0x2000,
+            mirroring: Mirroring::Vertical,
+            has_battery: true,
+            has_trainer: false,
+            prg_ram_bytes: 0,
+            prg_nvram_bytes: 0,
+            chr_ram_bytes: 0,
+            chr_nvram_bytes: 0,
+            timing: Timing::Ntsc,
+        }
+    }
+
+    fn rom_contents() -> (Vec<u8>, Vec<u8>) {
+        let prg_rom_contents = (0..0x4000).map(|index| index as u8).collect();
+        let chr_rom_contents = (0..0x2000).map(|index| (index >> 8) as u8).collect();
+        (prg_rom_contents, chr_rom_contents)
+    }
+
+    #[test]
+    fn splits_and_joins_roms() {
+        let (prg_rom_contents, chr_rom_contents) = rom_contents();
+        let contents = format_nes_file(&ines_header(3), &[], &prg_rom_contents, &chr_rom_contents).unwrap();
+
+        let parts = split_contents(&contents).unwrap();
+        assert_eq!(parts, vec![("prg", prg_rom_contents.as_slice()), ("chr", chr_rom_contents.as_slice())]);
+
+        // The ROM sizes come from the binaries rather than the header passed in.
+        let header = NesHeader { prg_rom_bytes: 0, chr_rom_bytes: 0, ..ines_header(3) };
+        assert_eq!(join_contents(parts[0].1, parts[1].1, &header).unwrap(), contents);
+    }
+
+    #[test]
+    fn splits_trainers_out() {
+        let (prg_rom_contents, _) = rom_contents();
+        let header = NesHeader { has_trainer: true, chr_rom_bytes: 0, ..ines_header(0) };
+        let contents = format_nes_file(&header, &[0x42; 512], &prg_rom_contents, &[]).unwrap();
+
+        let parts = split_contents(&contents).unwrap();
+        assert_eq!(parts.iter().map(|(extension, _)| *extension).collect::<Vec<&str>>(), vec!["trainer", "prg"]);
+        assert_eq!(parts[0].1, &[0x42; 512]);
+    }
+
+    #[test]
+    fn upgrades_headers_to_nes2() {
+        let (prg_rom_contents, chr_rom_contents) = rom_contents();
+        let contents = format_nes_file(&ines_header(1), &[], &prg_rom_contents, &chr_rom_contents).unwrap();
+
+        // Without a database entry, only the format changes, keeping the RAM sizes guessed from iNES.
+        let upgraded = upgrade_contents_to_nes2(&contents, &HeaderDatabase::parse("").unwrap()).unwrap();
+        let nes_file = parse_nes_file(&upgraded).unwrap();
+        assert_eq!(nes_file.header, NesHeader { is_nes2: true, ..parse_nes_file(&contents).unwrap().header });
+        assert_eq!((nes_file.prg_rom_contents, nes_file.chr_rom_contents), (prg_rom_contents.as_slice(), chr_rom_contents.as_slice()));
+
+        // NES 2.0 headers missing from the database are kept as they are.
+        assert_eq!(upgrade_contents_to_nes2(&upgraded, &HeaderDatabase::parse("").unwrap()).unwrap(), upgraded);
+
+        let database = HeaderDatabase::parse(&format!(
+            r#"<game><prgrom size="16384" crc32="{:08X}"/><chrrom size="8192" crc32="{:08X}"/><prgram size="8192"/><pcb mapper="1" submapper="5" mirroring="H" battery="0"/></game>"#,
+            crc32(&prg_rom_contents), crc32(&chr_rom_contents))).unwrap();
+        let nes_file_contents = upgrade_contents_to_nes2(&contents, &database).unwrap();
+        let header = parse_nes_file(&nes_file_contents).unwrap().header;
+        assert!(header.is_nes2);
+        assert_eq!((header.mapper_id, header.submapper_id, header.prg_ram_bytes), (1, 5, 8192));
+        assert_eq!(header.mirroring, Mirroring::Horizontal);
+
+        assert!(upgrade_contents_to_nes2(&contents[..100], &database).is_err());
+    }
+}
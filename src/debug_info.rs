@@ -0,0 +1,296 @@
+use std::{collections::{BTreeMap, HashMap}, fs};
+
+// ld65 debug info files (`--dbgfile`) are line based. Each line is a record type, a tab, and a
+// comma-separated list of `key=value` attributes:
+//
+//     seg	id=0,name="CODE",start=0x008000,size=0x0123,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+//     span	id=0,seg=0,start=0,size=3
+//     line	id=0,file=0,line=12,span=0
+//     sym	id=0,name="reset",addrsize=absolute,scope=0,def=1,val=0x8000,seg=0,type=lab
+//
+// Only the records needed to name addresses and map them back to source lines are used. The
+// format is described at https://cc65.github.io/doc/debugging.html and in cc65's dbginfo.c.
+
+const LINE_TYPE_ASSEMBLER: u32 = 0;
+const LINE_TYPE_EXTERNAL: u32 = 1;
+
+pub struct DebugSymbol {
+    pub name: String,
+    pub address: usize,
+    pub size: usize,
+}
+
+pub struct DebugSegment {
+    pub name: String,
+    pub start: usize,
+    pub size: usize,
+    pub segment_type: String,
+    pub output_offset: Option<usize>,
+}
+
+pub struct SourceLocation {
+    pub filename: String,
+    pub line: usize,
+}
+
+pub struct DebugInfo {
+    pub symbols: Vec<DebugSymbol>,
+    pub segments: Vec<DebugSegment>,
+    pub source_locations: BTreeMap<usize, SourceLocation>,
+}
+
+struct Span {
+    segment_id: usize,
+    start: usize,
+}
+
+struct LineRecord {
+    file_id: usize,
+    line: usize,
+    line_type: u32,
+    span_ids: Vec<usize>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl DebugInfo {
+    pub fn load_from_file(filename: &str) -> Self {
+        let contents = match fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read debug info file: {error}"),
+        };
+
+        match Self::parse(&contents) {
+            Ok(debug_info) => debug_info,
+            Err(error) => panic!("[ERROR] Could not parse debug info file {filename}: {error}"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut filenames: HashMap<usize, String> = HashMap::new();
+        let mut segments: HashMap<usize, DebugSegment> = HashMap::new();
+        let mut spans: HashMap<usize, Span> = HashMap::new();
+        let mut line_records: Vec<LineRecord> = Vec::new();
+        let mut symbols = Vec::new();
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let Some((record_type, attributes)) = line.split_once('\t') else {
+                continue;
+            };
+            let attributes = parse_attributes(attributes);
+            let error_context = |error: String| format!("line {}: {error}", line_index + 1);
+
+            match record_type {
+                "file" => {
+                    let id = get_number(&attributes, "id").map_err(error_context)?;
+                    filenames.insert(id, get_string(&attributes, "name").map_err(error_context)?);
+                },
+                "seg" => {
+                    let id = get_number(&attributes, "id").map_err(error_context)?;
+                    segments.insert(id, DebugSegment {
+                        name: get_string(&attributes, "name").map_err(error_context)?,
+                        start: get_number(&attributes, "start").map_err(error_context)?,
+                        size: get_number(&attributes, "size").map_err(error_context)?,
+                        segment_type: get_string(&attributes, "type").unwrap_or_default(),
+                        output_offset: get_number(&attributes, "ooffs").ok(),
+                    });
+                },
+                "span" => {
+                    let id = get_number(&attributes, "id").map_err(error_context)?;
+                    spans.insert(id, Span {
+                        segment_id: get_number(&attributes, "seg").map_err(error_context)?,
+                        start: get_number(&attributes, "start").map_err(error_context)?,
+                    });
+                },
+                "line" => {
+                    let Some(span_list) = attributes.get("span") else {
+                        continue;
+                    };
+                    line_records.push(LineRecord {
+                        file_id: get_number(&attributes, "file").map_err(error_context)?,
+                        line: get_number(&attributes, "line").map_err(error_context)?,
+                        line_type: get_number(&attributes, "type").unwrap_or(LINE_TYPE_ASSEMBLER as usize) as u32,
+                        span_ids: span_list.split('+').filter_map(parse_number).collect(),
+                    });
+                },
+                "sym" => {
+                    // Equates are skipped since most of them are constants rather than addresses.
+                    if attributes.get("type").map(String::as_str) != Some("lab") {
+                        continue;
+                    }
+                    let Ok(address) = get_number(&attributes, "val") else {
+                        continue;
+                    };
+                    symbols.push(DebugSymbol {
+                        name: get_string(&attributes, "name").map_err(error_context)?,
+                        address,
+                        size: get_number(&attributes, "size").unwrap_or(1),
+                    });
+                },
+                _ => {},
+            }
+        }
+
+        let mut source_locations = BTreeMap::new();
+        let mut source_location_priorities = HashMap::new();
+        for line_record in line_records {
+            let Some(filename) = filenames.get(&line_record.file_id) else {
+                continue;
+            };
+
+            // C source lines beat the assembler lines generated from them, which beat macro
+            // expansion lines.
+            let priority = match line_record.line_type {
+                LINE_TYPE_EXTERNAL => 0,
+                LINE_TYPE_ASSEMBLER => 1,
+                _ => 2,
+            };
+
+            for span_id in &line_record.span_ids {
+                let Some(span) = spans.get(span_id) else {
+                    continue;
+                };
+                let Some(segment) = segments.get(&span.segment_id) else {
+                    continue;
+                };
+
+                let address = segment.start + span.start;
+                if source_location_priorities.get(&address).is_some_and(|existing| *existing <= priority) {
+                    continue;
+                }
+
+                source_location_priorities.insert(address, priority);
+                source_locations.insert(address, SourceLocation {
+                    filename: filename.clone(),
+                    line: line_record.line,
+                });
+            }
+        }
+
+        let mut segments: Vec<DebugSegment> = segments.into_values().collect();
+        segments.sort_by_key(|segment| segment.start);
+
+        Ok(Self {
+            symbols,
+            segments,
+            source_locations,
+        })
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn find_segment(&self, address: usize) -> Option<&DebugSegment> {
+        self.segments.iter().find(|segment| address >= segment.start && address < segment.start + segment.size)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_attributes(text: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+
+    let mut key = String::new();
+    let mut value = String::new();
+    let mut is_reading_value = false;
+    let mut is_in_quotes = false;
+
+    for character in text.chars().chain(std::iter::once(',')) {
+        match character {
+            '"' => is_in_quotes = !is_in_quotes,
+            '=' if !is_in_quotes && !is_reading_value => is_reading_value = true,
+            ',' if !is_in_quotes => {
+                attributes.insert(std::mem::take(&mut key), std::mem::take(&mut value));
+                is_reading_value = false;
+            },
+            _ if is_reading_value => value.push(character),
+            _ => key.push(character),
+        }
+    }
+
+    attributes
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex_digits) => usize::from_str_radix(hex_digits, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn get_number(attributes: &HashMap<String, String>, key: &str) -> Result<usize, String> {
+    let value = attributes.get(key).ok_or_else(|| format!("missing `{key}`"))?;
+    parse_number(value).ok_or_else(|| format!("invalid number `{value}` for `{key}`"))
+}
+
+// ---------------------------------------------------------------------------
+
+fn get_string(attributes: &HashMap<String, String>, key: &str) -> Result<String, String> {
+    attributes.get(key).cloned().ok_or_else(|| format!("missing `{key}`"))
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEBUG_INFO: &str = "\
+version\tmajor=2,minor=0
+file\tid=0,name=\"main.s\",size=100,mtime=0x5A000000,mod=0
+file\tid=1,name=\"macros.inc\",size=10,mtime=0x5A000000,mod=0
+seg\tid=0,name=\"CODE\",start=0x008000,size=0x0010,addrsize=absolute,type=ro,oname=\"game.nes\",ooffs=16
+seg\tid=1,name=\"ZEROPAGE\",start=0x000000,size=0x0002,addrsize=zeropage,type=rw
+span\tid=0,seg=0,start=0,size=1
+span\tid=1,seg=0,start=1,size=3
+line\tid=0,file=0,line=12,span=0
+line\tid=1,file=0,line=13,span=1
+line\tid=2,file=1,line=4,type=2,span=1
+sym\tid=0,name=\"reset\",addrsize=absolute,scope=0,def=0,val=0x8000,seg=0,type=lab
+sym\tid=1,name=\"ptr\",addrsize=zeropage,size=2,scope=0,def=0,val=0x0,seg=1,type=lab
+sym\tid=2,name=\"SPEED\",addrsize=zeropage,scope=0,def=0,val=0x3,type=equ
+";
+
+    #[test]
+    fn parses_labels_but_not_equates() {
+        let debug_info = DebugInfo::parse(DEBUG_INFO).unwrap();
+
+        assert_eq!(debug_info.symbols.len(), 2);
+        assert_eq!(debug_info.symbols[0].name, "reset");
+        assert_eq!(debug_info.symbols[0].address, 0x8000);
+        assert_eq!(debug_info.symbols[1].size, 2);
+    }
+
+    #[test]
+    fn maps_addresses_to_source_lines() {
+        let debug_info = DebugInfo::parse(DEBUG_INFO).unwrap();
+
+        let location = &debug_info.source_locations[&0x8000];
+        assert_eq!((location.filename.as_str(), location.line), ("main.s", 12));
+
+        // The assembler line wins over the macro expansion line for the same span.
+        let location = &debug_info.source_locations[&0x8001];
+        assert_eq!((location.filename.as_str(), location.line), ("main.s", 13));
+    }
+
+    #[test]
+    fn parses_segments() {
+        let debug_info = DebugInfo::parse(DEBUG_INFO).unwrap();
+
+        assert_eq!(debug_info.segments[0].name, "ZEROPAGE");
+        assert_eq!(debug_info.find_segment(0x800F).unwrap().output_offset, Some(16));
+        assert!(debug_info.find_segment(0x8010).is_none());
+    }
+
+    #[test]
+    fn quoted_values_may_contain_commas() {
+        let attributes = parse_attributes("id=0,name=\"a,b.s\",size=1");
+        assert_eq!(attributes["name"], "a,b.s");
+        assert_eq!(attributes["size"], "1");
+    }
+}
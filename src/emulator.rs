@@ -0,0 +1,642 @@
+use std::collections::BTreeSet;
+
+use crate::code_data_log::{self, CodeDataLog};
+use crate::opcodes::{decode_opcode, AddressingMode};
+
+// A headless 6502 core for discovering code that static tracing can't reach, such as jump tables
+// and RTS tricks. Only the CPU is emulated: the PPU, APU and controllers are stubbed just well
+// enough for typical wait loops to terminate, and the run is fully deterministic.
+//
+// Timing follows NTSC: 29781 CPU cycles per frame, with vblank starting at scanline 241.
+
+const CPU_CYCLES_PER_FRAME: u64 = 29781;
+const VBLANK_START_CYCLE: u64 = 27394;
+const OAM_DMA_CYCLES: u64 = 513;
+const INTERRUPT_CYCLES: u64 = 7;
+
+const NMI_VECTOR_ADDRESS: u16 = 0xFFFA;
+const RESET_VECTOR_ADDRESS: u16 = 0xFFFC;
+
+const STACK_PAGE: u16 = 0x0100;
+
+// Code that calls without ever returning would otherwise grow the expected return addresses
+// forever. The stack page only holds 128 of them, so the oldest of these are long overwritten.
+const MAX_EXPECTED_RETURN_ADDRESSES: usize = 256;
+
+const FLAG_CARRY: u8 = 0x01;
+const FLAG_ZERO: u8 = 0x02;
+const FLAG_INTERRUPT_DISABLE: u8 = 0x04;
+const FLAG_DECIMAL: u8 = 0x08;
+const FLAG_BREAK: u8 = 0x10;
+const FLAG_UNUSED: u8 = 0x20;
+const FLAG_OVERFLOW: u8 = 0x40;
+const FLAG_NEGATIVE: u8 = 0x80;
+
+const PPU_STATUS_VBLANK: u8 = 0x80;
+const PPU_STATUS_SPRITE_ZERO_HIT: u8 = 0x40;
+const PPU_CONTROL_NMI_ENABLE: u8 = 0x80;
+
+pub struct EmulationOptions {
+    pub frame_count: usize,
+    /// Fire NMI on every Nth vblank (when the game has NMI enabled).
+    pub nmi_interval: usize,
+}
+
+pub enum StopReason {
+    FramesCompleted,
+    Break(u16),
+    UnknownOpcode(u16, u8),
+}
+
+pub struct EmulationResult {
+    pub frames_completed: usize,
+    pub instructions_executed: u64,
+    pub executed_addresses: BTreeSet<usize>,
+    pub indirect_jump_targets: BTreeSet<usize>,
+    pub code_data_log: CodeDataLog,
+    pub stop_reason: StopReason,
+}
+
+struct Emulator<'a> {
+    prg_rom: &'a [u8],
+    ram: [u8; 0x800],
+    prg_ram: [u8; 0x2000],
+
+    a: u8,
+    x: u8,
+    y: u8,
+    s: u8,
+    p: u8,
+    pc: u16,
+    cycles: u64,
+
+    ppu_control: u8,
+    ppu_status: u8,
+    is_nmi_pending: bool,
+
+    // Return addresses pushed by JSR, used to spot RTS instructions that return somewhere else.
+    expected_return_addresses: Vec<u16>,
+
+    instructions_executed: u64,
+    executed_addresses: BTreeSet<usize>,
+    indirect_jump_targets: BTreeSet<usize>,
+    code_data_log: CodeDataLog,
+}
+
+// ---------------------------------------------------------------------------
+
+impl StopReason {
+    pub fn describe(&self) -> String {
+        match self {
+            StopReason::FramesCompleted => String::from("all frames completed"),
+            StopReason::Break(address) => format!("BRK at ${address:04X}"),
+            StopReason::UnknownOpcode(address, opcode) => format!("unknown opcode ${opcode:02X} at ${address:04X}"),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn emulate(prg_rom: &[u8], options: &EmulationOptions) -> EmulationResult {
+    let mut emulator = Emulator::new(prg_rom);
+    let nmi_interval = options.nmi_interval.max(1);
+
+    let mut frames_completed = 0;
+    let mut stop_reason = StopReason::FramesCompleted;
+
+    for frame in 0..options.frame_count {
+        let frame_start_cycle = emulator.cycles;
+
+        if let Err(reason) = emulator.run_until(frame_start_cycle + VBLANK_START_CYCLE) {
+            stop_reason = reason;
+            break;
+        }
+
+        emulator.ppu_status |= PPU_STATUS_VBLANK;
+        if emulator.ppu_control & PPU_CONTROL_NMI_ENABLE != 0 && frame % nmi_interval == 0 {
+            emulator.is_nmi_pending = true;
+        }
+
+        if let Err(reason) = emulator.run_until(frame_start_cycle + CPU_CYCLES_PER_FRAME) {
+            stop_reason = reason;
+            break;
+        }
+
+        emulator.ppu_status &= !(PPU_STATUS_VBLANK | PPU_STATUS_SPRITE_ZERO_HIT);
+        frames_completed += 1;
+    }
+
+    EmulationResult {
+        frames_completed,
+        instructions_executed: emulator.instructions_executed,
+        executed_addresses: emulator.executed_addresses,
+        indirect_jump_targets: emulator.indirect_jump_targets,
+        code_data_log: emulator.code_data_log,
+        stop_reason,
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl<'a> Emulator<'a> {
+    fn new(prg_rom: &'a [u8]) -> Self {
+        let mut emulator = Self {
+            prg_rom,
+            ram: [0u8; 0x800],
+            prg_ram: [0u8; 0x2000],
+
+            a: 0,
+            x: 0,
+            y: 0,
+            s: 0xFD,
+            p: FLAG_INTERRUPT_DISABLE | FLAG_UNUSED,
+            pc: 0,
+            cycles: 0,
+
+            ppu_control: 0,
+            ppu_status: 0,
+            is_nmi_pending: false,
+
+            expected_return_addresses: Vec::new(),
+
+            instructions_executed: 0,
+            executed_addresses: BTreeSet::new(),
+            indirect_jump_targets: BTreeSet::new(),
+            code_data_log: CodeDataLog::new(prg_rom.len()),
+        };
+
+        emulator.pc = emulator.peek_u16(RESET_VECTOR_ADDRESS);
+        emulator
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn run_until(&mut self, cycle: u64) -> Result<(), StopReason> {
+        while self.cycles < cycle {
+            if self.is_nmi_pending {
+                self.is_nmi_pending = false;
+                self.interrupt(NMI_VECTOR_ADDRESS);
+            }
+
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn interrupt(&mut self, vector_address: u16) {
+        self.push_u16(self.pc);
+        self.push((self.p | FLAG_UNUSED) & !FLAG_BREAK);
+        self.p |= FLAG_INTERRUPT_DISABLE;
+        self.pc = self.peek_u16(vector_address);
+        self.cycles += INTERRUPT_CYCLES;
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn step(&mut self) -> Result<(), StopReason> {
+        let instruction_address = self.pc;
+        let opcode_byte = self.peek(instruction_address);
+
+        let Some(opcode) = decode_opcode(opcode_byte) else {
+            return Err(StopReason::UnknownOpcode(instruction_address, opcode_byte));
+        };
+        if opcode.mnemonic == "BRK" {
+            return Err(StopReason::Break(instruction_address));
+        }
+
+        let bytes_count = opcode.addressing_mode.bytes_count() as u16;
+        for offset in 0..bytes_count {
+            self.log_prg_rom(instruction_address.wrapping_add(offset), code_data_log::CODE);
+        }
+        self.executed_addresses.insert(instruction_address as usize);
+        self.instructions_executed += 1;
+
+        let operand1 = self.peek(instruction_address.wrapping_add(1));
+        let operand_u16 = self.peek_u16(instruction_address.wrapping_add(1));
+        self.pc = instruction_address.wrapping_add(bytes_count);
+        self.cycles += opcode.cycles as u64;
+
+        // Resolve the effective address, charging the page crossing penalty where it applies.
+        let (operand_address, is_indirect) = match opcode.addressing_mode {
+            AddressingMode::Implied | AddressingMode::Accumulator | AddressingMode::Relative => (0, false),
+            AddressingMode::Immediate => (instruction_address.wrapping_add(1), false),
+            AddressingMode::ZeroPage => (operand1 as u16, false),
+            AddressingMode::ZeroPageX => (operand1.wrapping_add(self.x) as u16, false),
+            AddressingMode::ZeroPageY => (operand1.wrapping_add(self.y) as u16, false),
+            AddressingMode::Absolute => (operand_u16, false),
+            AddressingMode::AbsoluteX => (self.index(operand_u16, self.x, opcode.has_page_cross_penalty), false),
+            AddressingMode::AbsoluteY => (self.index(operand_u16, self.y, opcode.has_page_cross_penalty), false),
+            AddressingMode::Indirect => (self.peek_u16_with_page_wrap(operand_u16), true),
+            AddressingMode::IndexedIndirect => (self.peek_u16_with_page_wrap(operand1.wrapping_add(self.x) as u16), true),
+            AddressingMode::IndirectIndexed => {
+                let base_address = self.peek_u16_with_page_wrap(operand1 as u16);
+                (self.index(base_address, self.y, opcode.has_page_cross_penalty), true)
+            },
+        };
+
+        let is_accumulator = opcode.addressing_mode == AddressingMode::Accumulator;
+        let is_immediate = opcode.addressing_mode == AddressingMode::Immediate;
+        let read_operand = |emulator: &mut Self| {
+            if is_immediate {
+                operand1
+            } else {
+                emulator.read(operand_address, is_indirect)
+            }
+        };
+
+        match opcode.mnemonic {
+            "LDA" => { self.a = read_operand(self); self.set_zero_and_negative(self.a); },
+            "LDX" => { self.x = read_operand(self); self.set_zero_and_negative(self.x); },
+            "LDY" => { self.y = read_operand(self); self.set_zero_and_negative(self.y); },
+            "STA" => self.write(operand_address, self.a),
+            "STX" => self.write(operand_address, self.x),
+            "STY" => self.write(operand_address, self.y),
+
+            "ADC" => { let value = read_operand(self); self.add_with_carry(value); },
+            "SBC" => { let value = read_operand(self); self.add_with_carry(!value); },
+            "AND" => { self.a &= read_operand(self); self.set_zero_and_negative(self.a); },
+            "ORA" => { self.a |= read_operand(self); self.set_zero_and_negative(self.a); },
+            "EOR" => { self.a ^= read_operand(self); self.set_zero_and_negative(self.a); },
+            "CMP" => { let value = read_operand(self); self.compare(self.a, value); },
+            "CPX" => { let value = read_operand(self); self.compare(self.x, value); },
+            "CPY" => { let value = read_operand(self); self.compare(self.y, value); },
+            "BIT" => {
+                let value = read_operand(self);
+                self.set_flag(FLAG_ZERO, self.a & value == 0);
+                self.set_flag(FLAG_OVERFLOW, value & 0x40 != 0);
+                self.set_flag(FLAG_NEGATIVE, value & 0x80 != 0);
+            },
+
+            "ASL" | "LSR" | "ROL" | "ROR" => {
+                let value = if is_accumulator { self.a } else { self.read(operand_address, false) };
+                let carry_in = self.p & FLAG_CARRY;
+                let (result, carry_out) = match opcode.mnemonic {
+                    "ASL" => (value << 1, value & 0x80 != 0),
+                    "LSR" => (value >> 1, value & 0x01 != 0),
+                    "ROL" => ((value << 1) | carry_in, value & 0x80 != 0),
+                    _ => ((value >> 1) | (carry_in << 7), value & 0x01 != 0),
+                };
+                self.set_flag(FLAG_CARRY, carry_out);
+                self.set_zero_and_negative(result);
+                if is_accumulator {
+                    self.a = result;
+                } else {
+                    self.write(operand_address, result);
+                }
+            },
+            "INC" | "DEC" => {
+                let value = self.read(operand_address, false);
+                let result = if opcode.mnemonic == "INC" { value.wrapping_add(1) } else { value.wrapping_sub(1) };
+                self.set_zero_and_negative(result);
+                self.write(operand_address, result);
+            },
+            "INX" => { self.x = self.x.wrapping_add(1); self.set_zero_and_negative(self.x); },
+            "INY" => { self.y = self.y.wrapping_add(1); self.set_zero_and_negative(self.y); },
+            "DEX" => { self.x = self.x.wrapping_sub(1); self.set_zero_and_negative(self.x); },
+            "DEY" => { self.y = self.y.wrapping_sub(1); self.set_zero_and_negative(self.y); },
+
+            "BPL" => self.branch(operand1, self.p & FLAG_NEGATIVE == 0),
+            "BMI" => self.branch(operand1, self.p & FLAG_NEGATIVE != 0),
+            "BVC" => self.branch(operand1, self.p & FLAG_OVERFLOW == 0),
+            "BVS" => self.branch(operand1, self.p & FLAG_OVERFLOW != 0),
+            "BCC" => self.branch(operand1, self.p & FLAG_CARRY == 0),
+            "BCS" => self.branch(operand1, self.p & FLAG_CARRY != 0),
+            "BNE" => self.branch(operand1, self.p & FLAG_ZERO == 0),
+            "BEQ" => self.branch(operand1, self.p & FLAG_ZERO != 0),
+
+            "JMP" => {
+                if is_indirect {
+                    self.record_indirect_jump_target(operand_address);
+                }
+                self.pc = operand_address;
+            },
+            "JSR" => {
+                self.push_u16(self.pc.wrapping_sub(1));
+                if self.expected_return_addresses.len() == MAX_EXPECTED_RETURN_ADDRESSES {
+                    self.expected_return_addresses.remove(0);
+                }
+                self.expected_return_addresses.push(self.pc);
+                self.pc = operand_address;
+            },
+            "RTS" => {
+                let return_address = self.pull_u16().wrapping_add(1);
+                match self.expected_return_addresses.iter().rposition(|address| *address == return_address) {
+                    Some(position) => self.expected_return_addresses.truncate(position),
+                    None => self.record_indirect_jump_target(return_address),
+                }
+                self.pc = return_address;
+            },
+            "RTI" => {
+                self.p = (self.pull() | FLAG_UNUSED) & !FLAG_BREAK;
+                self.pc = self.pull_u16();
+            },
+
+            "PHA" => self.push(self.a),
+            "PHP" => self.push(self.p | FLAG_BREAK | FLAG_UNUSED),
+            "PLA" => { self.a = self.pull(); self.set_zero_and_negative(self.a); },
+            "PLP" => self.p = (self.pull() | FLAG_UNUSED) & !FLAG_BREAK,
+
+            "CLC" => self.set_flag(FLAG_CARRY, false),
+            "SEC" => self.set_flag(FLAG_CARRY, true),
+            "CLI" => self.set_flag(FLAG_INTERRUPT_DISABLE, false),
+            "SEI" => self.set_flag(FLAG_INTERRUPT_DISABLE, true),
+            "CLV" => self.set_flag(FLAG_OVERFLOW, false),
+            "CLD" => self.set_flag(FLAG_DECIMAL, false),
+            "SED" => self.set_flag(FLAG_DECIMAL, true),
+
+            "TAX" => { self.x = self.a; self.set_zero_and_negative(self.x); },
+            "TAY" => { self.y = self.a; self.set_zero_and_negative(self.y); },
+            "TXA" => { self.a = self.x; self.set_zero_and_negative(self.a); },
+            "TYA" => { self.a = self.y; self.set_zero_and_negative(self.a); },
+            "TSX" => { self.x = self.s; self.set_zero_and_negative(self.x); },
+            "TXS" => self.s = self.x,
+
+            _nop => {},
+        }
+
+        Ok(())
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn index(&mut self, base_address: u16, index: u8, has_page_cross_penalty: bool) -> u16 {
+        let address = base_address.wrapping_add(index as u16);
+        if has_page_cross_penalty && (address & 0xFF00) != (base_address & 0xFF00) {
+            self.cycles += 1;
+        }
+
+        address
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn branch(&mut self, offset: u8, is_taken: bool) {
+        if !is_taken {
+            return;
+        }
+
+        let target_address = self.pc.wrapping_add(offset as i8 as u16);
+        self.cycles += if (target_address & 0xFF00) != (self.pc & 0xFF00) { 2 } else { 1 };
+        self.pc = target_address;
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn add_with_carry(&mut self, value: u8) {
+        // The NES CPU has no decimal mode, so the D flag is ignored.
+        let sum = self.a as u16 + value as u16 + (self.p & FLAG_CARRY) as u16;
+        let result = sum as u8;
+
+        self.set_flag(FLAG_CARRY, sum > 0xFF);
+        self.set_flag(FLAG_OVERFLOW, (self.a ^ result) & (value ^ result) & 0x80 != 0);
+        self.a = result;
+        self.set_zero_and_negative(result);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn compare(&mut self, register: u8, value: u8) {
+        self.set_flag(FLAG_CARRY, register >= value);
+        self.set_zero_and_negative(register.wrapping_sub(value));
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn set_flag(&mut self, flag: u8, is_set: bool) {
+        if is_set {
+            self.p |= flag;
+        } else {
+            self.p &= !flag;
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn set_zero_and_negative(&mut self, value: u8) {
+        self.set_flag(FLAG_ZERO, value == 0);
+        self.set_flag(FLAG_NEGATIVE, value & 0x80 != 0);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn record_indirect_jump_target(&mut self, address: u16) {
+        self.indirect_jump_targets.insert(address as usize);
+        self.log_prg_rom(address, code_data_log::INDIRECT_CODE);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn push(&mut self, value: u8) {
+        self.ram[(STACK_PAGE | self.s as u16) as usize] = value;
+        self.s = self.s.wrapping_sub(1);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn push_u16(&mut self, value: u16) {
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn pull(&mut self) -> u8 {
+        self.s = self.s.wrapping_add(1);
+        self.ram[(STACK_PAGE | self.s as u16) as usize]
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn pull_u16(&mut self) -> u16 {
+        let low_byte = self.pull() as u16;
+        let high_byte = self.pull() as u16;
+        (high_byte << 8) | low_byte
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn prg_rom_offset(&self, address: u16) -> Option<usize> {
+        if address < 0x8000 || self.prg_rom.is_empty() {
+            None
+        } else {
+            Some((address as usize - 0x8000) % self.prg_rom.len())
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn log_prg_rom(&mut self, address: u16, flags: u8) {
+        if let Some(offset) = self.prg_rom_offset(address) {
+            self.code_data_log.mark(offset, flags);
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Reads memory without side effects, for fetching instructions and vectors.
+    fn peek(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize],
+            0x6000..=0x7FFF => self.prg_ram[(address - 0x6000) as usize],
+            0x8000..=0xFFFF => self.prg_rom_offset(address).map_or(0, |offset| self.prg_rom[offset]),
+            _ => 0,
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn peek_u16(&self, address: u16) -> u16 {
+        (self.peek(address.wrapping_add(1)) as u16) << 8 | self.peek(address) as u16
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Reads a pointer the way the 6502 does, where the high byte wraps around within the page.
+    fn peek_u16_with_page_wrap(&self, address: u16) -> u16 {
+        let high_byte_address = (address & 0xFF00) | (address.wrapping_add(1) & 0x00FF);
+        (self.peek(high_byte_address) as u16) << 8 | self.peek(address) as u16
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn read(&mut self, address: u16, is_indirect: bool) -> u8 {
+        match address {
+            0x2000..=0x3FFF if address & 0x0007 == 0x0002 => {
+                // Alternate the sprite 0 hit flag on every read so that loops waiting for it to
+                // clear and then set both finish.
+                let value = self.ppu_status;
+                self.ppu_status &= !PPU_STATUS_VBLANK;
+                self.ppu_status ^= PPU_STATUS_SPRITE_ZERO_HIT;
+                value
+            },
+            0x8000..=0xFFFF => {
+                let flags = if is_indirect { code_data_log::DATA | code_data_log::INDIRECT_DATA } else { code_data_log::DATA };
+                self.log_prg_rom(address, flags);
+                self.peek(address)
+            },
+            _ => self.peek(address),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram[(address & 0x07FF) as usize] = value,
+            0x2000..=0x3FFF if address & 0x0007 == 0x0000 => {
+                let is_enabling_nmi = self.ppu_control & PPU_CONTROL_NMI_ENABLE == 0 && value & PPU_CONTROL_NMI_ENABLE != 0;
+                if is_enabling_nmi && self.ppu_status & PPU_STATUS_VBLANK != 0 {
+                    self.is_nmi_pending = true;
+                }
+                self.ppu_control = value;
+            },
+            0x4014 => self.cycles += OAM_DMA_CYCLES,
+            0x6000..=0x7FFF => self.prg_ram[(address - 0x6000) as usize] = value,
+            _ => {},
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_prg_rom(code: &[u8], nmi_code: &[u8]) -> Vec<u8> {
+        let mut prg_rom = vec![0xEAu8; 0x4000];
+        prg_rom[..code.len()].copy_from_slice(code);
+        prg_rom[0x100..0x100 + nmi_code.len()].copy_from_slice(nmi_code);
+        prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0x81, 0x00, 0x80, 0x00, 0x81]);
+        prg_rom
+    }
+
+    #[test]
+    fn runs_main_loop_and_nmi() {
+        // RESET: LDA #$80 / STA $2000 / loop: JMP loop
+        // NMI: INC $10 / RTI
+        let prg_rom = create_prg_rom(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0x4C, 0x05, 0x80], &[0xE6, 0x10, 0x40]);
+        let result = emulate(&prg_rom, &EmulationOptions { frame_count: 3, nmi_interval: 1 });
+
+        assert!(matches!(result.stop_reason, StopReason::FramesCompleted));
+        assert_eq!(result.frames_completed, 3);
+        assert!(result.executed_addresses.contains(&0x8100));
+        assert!(result.executed_addresses.contains(&0x8102));
+        assert!(result.code_data_log.is_code(0x06));
+    }
+
+    #[test]
+    fn records_indirect_jumps_and_rts_tricks() {
+        // LDA #$20 / STA $00 / LDA #$80 / STA $01 / JMP ($0000)
+        // $8020: LDA #$80 / PHA / LDA #$2F / PHA / RTS  -> $8030
+        // $8030: JMP $8030
+        let mut code = vec![0xA9, 0x20, 0x85, 0x00, 0xA9, 0x80, 0x85, 0x01, 0x6C, 0x00, 0x00];
+        code.resize(0x20, 0xEA);
+        code.extend_from_slice(&[0xA9, 0x80, 0x48, 0xA9, 0x2F, 0x48, 0x60]);
+        code.resize(0x30, 0xEA);
+        code.extend_from_slice(&[0x4C, 0x30, 0x80]);
+
+        let prg_rom = create_prg_rom(&code, &[0x40]);
+        let result = emulate(&prg_rom, &EmulationOptions { frame_count: 1, nmi_interval: 1 });
+
+        assert_eq!(result.indirect_jump_targets.iter().copied().collect::<Vec<_>>(), vec![0x8020, 0x8030]);
+        assert_eq!(result.code_data_log.flags(0x20) & code_data_log::INDIRECT_CODE, code_data_log::INDIRECT_CODE);
+    }
+
+    #[test]
+    fn caps_expected_return_addresses() {
+        // loop: JSR loop
+        let prg_rom = create_prg_rom(&[0x20, 0x00, 0x80], &[0x40]);
+        let mut emulator = Emulator::new(&prg_rom);
+        assert!(emulator.run_until(CPU_CYCLES_PER_FRAME).is_ok());
+
+        assert!(emulator.instructions_executed > MAX_EXPECTED_RETURN_ADDRESSES as u64);
+        assert_eq!(emulator.expected_return_addresses.len(), MAX_EXPECTED_RETURN_ADDRESSES);
+    }
+
+    #[test]
+    fn waits_for_vblank_and_sprite_zero() {
+        // wait: BIT $2002 / BPL wait / sprite0: BIT $2002 / BVC sprite0 / JMP $800A
+        let prg_rom = create_prg_rom(&[0x2C, 0x02, 0x20, 0x10, 0xFB, 0x2C, 0x02, 0x20, 0x50, 0xFB, 0x4C, 0x0A, 0x80], &[0x40]);
+        let result = emulate(&prg_rom, &EmulationOptions { frame_count: 2, nmi_interval: 1 });
+
+        assert!(result.executed_addresses.contains(&0x800A));
+    }
+
+    #[test]
+    fn stops_on_brk() {
+        let prg_rom = create_prg_rom(&[0xEA, 0x00], &[0x40]);
+        let result = emulate(&prg_rom, &EmulationOptions { frame_count: 1, nmi_interval: 1 });
+
+        assert!(matches!(result.stop_reason, StopReason::Break(0x8001)));
+    }
+
+    #[test]
+    fn is_deterministic() {
+        let prg_rom = create_prg_rom(&[0xA9, 0x80, 0x8D, 0x00, 0x20, 0xE6, 0x20, 0x4C, 0x05, 0x80], &[0xE6, 0x10, 0x40]);
+        let first = emulate(&prg_rom, &EmulationOptions { frame_count: 5, nmi_interval: 2 });
+        let second = emulate(&prg_rom, &EmulationOptions { frame_count: 5, nmi_interval: 2 });
+
+        assert_eq!(first.instructions_executed, second.instructions_executed);
+        assert_eq!(first.executed_addresses, second.executed_addresses);
+    }
+
+    #[test]
+    fn adc_sets_overflow_and_carry() {
+        let prg_rom = create_prg_rom(&[], &[]);
+        let mut emulator = Emulator::new(&prg_rom);
+        emulator.a = 0x7F;
+        emulator.add_with_carry(0x01);
+        assert_eq!(emulator.a, 0x80);
+        assert!(emulator.p & FLAG_OVERFLOW != 0);
+        assert!(emulator.p & FLAG_CARRY == 0);
+
+        emulator.add_with_carry(0x80);
+        assert_eq!(emulator.a, 0x00);
+        assert!(emulator.p & FLAG_CARRY != 0);
+        assert!(emulator.p & FLAG_ZERO != 0);
+    }
+}
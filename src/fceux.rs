@@ -0,0 +1,159 @@
+use std::fs;
+
+use crate::symbols::parse_hex_address;
+
+// FCEUX name lists (.nl) have one entry per line:
+//
+//     $C000#Name#Comment
+//
+// An address may be followed by `/count` (hex) to name an array. Comment lines starting with `\`
+// continue the comment of the previous entry. FCEUX looks for `<rom>.<bank>.nl` per 16 KiB PRG
+// bank and `<rom>.ram.nl` for everything below $8000.
+
+pub struct FceuxName {
+    pub address: usize,
+    pub size: usize,
+    pub name: Option<String>,
+    pub comment: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn load_nl_file(filename: &str) -> Vec<FceuxName> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(error) => panic!("[ERROR] Could not read FCEUX name list file: {error}"),
+    };
+
+    match parse_nl(&contents) {
+        Ok(names) => names,
+        Err(error) => panic!("[ERROR] Could not parse FCEUX name list file {filename}: {error}"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn parse_nl(contents: &str) -> Result<Vec<FceuxName>, String> {
+    let mut names: Vec<FceuxName> = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(continued_comment) = line.strip_prefix('\\') {
+            let Some(previous_name) = names.last_mut() else {
+                return Err(format!("line {}: comment continuation without an entry", line_index + 1));
+            };
+            match &mut previous_name.comment {
+                Some(comment) => {
+                    comment.push('\n');
+                    comment.push_str(continued_comment);
+                },
+                None => previous_name.comment = Some(continued_comment.to_string()),
+            }
+            continue;
+        }
+
+        let mut fields = line.splitn(3, '#');
+        let address_field = fields.next().unwrap_or_default();
+        let name_field = fields.next().unwrap_or_default();
+        let comment_field = fields.next().unwrap_or_default().trim_end_matches('#');
+
+        let (address_field, size_field) = address_field.split_once('/').unwrap_or((address_field, "1"));
+        let (Some(address), Some(size)) = (parse_hex_address(address_field), parse_hex_address(size_field)) else {
+            return Err(format!("line {}: invalid address `{address_field}`", line_index + 1));
+        };
+
+        names.push(FceuxName {
+            address,
+            size: size.max(1),
+            name: if name_field.is_empty() { None } else { Some(name_field.to_string()) },
+            comment: if comment_field.is_empty() { None } else { Some(comment_field.to_string()) },
+        });
+    }
+
+    Ok(names)
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the PRG bank a `<rom>.<bank>.nl` file names, or None for `<rom>.ram.nl` and other files.
+pub fn name_list_bank(filename: &str) -> Option<usize> {
+    let (_, bank) = filename.strip_suffix(".nl")?.rsplit_once('.')?;
+    usize::from_str_radix(bank, 16).ok()
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn format_nl(names: &[FceuxName]) -> String {
+    let mut contents = String::new();
+
+    for name in names {
+        let address = if name.size > 1 {
+            format!("${:04X}/{:X}", name.address, name.size)
+        } else {
+            format!("${:04X}", name.address)
+        };
+
+        let mut comment_lines = name.comment.as_deref().unwrap_or_default().lines();
+        let first_comment_line = comment_lines.next().unwrap_or_default();
+        contents.push_str(&format!("{address}#{}#{first_comment_line}\n", name.name.as_deref().unwrap_or_default()));
+
+        for comment_line in comment_lines {
+            contents.push_str(&format!("\\{comment_line}\n"));
+        }
+    }
+
+    contents
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_names_comments_and_arrays() {
+        let names = parse_nl("$C000#Reset#Entry point\n\\waits for vblank\n$0300/10#buffer#\n$C010##just a comment\n").unwrap();
+
+        assert_eq!(names.len(), 3);
+        assert_eq!(names[0].address, 0xC000);
+        assert_eq!(names[0].name.as_deref(), Some("Reset"));
+        assert_eq!(names[0].comment.as_deref(), Some("Entry point\nwaits for vblank"));
+        assert_eq!((names[1].address, names[1].size), (0x0300, 0x10));
+        assert!(names[1].comment.is_none());
+        assert!(names[2].name.is_none());
+    }
+
+    #[test]
+    fn round_trips() {
+        let names = vec![
+            FceuxName { address: 0x8000, size: 1, name: Some(String::from("RESET")), comment: None },
+            FceuxName { address: 0x0400, size: 0x100, name: Some(String::from("oam")), comment: Some(String::from("a\nb")) },
+        ];
+
+        let contents = format_nl(&names);
+        assert_eq!(contents, "$8000#RESET#\n$0400/100#oam#a\n\\b\n");
+
+        let parsed = parse_nl(&contents).unwrap();
+        assert_eq!(parsed[1].size, 0x100);
+        assert_eq!(parsed[1].comment.as_deref(), Some("a\nb"));
+    }
+
+    #[test]
+    fn reads_the_bank_from_the_filename() {
+        assert_eq!(name_list_bank("game.nes.0.nl"), Some(0));
+        assert_eq!(name_list_bank("dir.v2/game.nes.1F.nl"), Some(0x1F));
+        assert_eq!(name_list_bank("game.nes.ram.nl"), None);
+        assert_eq!(name_list_bank("names.nl"), None);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(parse_nl("\\orphan comment\n").is_err());
+        assert!(parse_nl("$zz#name#\n").is_err());
+    }
+}
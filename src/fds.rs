@@ -0,0 +1,290 @@
+// Famicom Disk System images hold one or more 65500 byte disk sides, optionally behind a 16 byte
+// fwNES header (`FDS\x1A`, side count, zero padding). Each side is a sequence of blocks:
+//
+//     1  disk info      56 bytes  `*NINTENDO-HVC*`, game name, side and disk numbers, boot file id
+//     2  file amount     2 bytes  number of files the BIOS knows about
+//     3  file header    16 bytes  file number, id, name, load address, size, kind
+//     4  file data      1 + size bytes
+//
+// with a 3/4 block pair per file. Files past the declared amount are sometimes present and are
+// read too, since games can load them themselves. https://www.nesdev.org/wiki/FDS_disk_format
+
+pub const FWNES_HEADER_BYTES: usize = 16;
+pub const DISK_SIDE_BYTES: usize = 65500;
+
+const DISK_INFO_BLOCK_BYTES: usize = 56;
+const FILE_AMOUNT_BLOCK_BYTES: usize = 2;
+const FILE_HEADER_BLOCK_BYTES: usize = 16;
+const DISK_VERIFICATION: &[u8] = b"*NINTENDO-HVC*";
+
+/// The BIOS routines games call, along with how many bytes of pointers follow each call.
+pub const BIOS_ROUTINES: &[(usize, &str, usize)] = &[
+    (0xE149, "Delay132", 0),
+    (0xE153, "Delayms", 0),
+    (0xE161, "DisPFObj", 0),
+    (0xE16B, "EnPFObj", 0),
+    (0xE170, "DisObj", 0),
+    (0xE178, "EnObj", 0),
+    (0xE17E, "DisPF", 0),
+    (0xE185, "EnPF", 0),
+    (0xE1B2, "VINTWait", 0),
+    (0xE1F8, "LoadFiles", 4),
+    (0xE237, "AppendFile", 4),
+    (0xE239, "WriteFile", 4),
+    (0xE2B7, "CheckFileCount", 2),
+    (0xE2BB, "AdjustFileCount", 2),
+    (0xE301, "SetFileCount1", 2),
+    (0xE305, "SetFileCount", 2),
+    (0xE32A, "GetDiskInfo", 2),
+    (0xE7BB, "VRAMStructWrite", 0),
+    (0xE844, "FetchDirectPtr", 0),
+    (0xE86A, "WriteVRAMBuffer", 0),
+    (0xE8B3, "ReadVRAMBuffer", 0),
+    (0xE8D2, "PrepareVRAMString", 0),
+    (0xE8E1, "PrepareVRAMStrings", 0),
+    (0xE94F, "GetVRAMBufferByte", 0),
+    (0xE97D, "Pixel2NamConv", 0),
+    (0xE997, "Nam2PixelConv", 0),
+    (0xE9B1, "Random", 0),
+    (0xE9C8, "SpriteDMA", 0),
+    (0xE9D3, "CounterLogic", 0),
+    (0xE9EB, "ReadPads", 0),
+    (0xEA1A, "ReadDownPads", 0),
+    (0xEA1F, "ReadOrDownPads", 0),
+    (0xEA36, "ReadDownVerifyPads", 0),
+    (0xEA4C, "ReadOrDownVerifyPads", 0),
+    (0xEA68, "ReadDownExpPads", 0),
+    (0xEA84, "VRAMFill", 0),
+    (0xEAD2, "MemFill", 0),
+    (0xEAEA, "SetScroll", 0),
+    (0xEAFD, "JumpEngine", 0),
+    (0xEB13, "ReadKeyboard", 0),
+    (0xEBAF, "LoadTileset", 0),
+];
+
+pub const BIOS_JUMP_ENGINE: usize = 0xEAFD;
+
+/// The disk drive, timer IRQ and sound registers, along with how many bytes each covers.
+pub const REGISTERS: &[(usize, &str, usize)] = &[
+    (0x4020, "FdsIrqReloadLow_4020", 1),
+    (0x4021, "FdsIrqReloadHigh_4021", 1),
+    (0x4022, "FdsIrqControl_4022", 1),
+    (0x4023, "FdsMasterIo_4023", 1),
+    (0x4024, "FdsWriteData_4024", 1),
+    (0x4025, "FdsControl_4025", 1),
+    (0x4026, "FdsExtOutput_4026", 1),
+    (0x4030, "FdsDiskStatus_4030", 1),
+    (0x4031, "FdsReadData_4031", 1),
+    (0x4032, "FdsDriveStatus_4032", 1),
+    (0x4033, "FdsExtInput_4033", 1),
+    (0x4040, "FdsWaveTable_4040", 64),
+    (0x4080, "FdsVolumeEnvelope_4080", 1),
+    (0x4082, "FdsFreqLow_4082", 1),
+    (0x4083, "FdsFreqHigh_4083", 1),
+    (0x4084, "FdsModEnvelope_4084", 1),
+    (0x4085, "FdsModCounter_4085", 1),
+    (0x4086, "FdsModFreqLow_4086", 1),
+    (0x4087, "FdsModFreqHigh_4087", 1),
+    (0x4088, "FdsModTable_4088", 1),
+    (0x4089, "FdsWaveWrite_4089", 1),
+    (0x408A, "FdsEnvelopeSpeed_408A", 1),
+    (0x4090, "FdsVolumeGain_4090", 1),
+    (0x4092, "FdsModGain_4092", 1),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DiskFileKind {
+    Program,
+    Character,
+    Nametable,
+    Unknown(u8),
+}
+
+pub struct DiskFile {
+    pub number: u8,
+    pub id: u8,
+    pub name: String,
+    pub load_address: usize,
+    pub kind: DiskFileKind,
+    pub contents: Vec<u8>,
+}
+
+pub struct DiskSide {
+    pub game_name: String,
+    pub side_number: u8,
+    pub disk_number: u8,
+    pub boot_file_id: u8,
+    pub declared_file_count: usize,
+    pub files: Vec<DiskFile>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl DiskFileKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DiskFileKind::Program => "PRG",
+            DiskFileKind::Character => "CHR",
+            DiskFileKind::Nametable => "NT",
+            DiskFileKind::Unknown(_) => "unknown",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn is_fds_image(contents: &[u8]) -> bool {
+    contents.starts_with(b"FDS\x1A") || contents.get(1..1 + DISK_VERIFICATION.len()) == Some(DISK_VERIFICATION)
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn parse_fds(contents: &[u8]) -> Result<Vec<DiskSide>, String> {
+    let sides_contents = if contents.starts_with(b"FDS\x1A") { &contents[FWNES_HEADER_BYTES.min(contents.len())..] } else { contents };
+
+    let mut sides = Vec::new();
+    for (side_index, side_contents) in sides_contents.chunks(DISK_SIDE_BYTES).enumerate() {
+        // Some dumps pad the image out with a partial side of zeros.
+        if side_contents.iter().all(|byte| *byte == 0) {
+            continue;
+        }
+
+        sides.push(parse_disk_side(side_contents).map_err(|error| format!("side {}: {error}", side_index + 1))?);
+    }
+
+    if sides.is_empty() {
+        return Err(String::from("no disk sides found"));
+    }
+
+    Ok(sides)
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_disk_side(contents: &[u8]) -> Result<DiskSide, String> {
+    if contents.len() < DISK_INFO_BLOCK_BYTES + FILE_AMOUNT_BLOCK_BYTES || contents[0] != 1 {
+        return Err(String::from("missing disk info block"));
+    }
+    if &contents[1..1 + DISK_VERIFICATION.len()] != DISK_VERIFICATION {
+        return Err(String::from("disk info block doesn't start with *NINTENDO-HVC*"));
+    }
+
+    let game_name = String::from_utf8_lossy(&contents[16..19]).to_string();
+    let side_number = contents[21];
+    let disk_number = contents[22];
+    let boot_file_id = contents[25];
+
+    let mut offset = DISK_INFO_BLOCK_BYTES;
+    if contents[offset] != 2 {
+        return Err(String::from("missing file amount block"));
+    }
+    let declared_file_count = contents[offset + 1] as usize;
+    offset += FILE_AMOUNT_BLOCK_BYTES;
+
+    let mut files = Vec::new();
+    while contents.get(offset) == Some(&3) && offset + FILE_HEADER_BLOCK_BYTES < contents.len() {
+        let header = &contents[offset..offset + FILE_HEADER_BLOCK_BYTES];
+        let name = String::from_utf8_lossy(&header[3..11]).trim_end_matches(['\0', ' ']).to_string();
+        let load_address = (header[12] as usize) << 8 | header[11] as usize;
+        let size = (header[14] as usize) << 8 | header[13] as usize;
+        let kind = match header[15] {
+            0 => DiskFileKind::Program,
+            1 => DiskFileKind::Character,
+            2 => DiskFileKind::Nametable,
+            kind => DiskFileKind::Unknown(kind),
+        };
+        offset += FILE_HEADER_BLOCK_BYTES;
+
+        if contents.get(offset) != Some(&4) {
+            return Err(format!("file {name} has no data block"));
+        }
+        let Some(file_contents) = contents.get(offset + 1..offset + 1 + size) else {
+            return Err(format!("file {name} runs off the end of the disk side"));
+        };
+        offset += 1 + size;
+
+        files.push(DiskFile {
+            number: header[1],
+            id: header[2],
+            name,
+            load_address,
+            kind,
+            contents: file_contents.to_vec(),
+        });
+    }
+
+    Ok(DiskSide {
+        game_name,
+        side_number,
+        disk_number,
+        boot_file_id,
+        declared_file_count,
+        files,
+    })
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_disk_side(files: &[(&str, u8, usize, &[u8])]) -> Vec<u8> {
+        let mut contents = vec![1];
+        contents.extend_from_slice(DISK_VERIFICATION);
+        contents.resize(DISK_INFO_BLOCK_BYTES, 0);
+        contents[16..19].copy_from_slice(b"TST");
+        contents[25] = 1;
+        contents.extend_from_slice(&[2, files.len() as u8]);
+
+        for (file_number, (name, kind, load_address, file_contents)) in files.iter().enumerate() {
+            let mut header = vec![3, file_number as u8, file_number as u8];
+            header.extend_from_slice(format!("{name:<8}").as_bytes());
+            header.extend_from_slice(&[*load_address as u8, (*load_address >> 8) as u8]);
+            header.extend_from_slice(&[file_contents.len() as u8, (file_contents.len() >> 8) as u8]);
+            header.push(*kind);
+            contents.extend_from_slice(&header);
+            contents.push(4);
+            contents.extend_from_slice(file_contents);
+        }
+
+        contents.resize(DISK_SIDE_BYTES, 0);
+        contents
+    }
+
+    #[test]
+    fn parses_files_with_and_without_header() {
+        let side = create_disk_side(&[("KYODAKU-", 2, 0x2800, &[0x24; 4]), ("MAIN", 0, 0x6000, &[0xA9, 0x00, 0x60])]);
+
+        let mut contents = b"FDS\x1A\x01".to_vec();
+        contents.resize(FWNES_HEADER_BYTES, 0);
+        contents.extend_from_slice(&side);
+
+        for contents in [&contents, &side] {
+            assert!(is_fds_image(contents));
+
+            let sides = parse_fds(contents).unwrap();
+            assert_eq!(sides.len(), 1);
+            assert_eq!(sides[0].game_name, "TST");
+            assert_eq!(sides[0].declared_file_count, 2);
+            assert_eq!(sides[0].files[0].kind, DiskFileKind::Nametable);
+            assert_eq!(sides[0].files[1].name, "MAIN");
+            assert_eq!(sides[0].files[1].load_address, 0x6000);
+            assert_eq!(sides[0].files[1].contents, vec![0xA9, 0x00, 0x60]);
+        }
+    }
+
+    #[test]
+    fn rejects_missing_disk_info() {
+        assert!(!is_fds_image(&[0x4E, 0x45, 0x53, 0x1A]));
+        assert!(parse_fds(&[0x02; 100]).is_err());
+    }
+
+    #[test]
+    fn reports_truncated_files() {
+        let mut side = create_disk_side(&[("MAIN", 0, 0x6000, &[0xEA; 16])]);
+        side.truncate(DISK_INFO_BLOCK_BYTES + FILE_AMOUNT_BLOCK_BYTES + FILE_HEADER_BLOCK_BYTES + 8);
+
+        assert!(parse_fds(&side).is_err_and(|error| error.contains("runs off the end")));
+    }
+}
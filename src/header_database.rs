@@ -0,0 +1,280 @@
+use std::fs;
+
+use crate::checksums::{crc32, format_sha1, sha1};
+use crate::nes_header::{Mirroring, NesHeader, Timing};
+
+// Known-good headers in the NES 2.0 XML database format (https://forums.nesdev.org/viewtopic.php?t=19940),
+// one `<game>` element per ROM, each preceded by a comment with its title:
+//
+//     <!-- Super Mario Bros. (World).nes -->
+//     <game>
+//       <prgrom size="32768" crc32="..." sha1="..."/>
+//       <chrrom size="8192" crc32="..." sha1="..."/>
+//       <rom size="40960" crc32="3337EC46" sha1="..."/>
+//       <pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+//       <console type="0" region="0"/>
+//     </game>
+//
+// Checksums cover the raw ROM contents without the header, with `rom` being PRG followed by CHR.
+// Only the elements describing the header are read; any others are skipped. A small database is
+// built in, and the full one can be loaded from a file.
+
+const BUILT_IN_DATABASE: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<nes20db>
+  <!-- Super Mario Bros. (World).nes -->
+  <game>
+    <prgrom size="32768"/>
+    <chrrom size="8192"/>
+    <rom size="40960" crc32="3337EC46"/>
+    <pcb mapper="0" submapper="0" mirroring="V" battery="0"/>
+    <console type="0" region="0"/>
+  </game>
+</nes20db>
+"#;
+
+pub struct DatabaseEntry {
+    pub title: String,
+    pub rom_crc32: Option<u32>,
+    pub prg_crc32: Option<u32>,
+    pub chr_crc32: Option<u32>,
+    pub rom_sha1: Option<String>,
+    pub prg_sha1: Option<String>,
+    pub chr_sha1: Option<String>,
+    pub header: NesHeader,
+}
+
+pub struct HeaderDatabase {
+    entries: Vec<DatabaseEntry>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl HeaderDatabase {
+    pub fn built_in() -> Self {
+        match Self::parse(BUILT_IN_DATABASE) {
+            Ok(database) => database,
+            Err(error) => panic!("[ERROR] Could not parse built-in header database: {error}"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_from_file(filename: &str) -> Self {
+        let contents = match fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read header database file: {error}"),
+        };
+
+        match Self::parse(&contents) {
+            Ok(database) => database,
+            Err(error) => panic!("[ERROR] Could not parse header database file {filename}: {error}"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+
+        let mut remaining = contents;
+        while let Some(game_start) = remaining.find("<game>") {
+            let Some(game_length) = remaining[game_start..].find("</game>") else {
+                return Err(format!("game {} isn't closed", entries.len() + 1));
+            };
+            let game = &remaining[game_start..game_start + game_length];
+
+            // The title is the last comment before the game.
+            let title = remaining[..game_start].rfind("<!--")
+                .and_then(|comment_start| {
+                    let comment = &remaining[comment_start + 4..game_start];
+                    comment.find("-->").map(|comment_end| comment[..comment_end].trim().trim_end_matches(".nes").to_string())
+                })
+                .unwrap_or_default();
+
+            entries.push(parse_game(game, title).map_err(|error| format!("game {}: {error}", entries.len() + 1))?);
+            remaining = &remaining[game_start + game_length..];
+        }
+
+        Ok(Self { entries })
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Finds the entry for the given ROM contents, by either checksum of the whole ROM or else of
+    /// its PRG and CHR.
+    pub fn find(&self, prg_rom_contents: &[u8], chr_rom_contents: &[u8]) -> Option<&DatabaseEntry> {
+        // The whole database gets scanned, so each checksum is only worked out once.
+        let rom_checksums = Checksums::of(&[prg_rom_contents, chr_rom_contents].concat());
+        let prg_checksums = Checksums::of(prg_rom_contents);
+        let chr_checksums = Checksums::of(chr_rom_contents);
+
+        self.entries.iter().find(|entry| rom_checksums.matches(entry.rom_crc32, &entry.rom_sha1))
+            .or_else(|| self.entries.iter().find(|entry| {
+                prg_checksums.matches(entry.prg_crc32, &entry.prg_sha1)
+                    && (entry.header.chr_rom_bytes == 0 || chr_checksums.matches(entry.chr_crc32, &entry.chr_sha1))
+            }))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+struct Checksums {
+    crc32: u32,
+    sha1: String,
+}
+
+// ---------------------------------------------------------------------------
+
+impl Checksums {
+    fn of(contents: &[u8]) -> Self {
+        Self {
+            crc32: crc32(contents),
+            sha1: format_sha1(&sha1(contents)),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn matches(&self, entry_crc32: Option<u32>, entry_sha1: &Option<String>) -> bool {
+        entry_crc32 == Some(self.crc32) || entry_sha1.as_ref().is_some_and(|entry_sha1| *entry_sha1 == self.sha1)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_game(game: &str, title: String) -> Result<DatabaseEntry, String> {
+    let size_of = |element: &str| -> Result<usize, String> {
+        attribute(game, element, "size").map_or(Ok(0), |size| size.parse().map_err(|_| format!("invalid {element} size `{size}`")))
+    };
+    let crc32_of = |element: &str| -> Result<Option<u32>, String> {
+        attribute(game, element, "crc32")
+            .map(|crc32| u32::from_str_radix(crc32, 16).map_err(|_| format!("invalid {element} crc32 `{crc32}`")))
+            .transpose()
+    };
+    let sha1_of = |element: &str| attribute(game, element, "sha1").map(str::to_uppercase);
+    let number_of = |element: &str, name: &str| -> Result<u16, String> {
+        attribute(game, element, name).map_or(Ok(0), |value| value.parse().map_err(|_| format!("invalid {element} {name} `{value}`")))
+    };
+
+    let mirroring = match attribute(game, "pcb", "mirroring") {
+        Some("H") | None => Mirroring::Horizontal,
+        Some("V") => Mirroring::Vertical,
+        Some("4") => Mirroring::FourScreen,
+        Some(mirroring) => return Err(format!("unknown mirroring `{mirroring}`")),
+    };
+    let timing = match number_of("console", "region")? {
+        0 => Timing::Ntsc,
+        1 => Timing::Pal,
+        2 => Timing::MultipleRegion,
+        3 => Timing::Dendy,
+        region => return Err(format!("unknown region {region}")),
+    };
+
+    Ok(DatabaseEntry {
+        title,
+        rom_crc32: crc32_of("rom")?,
+        prg_crc32: crc32_of("prgrom")?,
+        chr_crc32: crc32_of("chrrom")?,
+        rom_sha1: sha1_of("rom"),
+        prg_sha1: sha1_of("prgrom"),
+        chr_sha1: sha1_of("chrrom"),
+        header: NesHeader {
+            is_nes2: true,
+            mapper_id: number_of("pcb", "mapper")?,
+            submapper_id: number_of("pcb", "submapper")? as u8,
+            prg_rom_bytes: size_of("prgrom")?,
+            chr_rom_bytes: size_of("chrrom")?,
+            mirroring,
+            has_battery: number_of("pcb", "battery")? != 0,
+            has_trainer: size_of("trainer")? != 0,
+            prg_ram_bytes: size_of("prgram")?,
+            prg_nvram_bytes: size_of("prgnvram")?,
+            chr_ram_bytes: size_of("chrram")?,
+            chr_nvram_bytes: size_of("chrnvram")?,
+            timing,
+        },
+    })
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the value of `name` in the first `<element .../>` tag inside `game`.
+fn attribute<'a>(game: &'a str, element: &str, name: &str) -> Option<&'a str> {
+    let tag_start = game.find(&format!("<{element} "))?;
+    let tag = &game[tag_start..tag_start + game[tag_start..].find('>')?];
+
+    let value_start = tag.find(&format!(" {name}=\""))? + name.len() + 3;
+    let value_length = tag[value_start..].find('"')?;
+    Some(&tag[value_start..value_start + value_length])
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DATABASE: &str = r#"<nes20db>
+  <!-- First Game (USA).nes -->
+  <game>
+    <prgrom size="16384" crc32="D202612B"/>
+    <chrrom size="8192" crc32="BA6BE4A1"/>
+    <prgram size="8192"/>
+    <pcb mapper="1" submapper="5" mirroring="V" battery="1"/>
+    <console type="0" region="1"/>
+  </game>
+  <!-- Second Game (Japan).nes -->
+  <game>
+    <prgrom size="32768"/>
+    <rom size="32768" crc32="12345678"/>
+    <chrram size="8192"/>
+    <pcb mapper="2" mirroring="H"/>
+  </game>
+</nes20db>"#;
+
+    #[test]
+    fn parses_games() {
+        let database = HeaderDatabase::parse(DATABASE).unwrap();
+        let entries = &database.entries;
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].title, "First Game (USA)");
+        assert_eq!(entries[0].prg_crc32, Some(0xD202612B));
+        assert_eq!((entries[0].header.mapper_id, entries[0].header.submapper_id), (1, 5));
+        assert_eq!(entries[0].header.mirroring, Mirroring::Vertical);
+        assert!(entries[0].header.has_battery);
+        assert_eq!(entries[0].header.prg_ram_bytes, 8192);
+        assert_eq!(entries[0].header.timing, Timing::Pal);
+
+        assert_eq!(entries[1].title, "Second Game (Japan)");
+        assert_eq!(entries[1].rom_crc32, Some(0x12345678));
+        assert_eq!(entries[1].header.chr_ram_bytes, 8192);
+        assert!(!entries[1].header.has_battery);
+    }
+
+    #[test]
+    fn finds_games_by_checksum() {
+        let database = HeaderDatabase::parse(&DATABASE.replace("D202612B", &format!("{:08X}", crc32(&[0xEA; 16384])))
+            .replace("BA6BE4A1", &format!("{:08X}", crc32(&[0x55; 8192])))).unwrap();
+
+        assert_eq!(database.find(&[0xEA; 16384], &[0x55; 8192]).map(|entry| entry.title.as_str()), Some("First Game (USA)"));
+        assert!(database.find(&[0xEA; 16384], &[0xAA; 8192]).is_none());
+
+        let database = HeaderDatabase::parse(&DATABASE.replace(r#"crc32="12345678""#, &format!(r#"sha1="{}""#, format_sha1(&sha1(&[0x60; 32768])).to_lowercase()))).unwrap();
+        assert_eq!(database.find(&[0x60; 32768], &[]).map(|entry| entry.title.as_str()), Some("Second Game (Japan)"));
+        assert!(!HeaderDatabase::built_in().entries.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_values() {
+        assert!(HeaderDatabase::parse(r#"<game><pcb mapper="x"/></game>"#).is_err());
+        assert!(HeaderDatabase::parse(r#"<game><pcb mirroring="Q"/></game>"#).is_err());
+        assert!(HeaderDatabase::parse("<game>").is_err());
+    }
+}
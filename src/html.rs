@@ -0,0 +1,448 @@
+// The static HTML viewer: an index page with the header, vectors, labels and the memory outside
+// PRG, and a page per PRG bank, built from a `Listing` of what the disassembly found. Names and
+// addresses in operands become links, and data is shown as hex/ASCII rows. The pages don't load
+// anything from elsewhere, so they work straight from the disk.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+pub const HEX_ROW_BYTES: usize = 16;
+
+const STYLESHEET: &str = "\
+body { background: #1e1e1e; color: #d4d4d4; font-family: monospace; margin: 1em 2em; }
+a { color: #9cdcfe; text-decoration: none; }
+a:hover { text-decoration: underline; }
+nav { margin-bottom: 1em; }
+.line { white-space: pre; }
+.line:target, .label:target { background: #3a3d41; }
+.address { color: #858585; }
+.bytes { color: #6a9955; }
+.label { color: #dcdcaa; margin-top: 0.5em; white-space: pre; }
+.section { margin-top: 2em; }
+.xrefs { color: #858585; white-space: normal; margin-left: 2em; }
+.comment { color: #6a9955; }
+.data { color: #ce9178; white-space: pre; }
+table { border-collapse: collapse; }
+td { padding: 0 1em 0 0; vertical-align: top; }
+";
+
+/// Everything the viewer shows of a disassembly, with the names already worked out.
+pub struct Listing {
+    pub title: String,
+    pub header_fields: Vec<(String, String)>,
+    pub vectors: Vec<(&'static str, usize)>,
+    pub banks: Vec<ListingBank>,
+    pub outside_addresses: Vec<OutsideAddress>,
+    pub cross_references: Vec<(usize, usize, &'static str)>,
+    /// The name each address mentioned on the pages goes by, if any.
+    pub names: HashMap<usize, String>,
+    /// The user's symbols, which win over any other label with the same name.
+    pub symbols: Vec<(usize, String)>,
+}
+
+/// A PRG bank, from `start_address` up to `end_address` (exclusive).
+pub struct ListingBank {
+    pub start_address: usize,
+    pub end_address: usize,
+    pub lines: Vec<ListingLine>,
+}
+
+/// A line, or run of lines, in the listing of a bank.
+pub enum ListingLine {
+    /// The labels at `address`, each with whether it starts a section, and the symbol's comment.
+    Labels { address: usize, labels: Vec<(String, bool)>, comment: Option<String> },
+    Instruction { address: usize, bytes: Vec<u8>, text: String, overlapped_address: Option<usize> },
+    Data { address: usize, bytes: Vec<u8>, kind: &'static str },
+}
+
+/// An address outside PRG that something refers to or the user named.
+pub struct OutsideAddress {
+    pub address: usize,
+    pub labels: Vec<String>,
+    pub comment: Option<String>,
+}
+
+/// Works out where each address and name links to.
+struct Links {
+    /// The start of every instruction and data row, with its bank and end address (exclusive).
+    spans: BTreeMap<usize, (usize, usize)>,
+    outside_addresses: BTreeSet<usize>,
+    addresses_by_name: HashMap<String, usize>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl ListingLine {
+    pub fn address(&self) -> usize {
+        match self {
+            ListingLine::Labels { address, .. } | ListingLine::Instruction { address, .. } | ListingLine::Data { address, .. } => *address,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl Links {
+    fn new(listing: &Listing) -> Self {
+        // Every line gets an anchor, so addresses without one link to the line they're inside.
+        let mut spans: BTreeMap<usize, (usize, usize)> = BTreeMap::new();
+        for (bank, listing_bank) in listing.banks.iter().enumerate() {
+            for line in &listing_bank.lines {
+                match line {
+                    ListingLine::Instruction { address, bytes, .. } | ListingLine::Data { address, bytes, .. } => {
+                        spans.entry(*address).or_insert((bank, address + bytes.len()));
+                    },
+                    ListingLine::Labels { .. } => {},
+                }
+            }
+        }
+        let outside_addresses: BTreeSet<usize> = listing.outside_addresses.iter().map(|outside| outside.address).collect();
+
+        let mut addresses_by_name: HashMap<String, usize> = HashMap::new();
+        let labels_by_address = labels_by_address(listing);
+        let outside_labels = listing.outside_addresses.iter().map(|outside| (outside.address, outside.labels.iter().collect()));
+        let bank_labels = spans.keys().map(|address| {
+            let labels = labels_by_address.get(address).map(|labels| labels.iter().map(|(label, _)| label).collect());
+            (*address, labels.unwrap_or_default())
+        });
+        for (address, labels) in outside_labels.chain(bank_labels).collect::<Vec<(usize, Vec<&String>)>>() {
+            if let Some(name) = listing.names.get(&address) {
+                addresses_by_name.entry(name.clone()).or_insert(address);
+            }
+            for label in labels {
+                addresses_by_name.entry(label.clone()).or_insert(address);
+            }
+        }
+        for (address, name) in &listing.symbols {
+            addresses_by_name.insert(name.clone(), *address);
+        }
+
+        Self { spans, outside_addresses, addresses_by_name }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn href_of_address(&self, address: usize) -> Option<String> {
+        if let Some((start_address, (bank, end_address))) = self.spans.range(..=address).next_back() && address < *end_address {
+            return Some(format!("{}#a{:04X}", bank_page_filename(*bank), start_address));
+        }
+        self.outside_addresses.contains(&address).then(|| format!("index.html#a{address:04X}"))
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn href_of_name(&self, name: &str) -> Option<String> {
+        self.addresses_by_name.get(name).and_then(|address| self.href_of_address(*address))
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn linkify(&self, operand: &str) -> String {
+        linkify_operand(operand, &|name| self.href_of_name(name), &|address| self.href_of_address(address))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the pages for `listing`, each with its filename.
+pub fn format_pages(listing: &Listing) -> Vec<(String, String)> {
+    let links = Links::new(listing);
+
+    let mut cross_references: BTreeMap<usize, Vec<(usize, &str)>> = BTreeMap::new();
+    for (address, target_address, kind) in &listing.cross_references {
+        cross_references.entry(*target_address).or_default().push((*address, kind));
+    }
+    let format_cross_references = |address: usize| -> String {
+        let Some(references) = cross_references.get(&address) else {
+            return String::new();
+        };
+        let links: Vec<String> = references.iter()
+            .map(|(from_address, kind)| {
+                let from_text = format!("${from_address:04X}");
+                match links.href_of_address(*from_address) {
+                    Some(href) => format!("<a href=\"{href}\">{from_text}</a> {kind}"),
+                    None => format!("{from_text} {kind}"),
+                }
+            })
+            .collect();
+        format!("<div class=\"xrefs\">xrefs: {}</div>\n", links.join(", "))
+    };
+
+    let mut navigation = String::from("<a href=\"index.html\">index</a>");
+    for bank in 0..listing.banks.len() {
+        navigation.push_str(&format!(" | <a href=\"{}\">bank {bank}</a>", bank_page_filename(bank)));
+    }
+
+    let title = escape_html(&listing.title);
+    let mut index_body = format!("<h1>{title}</h1>\n<h2>Header</h2>\n<table>\n");
+    for (name, value) in &listing.header_fields {
+        index_body.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>\n", name, escape_html(value)));
+    }
+    index_body.push_str("</table>\n<h2>Vectors</h2>\n<table>\n");
+    for (name, address) in &listing.vectors {
+        let target = links.linkify(&format!("${address:04X}"));
+        let label = listing.names.get(address).map(|label| links.linkify(label)).unwrap_or_default();
+        index_body.push_str(&format!("<tr><td>{name}</td><td>{target}</td><td>{label}</td></tr>\n"));
+    }
+    index_body.push_str("</table>\n<h2>Banks</h2>\n<table>\n");
+    for (bank, listing_bank) in listing.banks.iter().enumerate() {
+        index_body.push_str(&format!(
+            "<tr><td><a href=\"{}\">bank {bank}</a></td><td>${:04X}-${:04X}</td></tr>\n",
+            bank_page_filename(bank), listing_bank.start_address, listing_bank.end_address - 1));
+    }
+    index_body.push_str("</table>\n<h2>Labels</h2>\n<table>\n");
+    for (address, labels) in labels_by_address(listing) {
+        for (label, _) in labels {
+            index_body.push_str(&format!("<tr><td>${:04X}</td><td>{}</td></tr>\n", address, links.linkify(label)));
+        }
+    }
+    index_body.push_str("</table>\n<h2>Memory outside PRG</h2>\n");
+    for outside in &listing.outside_addresses {
+        let name = listing.names.get(&outside.address).map(|name| escape_html(name)).unwrap_or_default();
+        index_body.push_str(&format!("<div class=\"label\" id=\"a{:04X}\">${:04X} {name}</div>\n", outside.address, outside.address));
+        if let Some(comment) = &outside.comment {
+            index_body.push_str(&format!("<div class=\"comment\">; {}</div>\n", escape_html(comment)));
+        }
+        index_body.push_str(&format_cross_references(outside.address));
+    }
+
+    let mut pages = vec![(String::from("index.html"), format_page(&listing.title, &navigation, &index_body))];
+    for (bank, listing_bank) in listing.banks.iter().enumerate() {
+        let mut body = format!("<h1>{title}, bank {bank}, ${:04X}-${:04X}</h1>\n", listing_bank.start_address, listing_bank.end_address - 1);
+
+        let mut anchored_address = None;
+        for line in &listing_bank.lines {
+            let address = line.address();
+            let id = if anchored_address == Some(address) { String::new() } else { format!(" id=\"a{address:04X}\"") };
+            anchored_address = Some(address);
+
+            match line {
+                ListingLine::Labels { labels, comment, .. } => {
+                    for (label_index, (label, is_section_start)) in labels.iter().enumerate() {
+                        let class = if *is_section_start { "label section" } else { "label" };
+                        let id = if label_index == 0 { id.as_str() } else { "" };
+                        body.push_str(&format!("<div class=\"{class}\"{id}>{}: <span class=\"address\">[{:04X}]</span></div>\n", escape_html(label), address));
+                    }
+                    if let Some(comment) = comment {
+                        for comment_line in comment.lines() {
+                            body.push_str(&format!("<div class=\"comment\">    ; {}</div>\n", escape_html(comment_line)));
+                        }
+                    }
+                    body.push_str(&format_cross_references(address));
+                },
+                ListingLine::Instruction { bytes, text, overlapped_address, .. } => {
+                    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+                    let instruction_html = match text.split_once(' ') {
+                        Some((mnemonic, operand)) => format!("{mnemonic} {}", links.linkify(operand)),
+                        None => escape_html(text),
+                    };
+                    let comment = match overlapped_address {
+                        Some(inner_address) => format!(" <span class=\"comment\">; overlaps the instruction at {:04X}</span>", inner_address),
+                        None => String::new(),
+                    };
+                    body.push_str(&format!(
+                        "<div class=\"line\"{id}><span class=\"address\">{:04X}</span>  <span class=\"bytes\">{:<8}</span>  {instruction_html}{comment}</div>\n",
+                        address, bytes.join(" ")));
+                },
+                ListingLine::Data { bytes, kind, .. } => {
+                    let (hex, ascii) = format_hex_row(bytes);
+                    body.push_str(&format!(
+                        "<div class=\"data\"{id}><span class=\"address\">{:04X}</span>  {hex}  |{ascii}|  <span class=\"comment\">{kind}</span></div>\n",
+                        address));
+                },
+            }
+        }
+
+        pages.push((bank_page_filename(bank), format_page(&listing.title, &navigation, &body)));
+    }
+
+    pages
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the labels of every address in the banks, in address order, taking the first bank an
+/// address appears in.
+fn labels_by_address(listing: &Listing) -> BTreeMap<usize, &[(String, bool)]> {
+    let mut labels_by_address: BTreeMap<usize, &[(String, bool)]> = BTreeMap::new();
+    for listing_bank in &listing.banks {
+        for line in &listing_bank.lines {
+            if let ListingLine::Labels { address, labels, .. } = line {
+                labels_by_address.entry(*address).or_insert(labels);
+            }
+        }
+    }
+    labels_by_address
+}
+
+// ---------------------------------------------------------------------------
+
+fn bank_page_filename(bank: usize) -> String {
+    format!("bank_{bank:02}.html")
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::new();
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn format_page(title: &str, navigation: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>\n{STYLESHEET}</style>\n</head>\n<body>\n<nav>{navigation}</nav>\n{body}</body>\n</html>\n",
+        escape_html(title))
+}
+
+// ---------------------------------------------------------------------------
+
+/// Escapes an operand such as `(ptr+1),Y` or `$8123`, linking every name `href_of_name` knows and
+/// every `$` address `href_of_address` knows.
+pub fn linkify_operand(
+    operand: &str,
+    href_of_name: &dyn Fn(&str) -> Option<String>,
+    href_of_address: &dyn Fn(usize) -> Option<String>,
+) -> String {
+    let mut linked = String::new();
+
+    let characters: Vec<char> = operand.chars().collect();
+    let mut index = 0;
+    while index < characters.len() {
+        let character = characters[index];
+        let token_end = if character == '$' {
+            index + 1 + characters[index + 1..].iter().take_while(|character| character.is_ascii_hexdigit()).count()
+        } else if character.is_ascii_alphabetic() || character == '_' {
+            index + characters[index..].iter().take_while(|character| character.is_ascii_alphanumeric() || **character == '_').count()
+        } else {
+            index + 1
+        };
+        let token: String = characters[index..token_end].iter().collect();
+
+        let href = if let Some(hex) = token.strip_prefix('$') {
+            usize::from_str_radix(hex, 16).ok().and_then(href_of_address)
+        } else if token_end > index + 1 || character.is_ascii_alphabetic() {
+            href_of_name(&token)
+        } else {
+            None
+        };
+
+        match href {
+            Some(href) => linked.push_str(&format!("<a href=\"{href}\">{}</a>", escape_html(&token))),
+            None => linked.push_str(&escape_html(&token)),
+        }
+        index = token_end;
+    }
+
+    linked
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the hex and ASCII columns for up to `HEX_ROW_BYTES` bytes, with unprintable bytes
+/// shown as dots.
+pub fn format_hex_row(bytes: &[u8]) -> (String, String) {
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+    let ascii: String = bytes.iter()
+        .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+        .collect();
+
+    (format!("{:<width$}", hex.join(" "), width = HEX_ROW_BYTES * 3 - 1), escape_html(&ascii))
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(escape_html("<a href=\"x\">&</a>"), "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;");
+    }
+
+    #[test]
+    fn links_names_and_addresses() {
+        let href_of_name = |name: &str| (name == "ptr").then(|| String::from("index.html#a0012"));
+        let href_of_address = |address: usize| (address == 0x8123).then(|| String::from("bank_00.html#a8123"));
+
+        assert_eq!(
+            linkify_operand("(ptr+1),Y", &href_of_name, &href_of_address),
+            "(<a href=\"index.html#a0012\">ptr</a>+1),Y");
+        assert_eq!(
+            linkify_operand("$8123,X", &href_of_name, &href_of_address),
+            "<a href=\"bank_00.html#a8123\">$8123</a>,X");
+        assert_eq!(linkify_operand("#$12", &href_of_name, &href_of_address), "#$12");
+    }
+
+    fn small_listing() -> Listing {
+        Listing {
+            title: String::from("game.nes"),
+            header_fields: vec![(String::from("mapper"), String::from("0"))],
+            vectors: vec![("RESET", 0xC000)],
+            banks: vec![ListingBank {
+                start_address: 0xC000,
+                end_address: 0xC006,
+                lines: vec![
+                    ListingLine::Labels { address: 0xC000, labels: vec![(String::from("reset"), true)], comment: None },
+                    ListingLine::Instruction { address: 0xC000, bytes: vec![0xAD, 0x04, 0xC0], text: String::from("LDA table+1"), overlapped_address: None },
+                    ListingLine::Instruction { address: 0xC003, bytes: vec![0x8D, 0x00, 0x03], text: String::from("STA buffer"), overlapped_address: None },
+                    ListingLine::Labels { address: 0xC006, labels: vec![(String::from("buffer"), false)], comment: None },
+                ],
+            }],
+            outside_addresses: vec![OutsideAddress { address: 0x0300, labels: Vec::new(), comment: Some(String::from("copied <here>")) }],
+            cross_references: vec![(0xC003, 0x0300, "write")],
+            names: HashMap::from([(0xC000, String::from("reset")), (0x0300, String::from("buffer"))]),
+            symbols: vec![(0x0300, String::from("buffer")), (0xC003, String::from("table"))],
+        }
+    }
+
+    #[test]
+    fn finds_names_through_labels_and_symbols() {
+        let links = Links::new(&small_listing());
+
+        assert_eq!(links.addresses_by_name.get("reset"), Some(&0xC000));
+        // The symbol wins over the label with the same name.
+        assert_eq!(links.addresses_by_name.get("buffer"), Some(&0x0300));
+        assert_eq!(links.href_of_name("buffer").as_deref(), Some("index.html#a0300"));
+        // Addresses inside an instruction link to the instruction.
+        assert_eq!(links.href_of_name("table").as_deref(), Some("bank_00.html#aC003"));
+        assert_eq!(links.href_of_address(0xC005).as_deref(), Some("bank_00.html#aC003"));
+        assert_eq!(links.href_of_address(0xC006), None);
+        assert_eq!(links.href_of_name("missing"), None);
+    }
+
+    #[test]
+    fn formats_index_and_bank_pages() {
+        let pages = format_pages(&small_listing());
+        let filenames: Vec<&str> = pages.iter().map(|(filename, _)| filename.as_str()).collect();
+        assert_eq!(filenames, vec!["index.html", "bank_00.html"]);
+
+        let index = &pages[0].1;
+        assert!(index.contains("<tr><td>RESET</td><td><a href=\"bank_00.html#aC000\">$C000</a></td><td><a href=\"bank_00.html#aC000\">reset</a></td></tr>\n"));
+        assert!(index.contains("<div class=\"label\" id=\"a0300\">$0300 buffer</div>\n<div class=\"comment\">; copied &lt;here&gt;</div>\n"));
+        assert!(index.contains("<div class=\"xrefs\">xrefs: <a href=\"bank_00.html#aC003\">$C003</a> write</div>\n"));
+
+        let bank = &pages[1].1;
+        assert!(bank.contains("<div class=\"label section\" id=\"aC000\">reset: <span class=\"address\">[C000]</span></div>\n"));
+        assert!(bank.contains("<div class=\"line\"><span class=\"address\">C000</span>  <span class=\"bytes\">AD 04 C0</span>  LDA <a href=\"bank_00.html#aC003\">table</a>+1</div>\n"));
+        assert!(bank.contains("<div class=\"line\" id=\"aC003\"><span class=\"address\">C003</span>  <span class=\"bytes\">8D 00 03</span>  STA <a href=\"index.html#a0300\">buffer</a></div>\n"));
+    }
+
+    #[test]
+    fn formats_hex_rows() {
+        let (hex, ascii) = format_hex_row(b"Hi<\x00");
+        assert_eq!(hex.trim_end(), "48 69 3C 00");
+        assert_eq!(hex.len(), HEX_ROW_BYTES * 3 - 1);
+        assert_eq!(ascii, "Hi&lt;.");
+    }
+}
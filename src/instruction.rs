@@ -3,15 +3,21 @@ use crate::labeller::Labeller;
 pub struct DisassembledInstruction {
     pub is_section_complete: bool,
     pub bytes_count: usize,
+    pub instruction_text: String,
     pub text_line: String,
     pub address_to_process_later: Option<usize>,
 }
 
 pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize, address: usize, labeller: &mut Labeller) -> Option<DisassembledInstruction> {
-    let operand1 = prg_rom_contents[contents_offset + 1];
-    let operand2 = prg_rom_contents[contents_offset + 2];
+    // Bytes past the end of the contents read as zero.
+    let byte_at = |index: usize| prg_rom_contents.get(contents_offset + index).copied().unwrap_or(0);
+    let operand1 = byte_at(1);
+    let operand2 = byte_at(2);
     let absolute_address = create_u16(operand1, operand2);
-    let absolute_address_formatted = format_absolute_address(absolute_address);
+    let absolute_address_formatted = format_absolute_address(absolute_address, labeller);
+    let stored_address_formatted = labeller.get_write_only_register_name(absolute_address as usize).cloned()
+        .unwrap_or_else(|| absolute_address_formatted.clone());
+    let zero_page_address_formatted = format_zero_page_address(operand1, labeller);
     let operand1_formatted = format!("${:02X}", operand1);
 
     let mut is_section_complete = false;
@@ -25,23 +31,23 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         0x00 => {
 
             bytes_count = 1;
-            instruction_text = Some(format!("BRK"));
+            instruction_text = Some(String::from("BRK"));
         },
         0x01 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ORA ({operand1_formatted},X)"));
+            instruction_text = Some(format!("ORA ({zero_page_address_formatted},X)"));
         },
         0x05 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ORA {operand1_formatted}"));
+            instruction_text = Some(format!("ORA {zero_page_address_formatted}"));
         },
         0x06 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ASL {operand1_formatted}"));
+            instruction_text = Some(format!("ASL {zero_page_address_formatted}"));
         },
         0x08 => {
             bytes_count = 1;
-            instruction_text = Some(format!("PHP"));
+            instruction_text = Some(String::from("PHP"));
         },
         0x09 => {
             bytes_count = 2;
@@ -49,7 +55,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x0A => {
             bytes_count = 1;
-            instruction_text = Some(format!("ASL A"));
+            instruction_text = Some(String::from("ASL A"));
         },
         0x0D => {
             bytes_count = 3;
@@ -69,19 +75,19 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x11 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ORA ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("ORA ({zero_page_address_formatted}),Y"));
         },
         0x15 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ORA {operand1_formatted},X"));
+            instruction_text = Some(format!("ORA {zero_page_address_formatted},X"));
         },
         0x16 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ASL {operand1_formatted},X"));
+            instruction_text = Some(format!("ASL {zero_page_address_formatted},X"));
         },
         0x18 => {
             bytes_count = 1;
-            instruction_text = Some(format!("CLC"));
+            instruction_text = Some(String::from("CLC"));
         },
         0x19 => {
             bytes_count = 3;
@@ -104,23 +110,23 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x21 => {
             bytes_count = 2;
-            instruction_text = Some(format!("AND ({operand1_formatted},X)"));
+            instruction_text = Some(format!("AND ({zero_page_address_formatted},X)"));
         },
         0x24 => {
             bytes_count = 2;
-            instruction_text = Some(format!("BIT {operand1_formatted}"));
+            instruction_text = Some(format!("BIT {zero_page_address_formatted}"));
         },
         0x25 => {
             bytes_count = 2;
-            instruction_text = Some(format!("AND {operand1_formatted}"));
+            instruction_text = Some(format!("AND {zero_page_address_formatted}"));
         },
         0x26 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ROL {operand1_formatted}"));
+            instruction_text = Some(format!("ROL {zero_page_address_formatted}"));
         },
         0x28 => {
             bytes_count = 1;
-            instruction_text = Some(format!("PLP"));
+            instruction_text = Some(String::from("PLP"));
         },
         0x29 => {
             bytes_count = 2;
@@ -128,7 +134,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x2A => {
             bytes_count = 1;
-            instruction_text = Some(format!("ROL A"));
+            instruction_text = Some(String::from("ROL A"));
         },
         0x2C => {
             bytes_count = 3;
@@ -152,19 +158,19 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x31 => {
             bytes_count = 2;
-            instruction_text = Some(format!("AND ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("AND ({zero_page_address_formatted}),Y"));
         },
         0x35 => {
             bytes_count = 2;
-            instruction_text = Some(format!("AND {operand1_formatted},X"));
+            instruction_text = Some(format!("AND {zero_page_address_formatted},X"));
         },
         0x36 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ROL {operand1_formatted},X"));
+            instruction_text = Some(format!("ROL {zero_page_address_formatted},X"));
         },
         0x38 => {
             bytes_count = 1;
-            instruction_text = Some(format!("SEC"));
+            instruction_text = Some(String::from("SEC"));
         },
         0x39 => {
             bytes_count = 3;
@@ -181,24 +187,24 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
 
         0x40 => {
             bytes_count = 1;
-            instruction_text = Some(format!("RTI"));
+            instruction_text = Some(String::from("RTI"));
             is_section_complete = true;
         },
         0x41 => {
             bytes_count = 2;
-            instruction_text = Some(format!("EOR ({operand1_formatted},X)"));
+            instruction_text = Some(format!("EOR ({zero_page_address_formatted},X)"));
         },
         0x45 => {
             bytes_count = 2;
-            instruction_text = Some(format!("EOR {operand1_formatted}"));
+            instruction_text = Some(format!("EOR {zero_page_address_formatted}"));
         },
         0x46 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LSR {operand1_formatted}"));
+            instruction_text = Some(format!("LSR {zero_page_address_formatted}"));
         },
         0x48 => {
             bytes_count = 1;
-            instruction_text = Some(format!("PHA"));
+            instruction_text = Some(String::from("PHA"));
         },
         0x49 => {
             bytes_count = 2;
@@ -206,7 +212,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x4A => {
             bytes_count = 1;
-            instruction_text = Some(format!("LSR A"));
+            instruction_text = Some(String::from("LSR A"));
         },
         0x4C => {
             bytes_count = 3;
@@ -233,19 +239,19 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x51 => {
             bytes_count = 2;
-            instruction_text = Some(format!("EOR ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("EOR ({zero_page_address_formatted}),Y"));
         },
         0x55 => {
             bytes_count = 2;
-            instruction_text = Some(format!("EOR {operand1_formatted},X"));
+            instruction_text = Some(format!("EOR {zero_page_address_formatted},X"));
         },
         0x56 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LSR {operand1_formatted},X"));
+            instruction_text = Some(format!("LSR {zero_page_address_formatted},X"));
         },
         0x58 => {
             bytes_count = 1;
-            instruction_text = Some(format!("CLI"));
+            instruction_text = Some(String::from("CLI"));
         },
         0x59 => {
             bytes_count = 3;
@@ -262,24 +268,24 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
 
         0x60 => {
             bytes_count = 1;
-            instruction_text = Some(format!("RTS"));
+            instruction_text = Some(String::from("RTS"));
             is_section_complete = true;
         },
         0x61 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ADC ({operand1_formatted},X)"));
+            instruction_text = Some(format!("ADC ({zero_page_address_formatted},X)"));
         },
         0x65 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ADC {operand1_formatted}"));
+            instruction_text = Some(format!("ADC {zero_page_address_formatted}"));
         },
         0x66 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ROR {operand1_formatted}"));
+            instruction_text = Some(format!("ROR {zero_page_address_formatted}"));
         },
         0x68 => {
             bytes_count = 1;
-            instruction_text = Some(format!("PLA"));
+            instruction_text = Some(String::from("PLA"));
         },
         0x69 => {
             bytes_count = 2;
@@ -287,7 +293,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x6A => {
             bytes_count = 1;
-            instruction_text = Some(format!("ROR A"));
+            instruction_text = Some(String::from("ROR A"));
         },
         0x6C => {
             bytes_count = 3;
@@ -312,19 +318,19 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x71 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ADC ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("ADC ({zero_page_address_formatted}),Y"));
         },
         0x75 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ADC {operand1_formatted},X"));
+            instruction_text = Some(format!("ADC {zero_page_address_formatted},X"));
         },
         0x76 => {
             bytes_count = 2;
-            instruction_text = Some(format!("ROR {operand1_formatted},X"));
+            instruction_text = Some(format!("ROR {zero_page_address_formatted},X"));
         },
         0x78 => {
             bytes_count = 1;
-            instruction_text = Some(format!("SEI"));
+            instruction_text = Some(String::from("SEI"));
         },
         0x79 => {
             bytes_count = 3;
@@ -341,39 +347,39 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
 
         0x81 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STA ({operand1_formatted},X)"));
+            instruction_text = Some(format!("STA ({zero_page_address_formatted},X)"));
         },
         0x84 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STY {operand1_formatted}"));
+            instruction_text = Some(format!("STY {zero_page_address_formatted}"));
         },
         0x85 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STA {operand1_formatted}"));
+            instruction_text = Some(format!("STA {zero_page_address_formatted}"));
         },
         0x86 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STX {operand1_formatted}"));
+            instruction_text = Some(format!("STX {zero_page_address_formatted}"));
         },
         0x88 => {
             bytes_count = 1;
-            instruction_text = Some(format!("DEY"));
+            instruction_text = Some(String::from("DEY"));
         },
         0x8A => {
             bytes_count = 1;
-            instruction_text = Some(format!("TXA"));
+            instruction_text = Some(String::from("TXA"));
         },
         0x8C => {
             bytes_count = 3;
-            instruction_text = Some(format!("STY {absolute_address_formatted}"));
+            instruction_text = Some(format!("STY {stored_address_formatted}"));
         },
         0x8D => {
             bytes_count = 3;
-            instruction_text = Some(format!("STA {absolute_address_formatted}"));
+            instruction_text = Some(format!("STA {stored_address_formatted}"));
         },
         0x8E => {
             bytes_count = 3;
-            instruction_text = Some(format!("STX {absolute_address_formatted}"));
+            instruction_text = Some(format!("STX {stored_address_formatted}"));
         },
 
         0x90 => {
@@ -385,35 +391,35 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0x91 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STA ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("STA ({zero_page_address_formatted}),Y"));
         },
         0x94 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STY {operand1_formatted},X"));
+            instruction_text = Some(format!("STY {zero_page_address_formatted},X"));
         },
         0x95 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STA {operand1_formatted},X"));
+            instruction_text = Some(format!("STA {zero_page_address_formatted},X"));
         },
         0x96 => {
             bytes_count = 2;
-            instruction_text = Some(format!("STX {operand1_formatted},Y"));
+            instruction_text = Some(format!("STX {zero_page_address_formatted},Y"));
         },
         0x98 => {
             bytes_count = 1;
-            instruction_text = Some(format!("TYA"));
+            instruction_text = Some(String::from("TYA"));
         },
         0x99 => {
             bytes_count = 3;
-            instruction_text = Some(format!("STA {absolute_address_formatted},Y"));
+            instruction_text = Some(format!("STA {stored_address_formatted},Y"));
         },
         0x9A => {
             bytes_count = 1;
-            instruction_text = Some(format!("TXS"));
+            instruction_text = Some(String::from("TXS"));
         },
         0x9D => {
             bytes_count = 3;
-            instruction_text = Some(format!("STA {absolute_address_formatted},X"));
+            instruction_text = Some(format!("STA {stored_address_formatted},X"));
         },
 
         0xA0 => {
@@ -422,7 +428,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xA1 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDA ({operand1_formatted},X)"));
+            instruction_text = Some(format!("LDA ({zero_page_address_formatted},X)"));
         },
         0xA2 => {
             bytes_count = 2;
@@ -430,19 +436,19 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xA4 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDY {operand1_formatted}"));
+            instruction_text = Some(format!("LDY {zero_page_address_formatted}"));
         },
         0xA5 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDA {operand1_formatted}"));
+            instruction_text = Some(format!("LDA {zero_page_address_formatted}"));
         },
         0xA6 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDX {operand1_formatted}"));
+            instruction_text = Some(format!("LDX {zero_page_address_formatted}"));
         },
         0xA8 => {
             bytes_count = 1;
-            instruction_text = Some(format!("TAY"));
+            instruction_text = Some(String::from("TAY"));
         },
         0xA9 => {
             bytes_count = 2;
@@ -450,7 +456,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xAA => {
             bytes_count = 1;
-            instruction_text = Some(format!("TAX"));
+            instruction_text = Some(String::from("TAX"));
         },
         0xAC => {
             bytes_count = 3;
@@ -474,23 +480,23 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xB1 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDA ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("LDA ({zero_page_address_formatted}),Y"));
         },
         0xB4 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDY {operand1_formatted},X"));
+            instruction_text = Some(format!("LDY {zero_page_address_formatted},X"));
         },
         0xB5 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDA {operand1_formatted},X"));
+            instruction_text = Some(format!("LDA {zero_page_address_formatted},X"));
         },
         0xB6 => {
             bytes_count = 2;
-            instruction_text = Some(format!("LDX {operand1_formatted},Y"));
+            instruction_text = Some(format!("LDX {zero_page_address_formatted},Y"));
         },
         0xB8 => {
             bytes_count = 1;
-            instruction_text = Some(format!("CLV"));
+            instruction_text = Some(String::from("CLV"));
         },
         0xB9 => {
             bytes_count = 3;
@@ -498,7 +504,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xBA => {
             bytes_count = 1;
-            instruction_text = Some(format!("TSX"));
+            instruction_text = Some(String::from("TSX"));
         },
         0xBC => {
             bytes_count = 3;
@@ -519,23 +525,23 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xC1 => {
             bytes_count = 2;
-            instruction_text = Some(format!("CMP ({operand1_formatted},X)"));
+            instruction_text = Some(format!("CMP ({zero_page_address_formatted},X)"));
         },
         0xC4 => {
             bytes_count = 2;
-            instruction_text = Some(format!("CPY {operand1_formatted}"));
+            instruction_text = Some(format!("CPY {zero_page_address_formatted}"));
         },
         0xC5 => {
             bytes_count = 2;
-            instruction_text = Some(format!("CMP {operand1_formatted}"));
+            instruction_text = Some(format!("CMP {zero_page_address_formatted}"));
         },
         0xC6 => {
             bytes_count = 2;
-            instruction_text = Some(format!("DEC {operand1_formatted}"));
+            instruction_text = Some(format!("DEC {zero_page_address_formatted}"));
         },
         0xC8 => {
             bytes_count = 1;
-            instruction_text = Some(format!("INY"));
+            instruction_text = Some(String::from("INY"));
         },
         0xC9 => {
             bytes_count = 2;
@@ -543,7 +549,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xCA => {
             bytes_count = 1;
-            instruction_text = Some(format!("DEX"));
+            instruction_text = Some(String::from("DEX"));
         },
         0xCC => {
             bytes_count = 3;
@@ -567,19 +573,19 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xD1 => {
             bytes_count = 2;
-            instruction_text = Some(format!("CMP ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("CMP ({zero_page_address_formatted}),Y"));
         },
         0xD5 => {
             bytes_count = 2;
-            instruction_text = Some(format!("CMP {operand1_formatted},X"));
+            instruction_text = Some(format!("CMP {zero_page_address_formatted},X"));
         },
         0xD6 => {
             bytes_count = 2;
-            instruction_text = Some(format!("DEC {operand1_formatted},X"));
+            instruction_text = Some(format!("DEC {zero_page_address_formatted},X"));
         },
         0xD8 => {
             bytes_count = 1;
-            instruction_text = Some(format!("CLD"));
+            instruction_text = Some(String::from("CLD"));
         },
         0xD9 => {
             bytes_count = 3;
@@ -600,23 +606,23 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xE1 => {
             bytes_count = 2;
-            instruction_text = Some(format!("SBC ({operand1_formatted},X)"));
+            instruction_text = Some(format!("SBC ({zero_page_address_formatted},X)"));
         },
         0xE4 => {
             bytes_count = 2;
-            instruction_text = Some(format!("CPX {operand1_formatted}"));
+            instruction_text = Some(format!("CPX {zero_page_address_formatted}"));
         },
         0xE5 => {
             bytes_count = 2;
-            instruction_text = Some(format!("SBC {operand1_formatted}"));
+            instruction_text = Some(format!("SBC {zero_page_address_formatted}"));
         },
         0xE6 => {
             bytes_count = 2;
-            instruction_text = Some(format!("INC {operand1_formatted}"));
+            instruction_text = Some(format!("INC {zero_page_address_formatted}"));
         },
         0xE8 => {
             bytes_count = 1;
-            instruction_text = Some(format!("INX"));
+            instruction_text = Some(String::from("INX"));
         },
         0xE9 => {
             bytes_count = 2;
@@ -624,7 +630,7 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xEA => {
             bytes_count = 1;
-            instruction_text = Some(format!("NOP"));
+            instruction_text = Some(String::from("NOP"));
         },
         0xEC => {
             bytes_count = 3;
@@ -648,19 +654,19 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         },
         0xF1 => {
             bytes_count = 2;
-            instruction_text = Some(format!("SBC ({operand1_formatted}),Y"));
+            instruction_text = Some(format!("SBC ({zero_page_address_formatted}),Y"));
         },
         0xF5 => {
             bytes_count = 2;
-            instruction_text = Some(format!("SBC {operand1_formatted},X"));
+            instruction_text = Some(format!("SBC {zero_page_address_formatted},X"));
         },
         0xF6 => {
             bytes_count = 2;
-            instruction_text = Some(format!("INC {operand1_formatted},X"));
+            instruction_text = Some(format!("INC {zero_page_address_formatted},X"));
         },
         0xF8 => {
             bytes_count = 1;
-            instruction_text = Some(format!("SED"));
+            instruction_text = Some(String::from("SED"));
         },
         0xF9 => {
             bytes_count = 3;
@@ -685,13 +691,14 @@ pub fn disassemble_instruction(prg_rom_contents: &[u8], contents_offset: usize,
         Some(instruction_text) => {
             let mut bytes = String::new();
             for i in 0..bytes_count {
-                bytes = format!("{bytes} {:02X}", prg_rom_contents[contents_offset + i]);
+                bytes = format!("{bytes} {:02X}", byte_at(i));
             }
             let text_line = format!("    {instruction_text}        # {:04X} |{bytes}", address);
 
             Some(DisassembledInstruction {
                 is_section_complete,
                 bytes_count,
+                instruction_text,
                 text_line,
                 address_to_process_later,
             })
@@ -710,45 +717,68 @@ fn create_u16(low_byte: u8, high_byte: u8) -> u16 {
 
 fn calculate_target_address(address: u16, signed_offset: u8) -> usize {
     let sign_extended_offset = ((signed_offset as i8) as i16) as u16;
-    (address as u16).wrapping_add(sign_extended_offset) as usize
+    address.wrapping_add(sign_extended_offset) as usize
 }
 
 // ---------------------------------------------------------------------------
 
-fn format_absolute_address(address: u16) -> String {
+fn format_zero_page_address(address: u8, labeller: &Labeller) -> String {
+    match labeller.get_symbol_name(address as usize) {
+        Some(symbol_name) => symbol_name,
+        None => format!("${:02X}", address),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn format_absolute_address(address: u16, labeller: &Labeller) -> String {
+    if let Some(symbol_name) = labeller.get_symbol_name(address as usize) {
+        return symbol_name;
+    }
+
+    match register_name(address as usize) {
+        Some(register_name) => String::from(register_name),
+        None => format!("${:04X}", address),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the name of the PPU or APU register at `address`, if there is one.
+pub fn register_name(address: usize) -> Option<&'static str> {
     // These names are taken from the Mesen emulator, because they're well-named. 🙂
     match address {
-        0x2000 => String::from("PpuControl_2000"),
-        0x2001 => String::from("PpuMask_2001"),
-        0x2002 => String::from("PpuStatus_2002"),
-        0x2003 => String::from("OamAddr_2003"),
-        0x2004 => String::from("OamData_2004"),
-        0x2005 => String::from("PpuScroll_2005"),
-        0x2006 => String::from("PpuAddr_2006"),
-        0x2007 => String::from("PpuData_2007"),
-        0x4000 => String::from("Sq0Duty_4000"),
-        0x4001 => String::from("Sq0Sweep_4001"),
-        0x4002 => String::from("Sq0Timer_4002"),
-        0x4003 => String::from("Sq0Length_4003"),
-        0x4004 => String::from("Sq1Duty_4004"),
-        0x4005 => String::from("Sq1Sweep_4005"),
-        0x4006 => String::from("Sq1Timer_4006"),
-        0x4007 => String::from("Sq1Length_4007"),
-        0x4008 => String::from("TrgLinear_4008"),
-        0x400A => String::from("TrgTimer_400A"),
-        0x400B => String::from("TrgLength_400B"),
-        0x400C => String::from("NoiseVolume_400C"),
-        0x400E => String::from("NoisePeriod_400E"),
-        0x400F => String::from("NoiseLength_400F"),
-        0x4010 => String::from("DmcFreq_4010"),
-        0x4011 => String::from("DmcCounter_4011"),
-        0x4012 => String::from("DmcAddress_4012"),
-        0x4013 => String::from("DmcLength_4013"),
-        0x4014 => String::from("SpriteDma_4014"),
-        0x4015 => String::from("ApuStatus_4015"),
-        0x4016 => String::from("Ctrl1_4016"),
-        0x4017 => String::from("Ctrl2_FrameCtr_4017"),
-        non_reserved_address => format!("${:04X}", non_reserved_address),
+        0x2000 => Some("PpuControl_2000"),
+        0x2001 => Some("PpuMask_2001"),
+        0x2002 => Some("PpuStatus_2002"),
+        0x2003 => Some("OamAddr_2003"),
+        0x2004 => Some("OamData_2004"),
+        0x2005 => Some("PpuScroll_2005"),
+        0x2006 => Some("PpuAddr_2006"),
+        0x2007 => Some("PpuData_2007"),
+        0x4000 => Some("Sq0Duty_4000"),
+        0x4001 => Some("Sq0Sweep_4001"),
+        0x4002 => Some("Sq0Timer_4002"),
+        0x4003 => Some("Sq0Length_4003"),
+        0x4004 => Some("Sq1Duty_4004"),
+        0x4005 => Some("Sq1Sweep_4005"),
+        0x4006 => Some("Sq1Timer_4006"),
+        0x4007 => Some("Sq1Length_4007"),
+        0x4008 => Some("TrgLinear_4008"),
+        0x400A => Some("TrgTimer_400A"),
+        0x400B => Some("TrgLength_400B"),
+        0x400C => Some("NoiseVolume_400C"),
+        0x400E => Some("NoisePeriod_400E"),
+        0x400F => Some("NoiseLength_400F"),
+        0x4010 => Some("DmcFreq_4010"),
+        0x4011 => Some("DmcCounter_4011"),
+        0x4012 => Some("DmcAddress_4012"),
+        0x4013 => Some("DmcLength_4013"),
+        0x4014 => Some("SpriteDma_4014"),
+        0x4015 => Some("ApuStatus_4015"),
+        0x4016 => Some("Ctrl1_4016"),
+        0x4017 => Some("Ctrl2_FrameCtr_4017"),
+        _ => None,
     }
 }
 
@@ -757,6 +787,7 @@ fn format_absolute_address(address: u16) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::symbols::SymbolTable;
 
     fn assert_disasm(bytes: [u8; 3], expected: &str) {
         let mut labeller = Labeller::new();
@@ -771,6 +802,29 @@ mod tests {
         );
     }
 
+    fn assert_disasm_with_symbols(bytes: [u8; 3], symbols: &str, expected: &str) {
+        let mut labeller = Labeller::new();
+        labeller.add_symbols(SymbolTable::parse(symbols).unwrap());
+        let result =
+            disassemble_instruction(&bytes, 0, 0x8000, &mut labeller).unwrap();
+
+        assert!(
+            result.text_line.contains(expected),
+            "Expected `{}` in `{}`",
+            expected,
+            result.text_line
+        );
+    }
+
+    #[test] fn symbol_zp()      { assert_disasm_with_symbols([0xA5,0x12,0], "$12 ptr_lo", "LDA ptr_lo"); }
+    #[test] fn symbol_indy()    { assert_disasm_with_symbols([0xB1,0x12,0], "$12 ptr_lo", "LDA (ptr_lo),Y"); }
+    #[test] fn symbol_indx()    { assert_disasm_with_symbols([0xA1,0x13,0], "$12 ptrs 4", "LDA (ptrs+1,X)"); }
+    #[test] fn symbol_absx()    { assert_disasm_with_symbols([0xBD,0x10,0x03], "$0300 buffer 32", "LDA buffer+16,X"); }
+    #[test] fn symbol_ind_jmp() { assert_disasm_with_symbols([0x6C,0x00,0x02], "$0200 vector 2", "JMP (vector)"); }
+    #[test] fn symbol_register_override() { assert_disasm_with_symbols([0x8D,0x00,0x20], "$2000 PPUCTRL", "STA PPUCTRL"); }
+    #[test] fn symbol_jsr()     { assert_disasm_with_symbols([0x20,0x00,0xC0], "$C000 InitGame", "JSR InitGame"); }
+    #[test] fn symbol_not_imm() { assert_disasm_with_symbols([0xA9,0x12,0], "$12 ptr_lo", "LDA #$12"); }
+
     #[test] fn adc_imm()  { assert_disasm([0x69,0x12,0], "ADC #$12"); }
     #[test] fn adc_zp()   { assert_disasm([0x65,0x12,0], "ADC $12"); }
     #[test] fn adc_zpx()  { assert_disasm([0x75,0x12,0], "ADC $12,X"); }
@@ -952,4 +1006,38 @@ mod tests {
     #[test] fn iny() { assert_disasm([0xC8,0,0], "INY"); }
     #[test] fn dex() { assert_disasm([0xCA,0,0], "DEX"); }
     #[test] fn dey() { assert_disasm([0x88,0,0], "DEY"); }
+
+    #[test]
+    fn agrees_with_the_opcode_table() {
+        use crate::opcodes::{decode_opcode, AddressingMode};
+
+        for opcode in 0..=255u8 {
+            let mut labeller = Labeller::new();
+            let result = disassemble_instruction(&[opcode, 0x12, 0x00], 0, 0x8000, &mut labeller);
+            let decoded_opcode = decode_opcode(opcode);
+            assert_eq!(result.is_some(), decoded_opcode.is_some(), "opcode ${opcode:02X}");
+            let (Some(result), Some(decoded_opcode)) = (result, decoded_opcode) else {
+                continue;
+            };
+
+            assert_eq!(result.bytes_count, decoded_opcode.addressing_mode.bytes_count(), "opcode ${opcode:02X}");
+            let (mnemonic, operand) = result.instruction_text.split_once(' ').unwrap_or((&result.instruction_text, ""));
+            assert_eq!(mnemonic, decoded_opcode.mnemonic, "opcode ${opcode:02X}");
+
+            let has_operand_syntax = match decoded_opcode.addressing_mode {
+                AddressingMode::Implied => operand.is_empty(),
+                AddressingMode::Accumulator => operand == "A",
+                AddressingMode::Immediate => operand == "#$12",
+                AddressingMode::ZeroPageX | AddressingMode::AbsoluteX => operand.ends_with(",X") && !operand.starts_with('('),
+                AddressingMode::ZeroPageY | AddressingMode::AbsoluteY => operand.ends_with(",Y") && !operand.starts_with('('),
+                AddressingMode::Indirect => operand.starts_with('(') && operand.ends_with(')'),
+                AddressingMode::IndexedIndirect => operand.starts_with('(') && operand.ends_with(",X)"),
+                AddressingMode::IndirectIndexed => operand.starts_with('(') && operand.ends_with("),Y"),
+                AddressingMode::ZeroPage | AddressingMode::Absolute | AddressingMode::Relative => {
+                    !operand.is_empty() && !operand.starts_with(['#', '(']) && !operand.contains(',')
+                },
+            };
+            assert!(has_operand_syntax, "opcode ${opcode:02X}: `{}` isn't {}", result.instruction_text, decoded_opcode.addressing_mode.name());
+        }
+    }
 }
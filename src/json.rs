@@ -0,0 +1,152 @@
+// Just enough JSON to write exports without a dependency. Objects keep their keys in insertion
+// order so the output is stable and diffs cleanly. Arrays of plain values are kept on one line,
+// so instruction bytes don't take up a line each.
+
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(&'static str, JsonValue)>),
+}
+
+// ---------------------------------------------------------------------------
+
+impl JsonValue {
+    pub fn from_option<T>(value: Option<T>, to_json: impl FnOnce(T) -> JsonValue) -> Self {
+        value.map_or(JsonValue::Null, to_json)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn format(&self) -> String {
+        let mut text = String::new();
+        self.format_into(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn format_into(&self, text: &mut String, indent: usize) {
+        match self {
+            JsonValue::Null => text.push_str("null"),
+            JsonValue::Bool(value) => text.push_str(if *value { "true" } else { "false" }),
+            JsonValue::Number(value) => text.push_str(&value.to_string()),
+            JsonValue::String(value) => text.push_str(&escape_json(value)),
+            JsonValue::Array(values) if values.iter().all(JsonValue::is_plain) => {
+                text.push('[');
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        text.push_str(", ");
+                    }
+                    value.format_into(text, indent);
+                }
+                text.push(']');
+            },
+            JsonValue::Array(values) => {
+                format_members(text, indent, '[', ']', values.iter().map(|value| (None, value)));
+            },
+            JsonValue::Object(members) => {
+                format_members(text, indent, '{', '}', members.iter().map(|(key, value)| (Some(*key), value)));
+            },
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn is_plain(&self) -> bool {
+        !matches!(self, JsonValue::Array(_) | JsonValue::Object(_))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn format_members<'a>(
+    text: &mut String,
+    indent: usize,
+    open: char,
+    close: char,
+    members: impl ExactSizeIterator<Item = (Option<&'a str>, &'a JsonValue)>,
+) {
+    if members.len() == 0 {
+        text.push(open);
+        text.push(close);
+        return;
+    }
+
+    text.push(open);
+    text.push('\n');
+    let member_count = members.len();
+    for (index, (key, value)) in members.enumerate() {
+        text.push_str(&"  ".repeat(indent + 1));
+        if let Some(key) = key {
+            text.push_str(&escape_json(key));
+            text.push_str(": ");
+        }
+        value.format_into(text, indent + 1);
+        if index + 1 < member_count {
+            text.push(',');
+        }
+        text.push('\n');
+    }
+    text.push_str(&"  ".repeat(indent));
+    text.push(close);
+}
+
+// ---------------------------------------------------------------------------
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            character if (character as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", character as u32)),
+            character => escaped.push(character),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_nested_values() {
+        let value = JsonValue::Object(vec![
+            ("name", JsonValue::String(String::from("RESET"))),
+            ("bytes", JsonValue::Array(vec![JsonValue::Number(0x78), JsonValue::Number(0xD8)])),
+            ("labels", JsonValue::Array(vec![JsonValue::Object(vec![("ok", JsonValue::Bool(true))])])),
+            ("comment", JsonValue::Null),
+            ("empty", JsonValue::Array(Vec::new())),
+        ]);
+
+        assert_eq!(value.format(), concat!(
+            "{\n",
+            "  \"name\": \"RESET\",\n",
+            "  \"bytes\": [120, 216],\n",
+            "  \"labels\": [\n",
+            "    {\n",
+            "      \"ok\": true\n",
+            "    }\n",
+            "  ],\n",
+            "  \"comment\": null,\n",
+            "  \"empty\": []\n",
+            "}\n",
+        ));
+    }
+
+    #[test]
+    fn escapes_strings() {
+        assert_eq!(escape_json("a \"b\"\\c\nd\u{1}"), r#""a \"b\"\\c\nd\u0001""#);
+    }
+}
@@ -1,17 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::symbols::SymbolTable;
 
 const BRANCH_LABEL_PREFIX: &str = "branch_target";
 const JUMP_LABEL_PREFIX: &str = "jump_target";
 const SUBROUTINE_LABEL_PREFIX: &str = "subroutine";
+const ENTRY_POINT_LABEL_PREFIX: &str = "entry_point";
+const PROBABLE_CODE_LABEL_PREFIX: &str = "probable_code";
+const DATA_LABEL_PREFIX: &str = "data";
 
 pub struct Labeller {
     next_branch_target_id: usize,
     next_jump_target_id: usize,
     next_subroutine_id: usize,
+    next_entry_point_id: usize,
+    next_probable_code_id: usize,
+    next_data_id: usize,
 
     branch_targets_to_labels: HashMap<usize, String>,
     jump_targets_to_labels: HashMap<usize, String>,
     subroutines_to_labels: HashMap<usize, String>,
+    entry_points_to_labels: HashMap<usize, String>,
+    probable_code_to_labels: HashMap<usize, String>,
+    data_to_labels: HashMap<usize, String>,
+
+    symbols: SymbolTable,
+    write_only_registers: HashMap<usize, String>,
 }
 
 // ---------------------------------------------------------------------------
@@ -22,20 +36,70 @@ impl Labeller {
             next_branch_target_id: 0,
             next_jump_target_id: 0,
             next_subroutine_id: 0,
+            next_entry_point_id: 0,
+            next_probable_code_id: 0,
+            next_data_id: 0,
 
             branch_targets_to_labels: HashMap::new(),
             jump_targets_to_labels: HashMap::new(),
             subroutines_to_labels: HashMap::new(),
+            entry_points_to_labels: HashMap::new(),
+            probable_code_to_labels: HashMap::new(),
+            data_to_labels: HashMap::new(),
+
+            symbols: SymbolTable::new(),
+            write_only_registers: HashMap::new(),
         }
     }
 
     // -----------------------------------------------------------------------
 
+    /// Merges user-supplied names into the labeller. These take precedence over the generated
+    /// `branch_target_N`/`jump_target_N`/`subroutine_N` labels.
+    pub fn add_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols.extend(symbols);
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the user's name for `address`, including `name+offset` for addresses inside a
+    /// multi-byte symbol.
+    pub fn get_symbol_name(&self, address: usize) -> Option<String> {
+        self.symbols.get_name(address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Names a register that only responds to writes, such as a sound chip register mapped over
+    /// ROM. Reads and jumps still see the ROM underneath, so only stores use this name.
+    pub fn add_write_only_register(&mut self, address: usize, name: &str) {
+        self.write_only_registers.insert(address, name.to_string());
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn get_write_only_register_name(&self, address: usize) -> Option<&String> {
+        self.write_only_registers.get(&address)
+    }
+
+    // -----------------------------------------------------------------------
+
     pub fn request_label_for_branch_target(&mut self, address: usize) -> String {
         if let Some(existing_label) = self.branch_targets_to_labels.get(&address) {
             return existing_label.clone();
         }
 
+        if let Some(symbol) = self.symbols.get_exact(address) {
+            self.branch_targets_to_labels.insert(address, symbol.name.clone());
+            return symbol.name.clone();
+        }
+
         let label_id = self.next_branch_target_id;
         self.next_branch_target_id += 1;
 
@@ -52,6 +116,11 @@ impl Labeller {
             return existing_label.clone();
         }
 
+        if let Some(symbol) = self.symbols.get_exact(address) {
+            self.jump_targets_to_labels.insert(address, symbol.name.clone());
+            return symbol.name.clone();
+        }
+
         let label_id = self.next_jump_target_id;
         self.next_jump_target_id += 1;
 
@@ -68,6 +137,11 @@ impl Labeller {
             return existing_label.clone();
         }
 
+        if let Some(symbol) = self.symbols.get_exact(address) {
+            self.subroutines_to_labels.insert(address, symbol.name.clone());
+            return symbol.name.clone();
+        }
+
         let label_id = self.next_subroutine_id;
         self.next_subroutine_id += 1;
 
@@ -79,6 +153,74 @@ impl Labeller {
 
     // -----------------------------------------------------------------------
 
+    /// Labels code found by means other than following the program's own control flow, such as
+    /// a code/data log.
+    pub fn request_label_for_entry_point(&mut self, address: usize) -> String {
+        if let Some(existing_label) = self.entry_points_to_labels.get(&address) {
+            return existing_label.clone();
+        }
+
+        if let Some(symbol) = self.symbols.get_exact(address) {
+            self.entry_points_to_labels.insert(address, symbol.name.clone());
+            return symbol.name.clone();
+        }
+
+        let label_id = self.next_entry_point_id;
+        self.next_entry_point_id += 1;
+
+        let label = format!("{ENTRY_POINT_LABEL_PREFIX}_{label_id}");
+        self.entry_points_to_labels.insert(address, label.clone());
+
+        label
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Labels code that was only guessed at by sweeping over otherwise unexplained bytes, so it
+    /// stands out from code that's known to be reached.
+    pub fn request_label_for_probable_code(&mut self, address: usize) -> String {
+        if let Some(existing_label) = self.probable_code_to_labels.get(&address) {
+            return existing_label.clone();
+        }
+
+        if let Some(symbol) = self.symbols.get_exact(address) {
+            self.probable_code_to_labels.insert(address, symbol.name.clone());
+            return symbol.name.clone();
+        }
+
+        let label_id = self.next_probable_code_id;
+        self.next_probable_code_id += 1;
+
+        let label = format!("{PROBABLE_CODE_LABEL_PREFIX}_{label_id}");
+        self.probable_code_to_labels.insert(address, label.clone());
+
+        label
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Labels data that a pointer table points at.
+    pub fn request_label_for_data(&mut self, address: usize) -> String {
+        if let Some(existing_label) = self.data_to_labels.get(&address) {
+            return existing_label.clone();
+        }
+
+        if let Some(symbol) = self.symbols.get_exact(address) {
+            self.data_to_labels.insert(address, symbol.name.clone());
+            return symbol.name.clone();
+        }
+
+        let label_id = self.next_data_id;
+        self.next_data_id += 1;
+
+        let label = format!("{DATA_LABEL_PREFIX}_{label_id}");
+        self.data_to_labels.insert(address, label.clone());
+
+        label
+    }
+
+    // -----------------------------------------------------------------------
+
     pub fn get_branch_target_label(&self, address: usize) -> Option<&String> {
         self.branch_targets_to_labels.get(&address)
     }
@@ -94,4 +236,64 @@ impl Labeller {
     pub fn get_subroutine_label(&self, address: usize) -> Option<&String> {
         self.subroutines_to_labels.get(&address)
     }
+
+    // -----------------------------------------------------------------------
+
+    pub fn get_entry_point_label(&self, address: usize) -> Option<&String> {
+        self.entry_points_to_labels.get(&address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn get_probable_code_label(&self, address: usize) -> Option<&String> {
+        self.probable_code_to_labels.get(&address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn get_data_label(&self, address: usize) -> Option<&String> {
+        self.data_to_labels.get(&address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn has_label(&self, address: usize) -> bool {
+        self.branch_targets_to_labels.contains_key(&address)
+            || self.jump_targets_to_labels.contains_key(&address)
+            || self.subroutines_to_labels.contains_key(&address)
+            || self.entry_points_to_labels.contains_key(&address)
+            || self.probable_code_to_labels.contains_key(&address)
+            || self.data_to_labels.contains_key(&address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn labelled_addresses(&self) -> BTreeSet<usize> {
+        self.branch_targets_to_labels.keys()
+            .chain(self.jump_targets_to_labels.keys())
+            .chain(self.subroutines_to_labels.keys())
+            .chain(self.entry_points_to_labels.keys())
+            .chain(self.probable_code_to_labels.keys())
+            .chain(self.data_to_labels.keys())
+            .copied()
+            .collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn subroutine_addresses(&self) -> BTreeSet<usize> {
+        self.subroutines_to_labels.keys().copied().collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn entry_point_addresses(&self) -> BTreeSet<usize> {
+        self.entry_points_to_labels.keys().copied().collect()
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn probable_code_addresses(&self) -> BTreeSet<usize> {
+        self.probable_code_to_labels.keys().copied().collect()
+    }
 }
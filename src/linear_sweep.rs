@@ -0,0 +1,239 @@
+use crate::opcodes::{decode_opcode, AddressingMode};
+
+// A linear sweep over the PRG ROM bytes that neither tracing nor the code/data log explained.
+// Every offset in a gap is tried as the start of a run of instructions that ends in RTS, RTI or
+// JMP without hitting an unknown opcode or BRK. Runs are then scored on how much they look like
+// real code: calls and jumps into known code, branches landing on their own instructions and
+// accesses to the PPU and APU registers count for a run, while targets outside of PRG ROM,
+// branches into the middle of an instruction and repetitive filler count against it.
+
+pub const MIN_SCORE: i32 = 10;
+
+const MAX_RUN_INSTRUCTIONS: usize = 64;
+
+// Every run gets this for ending in RTS, RTI or JMP, which random bytes rarely manage for long. It
+// biases the sweep towards short routines: eight instructions with nothing against them are just
+// enough to reach MIN_SCORE.
+const RUN_END_BONUS: i32 = 2;
+
+#[derive(Debug, PartialEq)]
+pub struct ProbableCode {
+    pub offset: usize,
+    pub bytes: usize,
+    pub score: i32,
+}
+
+struct Run {
+    instruction_offsets: Vec<usize>,
+    end_offset: usize,
+}
+
+// ---------------------------------------------------------------------------
+
+/// Finds the runs of probable code among the offsets not yet `explored`. `is_known_code` tells
+/// whether an address is the start of an instruction found some other way. `base_address` is
+/// where offset 0 is mapped, and `address_to_offset` finds the offset any CPU address reads from,
+/// including mirrors of the PRG ROM.
+pub fn find_probable_code(
+    prg_rom_contents: &[u8],
+    explored: &[bool],
+    base_address: usize,
+    address_to_offset: &dyn Fn(usize) -> Option<usize>,
+    is_known_code: &dyn Fn(usize) -> bool,
+) -> Vec<ProbableCode> {
+    let mut probable_code = Vec::new();
+
+    let mut offset = 0;
+    while offset < prg_rom_contents.len() {
+        if explored[offset] {
+            offset += 1;
+            continue;
+        }
+
+        let Some(run) = decode_run(prg_rom_contents, explored, offset) else {
+            offset += 1;
+            continue;
+        };
+
+        let score = score_run(prg_rom_contents, &run, base_address, address_to_offset, is_known_code);
+        if score < MIN_SCORE {
+            offset += 1;
+            continue;
+        }
+
+        probable_code.push(ProbableCode {
+            offset,
+            bytes: run.end_offset - offset,
+            score,
+        });
+        offset = run.end_offset;
+    }
+
+    probable_code
+}
+
+// ---------------------------------------------------------------------------
+
+/// Decodes instructions from `offset` until one ends the run, giving up if anything doesn't decode
+/// or spills out of the gap.
+fn decode_run(prg_rom_contents: &[u8], explored: &[bool], offset: usize) -> Option<Run> {
+    let mut instruction_offsets = Vec::new();
+    let mut current_offset = offset;
+
+    while instruction_offsets.len() < MAX_RUN_INSTRUCTIONS {
+        let decoded_opcode = decode_opcode(*prg_rom_contents.get(current_offset)?)?;
+        if decoded_opcode.mnemonic == "BRK" {
+            return None;
+        }
+
+        let end_offset = current_offset + decoded_opcode.addressing_mode.bytes_count();
+        if end_offset > prg_rom_contents.len() || explored[current_offset..end_offset].iter().any(|is_explored| *is_explored) {
+            return None;
+        }
+
+        instruction_offsets.push(current_offset);
+        current_offset = end_offset;
+
+        if matches!(decoded_opcode.mnemonic, "RTS" | "RTI" | "JMP") {
+            return Some(Run {
+                instruction_offsets,
+                end_offset,
+            });
+        }
+    }
+
+    None
+}
+
+// ---------------------------------------------------------------------------
+
+fn score_run(
+    prg_rom_contents: &[u8],
+    run: &Run,
+    base_address: usize,
+    address_to_offset: &dyn Fn(usize) -> Option<usize>,
+    is_known_code: &dyn Fn(usize) -> bool,
+) -> i32 {
+    let run_start_offset = run.instruction_offsets[0];
+
+    let mut score = run.instruction_offsets.len() as i32;
+
+    let mut repeated_instruction_count = 0;
+    for (index, offset) in run.instruction_offsets.iter().enumerate() {
+        let opcode = prg_rom_contents[*offset];
+        let Some(decoded_opcode) = decode_opcode(opcode) else {
+            continue;
+        };
+        let operand1 = prg_rom_contents.get(offset + 1).copied().unwrap_or(0) as usize;
+        let operand2 = prg_rom_contents.get(offset + 2).copied().unwrap_or(0) as usize;
+        let absolute_address = (operand2 << 8) | operand1;
+
+        if index > 0 && opcode == prg_rom_contents[run.instruction_offsets[index - 1]] {
+            repeated_instruction_count += 1;
+        }
+
+        match decoded_opcode.addressing_mode {
+            AddressingMode::Relative => {
+                let target_offset = (offset + 2).checked_add_signed(operand1 as u8 as i8 as isize);
+                match target_offset {
+                    Some(target_offset) if run.instruction_offsets.contains(&target_offset) || is_known_code(base_address + target_offset) => {
+                        score += 2;
+                    },
+                    Some(target_offset) if target_offset >= run_start_offset && target_offset < run.end_offset => score -= 5,
+                    _ => score -= 2,
+                }
+            },
+            AddressingMode::Absolute if matches!(decoded_opcode.mnemonic, "JSR" | "JMP") => {
+                match address_to_offset(absolute_address) {
+                    Some(target_offset) if run.instruction_offsets.contains(&target_offset) || is_known_code(base_address + target_offset) => {
+                        score += 4;
+                    },
+                    Some(_) => {},
+                    None => score -= 5,
+                }
+            },
+            AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY => {
+                let is_register = (0x2000..=0x2007).contains(&absolute_address) || (0x4000..=0x4017).contains(&absolute_address);
+                if is_register {
+                    score += 2;
+                } else if decoded_opcode.mnemonic.starts_with("ST") && address_to_offset(absolute_address).is_some() {
+                    score -= 2;
+                } else if absolute_address < 0x0100 {
+                    // Assemblers use zero page addressing for these, so real code rarely does this.
+                    score -= 2;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Runs of the same instruction are usually padding or table data.
+    if repeated_instruction_count * 2 >= run.instruction_offsets.len() {
+        score -= 20;
+    }
+
+    score + RUN_END_BONUS
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Maps $8000-$FFFF onto 32 KiB of PRG ROM, as NROM-256 does.
+    fn prg_rom_offset(address: usize) -> Option<usize> {
+        address.checked_sub(0x8000).filter(|offset| *offset < 0x8000)
+    }
+
+    #[test]
+    fn finds_code_calling_known_code() {
+        // $8000: .byte $FF, $FF
+        // $8002: LDA #$00, STA $2001, JSR $9000, LDA $12, BNE $800A, RTS
+        let prg_rom_contents = [0xFF, 0xFF, 0xA9, 0x00, 0x8D, 0x01, 0x20, 0x20, 0x00, 0x90, 0xA5, 0x12, 0xD0, 0xFC, 0x60];
+        let explored = vec![false; prg_rom_contents.len()];
+        let is_known_code = |address: usize| address == 0x9000;
+
+        let probable_code = find_probable_code(&prg_rom_contents, &explored, 0x8000, &prg_rom_offset, &is_known_code);
+        assert_eq!(probable_code.len(), 1);
+        assert_eq!((probable_code[0].offset, probable_code[0].bytes), (2, 13));
+    }
+
+    #[test]
+    fn skips_explored_bytes_and_filler() {
+        // NOP padding ending in an RTS.
+        let prg_rom_contents = [0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0x60];
+        let explored = vec![false; prg_rom_contents.len()];
+        assert!(find_probable_code(&prg_rom_contents, &explored, 0x8000, &prg_rom_offset, &|_| false).is_empty());
+
+        // A plausible run, but its RTS was already explored.
+        let prg_rom_contents = [0xA9, 0x00, 0x8D, 0x01, 0x20, 0x20, 0x00, 0x90, 0x60];
+        let mut explored = vec![false; prg_rom_contents.len()];
+        explored[8] = true;
+        assert!(find_probable_code(&prg_rom_contents, &explored, 0x8000, &prg_rom_offset, &|address| address == 0x9000).is_empty());
+    }
+
+    #[test]
+    fn rejects_targets_outside_prg_rom() {
+        // JSR $0345, JMP $1234
+        let prg_rom_contents = [0x20, 0x45, 0x03, 0x4C, 0x34, 0x12];
+        let explored = vec![false; prg_rom_contents.len()];
+        assert!(find_probable_code(&prg_rom_contents, &explored, 0x8000, &prg_rom_offset, &|_| false).is_empty());
+    }
+
+    #[test]
+    fn resolves_targets_through_mirrors_of_prg_rom() {
+        // 16 KiB at $C000, mirrored at $8000:
+        // $C000: .byte $FF
+        // $C001: LDA $12, BEQ $C007, JSR $8001, LDA #$01, RTS
+        let mut prg_rom_contents = vec![0xFF; 0x4000];
+        prg_rom_contents[1..12].copy_from_slice(&[0xA5, 0x12, 0xF0, 0x03, 0x20, 0x01, 0x80, 0xA9, 0x01, 0x60, 0xFF]);
+        let mut explored = vec![true; prg_rom_contents.len()];
+        explored[..11].fill(false);
+        let mirrored_prg_rom_offset = |address: usize| address.checked_sub(0x8000).map(|offset| offset % 0x4000);
+
+        let probable_code = find_probable_code(&prg_rom_contents, &explored, 0xC000, &mirrored_prg_rom_offset, &|_| false);
+        assert_eq!(probable_code.len(), 1);
+        assert_eq!((probable_code[0].offset, probable_code[0].bytes), (1, 10));
+    }
+}
@@ -1,22 +1,412 @@
-use std::{env, process::ExitCode};
+use std::{env, path::Path, process::ExitCode};
 
 use crate::cartridge::Cartridge;
+use crate::emulator::EmulationOptions;
+use crate::header_database::HeaderDatabase;
+use crate::nes_header::{Mirroring, NesHeader, Timing};
+use crate::project::Project;
+use crate::symbols::SymbolTable;
 
+mod annotations;
+mod browser;
+mod call_graph;
 mod cartridge;
+mod checksums;
+mod code_data_log;
+mod control_flow;
+mod convert;
+mod debug_info;
+mod emulator;
+mod fceux;
+mod fds;
+mod header_database;
+mod html;
 mod instruction;
+mod json;
 mod labeller;
+mod linear_sweep;
+mod mesen;
+mod nes_header;
+mod nsf;
+mod opcodes;
+mod project;
+mod register_writes;
+mod return_address;
+mod stack_usage;
+mod symbols;
+mod timing;
+mod unif;
 
 fn main() -> ExitCode {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} cartridge_file", args[0]);
+    if args.get(1).is_some_and(|arg| arg == "convert") {
+        return run_convert(&args);
+    }
+    let is_browsing = args.get(1).is_some_and(|arg| arg == "browse");
+
+    let mut cartridge_filename = None;
+    let mut symbols_filename = None;
+    let mut mesen_label_filenames = Vec::new();
+    let mut fceux_name_list_filenames = Vec::new();
+    let mut should_export_fceux_name_lists = false;
+    let mut debug_info_filename = None;
+    let mut annotations_filename = None;
+    let mut code_data_log_filename = None;
+    let mut saved_code_data_log_filename = None;
+    let mut emulation_frame_count = None;
+    let mut nmi_interval = 1;
+    let mut should_show_cycle_counts = false;
+    let mut should_sweep_for_code = false;
+    let mut dot_directory = None;
+    let mut output_directory = None;
+    let mut json_filename = None;
+    let mut html_directory = None;
+    let mut should_print_call_graph_report = false;
+    let mut should_print_stack_usage_report = false;
+    let mut disk_side = 0;
+    let mut should_identify = false;
+    let mut database_filename = None;
+    let mut should_use_database_header = false;
+    let mut should_load_project = true;
+
+    let mut arg_index = if is_browsing { 2 } else { 1 };
+    while arg_index < args.len() {
+        match args[arg_index].as_str() {
+            "--symbols" if arg_index + 1 < args.len() => {
+                symbols_filename = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--mlb" if arg_index + 1 < args.len() => {
+                mesen_label_filenames.push(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--nl" if arg_index + 1 < args.len() => {
+                fceux_name_list_filenames.push(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--dbg" if arg_index + 1 < args.len() => {
+                debug_info_filename = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--annotations" if arg_index + 1 < args.len() => {
+                annotations_filename = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--cdl" if arg_index + 1 < args.len() => {
+                code_data_log_filename = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--save-cdl" if arg_index + 1 < args.len() => {
+                saved_code_data_log_filename = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--emulate" if arg_index + 1 < args.len() => {
+                let Ok(frame_count) = args[arg_index + 1].parse() else {
+                    print_usage(&args[0]);
+                    return ExitCode::FAILURE;
+                };
+                emulation_frame_count = Some(frame_count);
+                arg_index += 1;
+            },
+            "--nmi-interval" if arg_index + 1 < args.len() => {
+                let Ok(interval) = args[arg_index + 1].parse() else {
+                    print_usage(&args[0]);
+                    return ExitCode::FAILURE;
+                };
+                nmi_interval = interval;
+                arg_index += 1;
+            },
+            "--disk-side" if arg_index + 1 < args.len() => {
+                let Ok(side) = args[arg_index + 1].parse::<usize>() else {
+                    print_usage(&args[0]);
+                    return ExitCode::FAILURE;
+                };
+                if side == 0 {
+                    print_usage(&args[0]);
+                    return ExitCode::FAILURE;
+                }
+                disk_side = side - 1;
+                arg_index += 1;
+            },
+            "--identify" => {
+                should_identify = true;
+            },
+            "--database" if arg_index + 1 < args.len() => {
+                database_filename = Some(&args[arg_index + 1]);
+                should_identify = true;
+                arg_index += 1;
+            },
+            "--use-database-header" => {
+                should_identify = true;
+                should_use_database_header = true;
+            },
+            "--no-project" => {
+                should_load_project = false;
+            },
+            "--cycles" => {
+                should_show_cycle_counts = true;
+            },
+            "--sweep" => {
+                should_sweep_for_code = true;
+            },
+            "--dot" if arg_index + 1 < args.len() => {
+                dot_directory = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--output-dir" if arg_index + 1 < args.len() => {
+                output_directory = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--json" if arg_index + 1 < args.len() => {
+                json_filename = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--html" if arg_index + 1 < args.len() => {
+                html_directory = Some(&args[arg_index + 1]);
+                arg_index += 1;
+            },
+            "--call-report" => {
+                should_print_call_graph_report = true;
+            },
+            "--stack-report" => {
+                should_print_stack_usage_report = true;
+            },
+            "--export-nl" => {
+                should_export_fceux_name_lists = true;
+            },
+            filename if cartridge_filename.is_none() && !filename.starts_with("--") => {
+                cartridge_filename = Some(filename);
+            },
+            _ => {
+                print_usage(&args[0]);
+                return ExitCode::FAILURE;
+            },
+        }
+        arg_index += 1;
+    }
+
+    let Some(cartridge_filename) = cartridge_filename else {
+        print_usage(&args[0]);
         return ExitCode::FAILURE;
+    };
+
+    // Browsing loads the ROM again whenever a name or comment changes, so this is kept separate
+    // from disassembling it.
+    let load_cartridge = || {
+        let lowercase_filename = cartridge_filename.to_lowercase();
+        let mut cartridge = if lowercase_filename.ends_with(".fds") {
+            Cartridge::load_from_fds_file(cartridge_filename, disk_side)
+        } else if lowercase_filename.ends_with(".nsf") || lowercase_filename.ends_with(".nsfe") {
+            Cartridge::load_from_nsf_file(cartridge_filename)
+        } else if lowercase_filename.ends_with(".unf") || lowercase_filename.ends_with(".unif") {
+            Cartridge::load_from_unif_file(cartridge_filename)
+        } else {
+            Cartridge::load_from_file(cartridge_filename)
+        };
+        if should_identify {
+            let database = match database_filename {
+                Some(database_filename) => HeaderDatabase::load_from_file(database_filename),
+                None => HeaderDatabase::built_in(),
+            };
+            cartridge.identify(&database, should_use_database_header);
+        }
+        if let Some(symbols_filename) = symbols_filename {
+            cartridge.load_symbols(SymbolTable::load_from_file(symbols_filename));
+        }
+        for mesen_label_filename in &mesen_label_filenames {
+            cartridge.load_mesen_labels(mesen_label_filename);
+        }
+        for fceux_name_list_filename in &fceux_name_list_filenames {
+            cartridge.load_fceux_name_list(fceux_name_list_filename);
+        }
+        if let Some(debug_info_filename) = debug_info_filename {
+            cartridge.load_debug_info(debug_info_filename);
+        }
+        if let Some(annotations_filename) = annotations_filename {
+            cartridge.load_annotations(annotations_filename);
+        }
+        if let Some(code_data_log_filename) = code_data_log_filename {
+            cartridge.load_code_data_log(code_data_log_filename);
+        }
+        if should_show_cycle_counts {
+            cartridge.enable_cycle_counts();
+        }
+        if should_sweep_for_code {
+            cartridge.enable_linear_sweep();
+        }
+        if let Some(frame_count) = emulation_frame_count {
+            cartridge.enable_emulation(EmulationOptions { frame_count, nmi_interval });
+        }
+        cartridge
+    };
+
+    if is_browsing {
+        browser::browse(cartridge_filename, &load_cartridge, should_load_project);
+        return ExitCode::SUCCESS;
     }
 
-    let cartridge_filename = &args[1];
-    let mut cartridge = Cartridge::load_from_file(cartridge_filename);
+    let mut cartridge = load_cartridge();
+    let project_filename = Project::filename_for(cartridge_filename);
+    if should_load_project && Path::new(&project_filename).exists() {
+        cartridge.load_project(&Project::load_from_file(&project_filename));
+    }
     cartridge.disassemble();
-    cartridge.print_disassembly();
+    match output_directory {
+        Some(output_directory) => cartridge.export_disassembly(output_directory, cartridge_filename),
+        None => cartridge.print_disassembly(),
+    }
+    cartridge.print_segment_report();
+    if let Some(json_filename) = json_filename {
+        cartridge.export_json(json_filename);
+    }
+    if let Some(html_directory) = html_directory {
+        cartridge.export_html(html_directory, cartridge_filename);
+    }
+    if should_print_call_graph_report {
+        cartridge.print_call_graph_report();
+    }
+    if should_print_stack_usage_report {
+        cartridge.print_stack_usage_report();
+    }
+
+    if let Some(saved_code_data_log_filename) = saved_code_data_log_filename {
+        cartridge.save_code_data_log(saved_code_data_log_filename);
+    }
+    if let Some(dot_directory) = dot_directory {
+        cartridge.export_control_flow_graphs(dot_directory);
+    }
+    if should_export_fceux_name_lists {
+        cartridge.export_fceux_name_lists(cartridge_filename);
+    }
 
-    return ExitCode::SUCCESS;
+    ExitCode::SUCCESS
+}
+
+// ---------------------------------------------------------------------------
+
+fn run_convert(args: &[String]) -> ExitCode {
+    match args.get(2).map(String::as_str) {
+        Some("nes2") if args.len() == 5 => convert::upgrade_to_nes2(&args[3], &args[4]),
+        Some("split") if args.len() == 5 => convert::split_rom(&args[3], &args[4]),
+        Some("join") if args.len() >= 5 => {
+            let Some((header, chr_filename)) = parse_join_options(&args[4..args.len() - 1]) else {
+                print_usage(&args[0]);
+                return ExitCode::FAILURE;
+            };
+
+            convert::join_rom(&args[3], chr_filename, &header, &args[args.len() - 1]);
+        },
+        _ => {
+            print_usage(&args[0]);
+            return ExitCode::FAILURE;
+        },
+    }
+
+    ExitCode::SUCCESS
+}
+
+// ---------------------------------------------------------------------------
+
+/// Parses the options of `convert join` between the PRG file and the output file, returning the
+/// header they describe and the CHR file, or `None` if any of them is invalid.
+fn parse_join_options(option_args: &[String]) -> Option<(NesHeader, Option<&str>)> {
+    let mut header = NesHeader {
+        is_nes2: false,
+        mapper_id: 0,
+        submapper_id: 0,
+        prg_rom_bytes: 0,
+        chr_rom_bytes: 0,
+        mirroring: Mirroring::Horizontal,
+        has_battery: false,
+        has_trainer: false,
+        prg_ram_bytes: 0,
+        prg_nvram_bytes: 0,
+        chr_ram_bytes: 0,
+        chr_nvram_bytes: 0,
+        timing: Timing::Ntsc,
+    };
+    let mut chr_filename = None;
+
+    let mut arg_index = 0;
+    while arg_index < option_args.len() {
+        let value = option_args.get(arg_index + 1).map(String::as_str);
+        let number = value.and_then(|value| value.parse::<usize>().ok());
+        let mut takes_value = true;
+        match (option_args[arg_index].as_str(), value, number) {
+            ("--chr", Some(value), _) => chr_filename = Some(value),
+            ("--mapper", _, Some(number)) if number <= 0xFFF => header.mapper_id = number as u16,
+            ("--submapper", _, Some(number)) if number <= 0x0F => header.submapper_id = number as u8,
+            ("--prg-ram", _, Some(number)) => header.prg_ram_bytes = number,
+            ("--prg-nvram", _, Some(number)) => header.prg_nvram_bytes = number,
+            ("--chr-ram", _, Some(number)) => header.chr_ram_bytes = number,
+            ("--mirroring", Some("h"), _) => header.mirroring = Mirroring::Horizontal,
+            ("--mirroring", Some("v"), _) => header.mirroring = Mirroring::Vertical,
+            ("--mirroring", Some("4"), _) => header.mirroring = Mirroring::FourScreen,
+            ("--battery", _, _) => {
+                header.has_battery = true;
+                takes_value = false;
+            },
+            ("--pal", _, _) => {
+                header.timing = Timing::Pal;
+                takes_value = false;
+            },
+            ("--nes2", _, _) => {
+                header.is_nes2 = true;
+                takes_value = false;
+            },
+            _ => return None,
+        }
+        arg_index += if takes_value { 2 } else { 1 };
+    }
+
+    Some((header, chr_filename))
+}
+
+// ---------------------------------------------------------------------------
+
+fn print_usage(program_name: &str) {
+    eprintln!("Usage: {program_name} cartridge_file [--symbols symbol_file] [--mlb mesen_label_file]... [--nl fceux_name_list_file]... [--disk-side side] [--identify] [--database nes20db_file] [--use-database-header] [--no-project] [--dbg ld65_debug_file] [--annotations annotation_file] [--cdl code_data_log_file] [--emulate frames [--nmi-interval frames]] [--save-cdl code_data_log_file] [--cycles] [--sweep] [--dot directory] [--output-dir directory] [--json json_file] [--html directory] [--call-report] [--stack-report] [--export-nl]");
+    eprintln!("       {program_name} browse cartridge_file [options]");
+    eprintln!("         (NSF and NSFe music rips are disassembled with their initial bank mapping only)");
+    eprintln!("       {program_name} convert nes2 input.nes output.nes");
+    eprintln!("       {program_name} convert split input.nes output_prefix");
+    eprintln!("       {program_name} convert join prg_file [--chr chr_file] [--mapper id] [--submapper id] [--mirroring h|v|4] [--battery] [--prg-ram bytes] [--prg-nvram bytes] [--chr-ram bytes] [--pal] [--nes2] output.nes");
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parses_join_options() {
+        let option_args = args("--chr game.chr --mapper 4 --submapper 1 --mirroring v --battery --prg-ram 8192 --prg-nvram 0 --chr-ram 0 --pal --nes2");
+        let (header, chr_filename) = parse_join_options(&option_args).unwrap();
+
+        assert_eq!(chr_filename, Some("game.chr"));
+        assert_eq!((header.mapper_id, header.submapper_id), (4, 1));
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert!(header.has_battery && header.is_nes2);
+        assert_eq!(header.prg_ram_bytes, 8192);
+        assert_eq!(header.timing, Timing::Pal);
+
+        let (header, chr_filename) = parse_join_options(&[]).unwrap();
+        assert_eq!(chr_filename, None);
+        assert_eq!((header.mapper_id, header.mirroring, header.is_nes2), (0, Mirroring::Horizontal, false));
+    }
+
+    #[test]
+    fn rejects_bad_join_options() {
+        assert!(parse_join_options(&args("--mapper 4096")).is_none());
+        assert!(parse_join_options(&args("--submapper 16")).is_none());
+        assert!(parse_join_options(&args("--mapper")).is_none());
+        assert!(parse_join_options(&args("--mirroring x")).is_none());
+        assert!(parse_join_options(&args("--chr")).is_none());
+        assert!(parse_join_options(&args("--speed 2")).is_none());
+    }
 }
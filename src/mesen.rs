@@ -0,0 +1,151 @@
+use std::fs;
+
+use crate::symbols::parse_hex_address;
+
+// Mesen label files (.mlb) have one label per line:
+//
+//     Type:Address[-EndAddress]:Label[:Comment]
+//
+// Mesen 1 uses single-letter memory types, while Mesen 2 spells them out. Addresses are hex
+// offsets into the given memory type, and comments use `\n` for line breaks. Either the label or
+// the comment may be empty.
+
+#[derive(Debug, PartialEq)]
+pub enum MesenMemoryType {
+    PrgRom,
+    InternalRam,
+    WorkRam,
+    SaveRam,
+    Register,
+}
+
+pub struct MesenLabel {
+    pub memory_type: MesenMemoryType,
+    pub start_offset: usize,
+    pub end_offset: usize,
+    pub label: Option<String>,
+    pub comment: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn load_mlb_file(filename: &str) -> Vec<MesenLabel> {
+    let contents = match fs::read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(error) => panic!("[ERROR] Could not read Mesen label file: {error}"),
+    };
+
+    match parse_mlb(&contents) {
+        Ok(labels) => labels,
+        Err(error) => panic!("[ERROR] Could not parse Mesen label file {filename}: {error}"),
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn parse_mlb(contents: &str) -> Result<Vec<MesenLabel>, String> {
+    let mut labels = Vec::new();
+
+    for (line_index, line) in contents.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let mut fields = line.splitn(4, ':');
+        let memory_type_field = fields.next().unwrap_or_default();
+        let address_field = fields.next().unwrap_or_default();
+        let label_field = fields.next().unwrap_or_default();
+        let comment_field = fields.next().unwrap_or_default();
+
+        let Some(memory_type) = parse_memory_type(memory_type_field) else {
+            // Labels for memory we don't disassemble (CHR, palette, etc.) are skipped.
+            continue;
+        };
+
+        let (start_field, end_field) = address_field.split_once('-').unwrap_or((address_field, address_field));
+        let (Some(start_offset), Some(end_offset)) = (parse_hex_address(start_field), parse_hex_address(end_field)) else {
+            return Err(format!("line {}: invalid address `{address_field}`", line_index + 1));
+        };
+        if end_offset < start_offset {
+            return Err(format!("line {}: address range `{address_field}` ends before it starts", line_index + 1));
+        }
+
+        let label = if label_field.is_empty() { None } else { Some(label_field.to_string()) };
+        let comment = if comment_field.is_empty() { None } else { Some(comment_field.replace("\\n", "\n")) };
+        if label.is_none() && comment.is_none() {
+            continue;
+        }
+
+        labels.push(MesenLabel {
+            memory_type,
+            start_offset,
+            end_offset,
+            label,
+            comment,
+        });
+    }
+
+    Ok(labels)
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_memory_type(text: &str) -> Option<MesenMemoryType> {
+    match text {
+        "P" | "NesPrgRom" => Some(MesenMemoryType::PrgRom),
+        "R" | "NesInternalRam" => Some(MesenMemoryType::InternalRam),
+        "W" | "NesWorkRam" => Some(MesenMemoryType::WorkRam),
+        "S" | "NesSaveRam" => Some(MesenMemoryType::SaveRam),
+        "G" | "NesMemory" | "Register" => Some(MesenMemoryType::Register),
+        _ => None,
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mesen1_labels() {
+        let labels = parse_mlb("P:0123:ReadJoypad:Reads both controllers\nR:0010-0011:ptr\n").unwrap();
+
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[0].memory_type, MesenMemoryType::PrgRom);
+        assert_eq!(labels[0].start_offset, 0x123);
+        assert_eq!(labels[0].label.as_deref(), Some("ReadJoypad"));
+        assert_eq!(labels[0].comment.as_deref(), Some("Reads both controllers"));
+        assert_eq!(labels[1].memory_type, MesenMemoryType::InternalRam);
+        assert_eq!((labels[1].start_offset, labels[1].end_offset), (0x10, 0x11));
+        assert!(labels[1].comment.is_none());
+    }
+
+    #[test]
+    fn parses_mesen2_labels_and_comment_only_entries() {
+        let labels = parse_mlb("NesWorkRam:0000:save_slot\nNesPrgRom:7FFA::vectors\\nthree of them\n").unwrap();
+
+        assert_eq!(labels[0].memory_type, MesenMemoryType::WorkRam);
+        assert!(labels[1].label.is_none());
+        assert_eq!(labels[1].comment.as_deref(), Some("vectors\nthree of them"));
+    }
+
+    #[test]
+    fn comments_may_contain_colons() {
+        let labels = parse_mlb("P:0000:Reset:note: waits for vblank\n").unwrap();
+        assert_eq!(labels[0].comment.as_deref(), Some("note: waits for vblank"));
+    }
+
+    #[test]
+    fn skips_unsupported_memory_types() {
+        let labels = parse_mlb("NesChrRom:0000:tiles\n").unwrap();
+        assert!(labels.is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_addresses() {
+        assert!(parse_mlb("P:zz:label\n").is_err());
+        assert!(parse_mlb("P:0010-0001:label\n").is_err());
+    }
+}
@@ -0,0 +1,337 @@
+// The 16 byte header in front of .nes files, in both the original iNES format and NES 2.0:
+//
+//     0-3  `NES\x1A`
+//     4    PRG ROM size in 16 KiB units (low byte in NES 2.0)
+//     5    CHR ROM size in 8 KiB units (low byte in NES 2.0)
+//     6    mapper bits 0-3, four screen, trainer, battery and vertical mirroring flags
+//     7    mapper bits 4-7, with bits 2-3 set to %10 for NES 2.0
+//     8    NES 2.0: mapper bits 8-11 and submapper; iNES: PRG RAM size in 8 KiB units
+//     9    NES 2.0: PRG and CHR ROM size high nibbles; iNES: PAL flag
+//     10   NES 2.0: PRG RAM and PRG NVRAM sizes as shift counts of 64 bytes
+//     11   NES 2.0: CHR RAM and CHR NVRAM sizes, the same way
+//     12   NES 2.0: CPU/PPU timing
+//
+// https://www.nesdev.org/wiki/INES https://www.nesdev.org/wiki/NES_2.0
+
+pub const NES_HEADER_BYTES: usize = 16;
+pub const TRAINER_BYTES: usize = 512;
+pub const PRG_ROM_BANK_BYTES: usize = 16384;
+pub const CHR_ROM_BANK_BYTES: usize = 8192;
+
+const NES_MAGIC: &[u8] = b"NES\x1A";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultipleRegion,
+    Dendy,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NesHeader {
+    pub is_nes2: bool,
+    pub mapper_id: u16,
+    pub submapper_id: u8,
+    pub prg_rom_bytes: usize,
+    pub chr_rom_bytes: usize,
+    pub mirroring: Mirroring,
+    pub has_battery: bool,
+    pub has_trainer: bool,
+    pub prg_ram_bytes: usize,
+    pub prg_nvram_bytes: usize,
+    pub chr_ram_bytes: usize,
+    pub chr_nvram_bytes: usize,
+    pub timing: Timing,
+}
+
+pub struct NesFile<'a> {
+    pub header: NesHeader,
+    pub trainer: &'a [u8],
+    pub prg_rom_contents: &'a [u8],
+    pub chr_rom_contents: &'a [u8],
+}
+
+// ---------------------------------------------------------------------------
+
+impl Mirroring {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mirroring::Horizontal => "horizontal",
+            Mirroring::Vertical => "vertical",
+            Mirroring::FourScreen => "four screen",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl Timing {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Timing::Ntsc => "NTSC",
+            Timing::Pal => "PAL",
+            Timing::MultipleRegion => "multiple region",
+            Timing::Dendy => "Dendy",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl NesHeader {
+    pub fn parse(header: &[u8]) -> Result<Self, String> {
+        if header.len() < NES_HEADER_BYTES || !header.starts_with(NES_MAGIC) {
+            return Err(String::from("missing NES\\x1A signature"));
+        }
+
+        let mirroring = if header[6] & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if header[6] & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let mapper_id = ((header[7] & 0xF0) | (header[6] >> 4)) as u16;
+        let has_battery = header[6] & 0x02 != 0;
+        let has_trainer = header[6] & 0x04 != 0;
+
+        if header[7] & 0x0C != 0x08 {
+            // The PRG RAM size is rarely filled in, so a battery is taken to mean the 8 KiB most
+            // boards with one have.
+            let prg_ram_bytes = header[8] as usize * 8192;
+            let prg_nvram_bytes = if has_battery { prg_ram_bytes.max(8192) } else { 0 };
+
+            return Ok(Self {
+                is_nes2: false,
+                mapper_id,
+                submapper_id: 0,
+                prg_rom_bytes: header[4] as usize * PRG_ROM_BANK_BYTES,
+                chr_rom_bytes: header[5] as usize * CHR_ROM_BANK_BYTES,
+                mirroring,
+                has_battery,
+                has_trainer,
+                prg_ram_bytes: if has_battery { 0 } else { prg_ram_bytes },
+                prg_nvram_bytes,
+                chr_ram_bytes: if header[5] == 0 { CHR_ROM_BANK_BYTES } else { 0 },
+                chr_nvram_bytes: 0,
+                timing: if header[9] & 0x01 != 0 { Timing::Pal } else { Timing::Ntsc },
+            });
+        }
+
+        Ok(Self {
+            is_nes2: true,
+            mapper_id: mapper_id | ((header[8] & 0x0F) as u16) << 8,
+            submapper_id: header[8] >> 4,
+            prg_rom_bytes: parse_rom_size(header[4], header[9] & 0x0F, PRG_ROM_BANK_BYTES),
+            chr_rom_bytes: parse_rom_size(header[5], header[9] >> 4, CHR_ROM_BANK_BYTES),
+            mirroring,
+            has_battery,
+            has_trainer,
+            prg_ram_bytes: parse_ram_size(header[10] & 0x0F),
+            prg_nvram_bytes: parse_ram_size(header[10] >> 4),
+            chr_ram_bytes: parse_ram_size(header[11] & 0x0F),
+            chr_nvram_bytes: parse_ram_size(header[11] >> 4),
+            timing: match header[12] & 0x03 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                2 => Timing::MultipleRegion,
+                _ => Timing::Dendy,
+            },
+        })
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Encodes the header as NES 2.0 if `is_nes2` is set, otherwise as iNES. ROM sizes have to be
+    /// whole banks and RAM sizes powers of two from 128 bytes.
+    pub fn to_bytes(&self) -> Result<[u8; NES_HEADER_BYTES], String> {
+        if !self.prg_rom_bytes.is_multiple_of(PRG_ROM_BANK_BYTES) || !self.chr_rom_bytes.is_multiple_of(CHR_ROM_BANK_BYTES) {
+            return Err(String::from("ROM sizes have to be whole 16 KiB PRG and 8 KiB CHR banks"));
+        }
+        let prg_rom_banks = self.prg_rom_bytes / PRG_ROM_BANK_BYTES;
+        let chr_rom_banks = self.chr_rom_bytes / CHR_ROM_BANK_BYTES;
+
+        let mut header = [0u8; NES_HEADER_BYTES];
+        header[..4].copy_from_slice(NES_MAGIC);
+        header[6] = ((self.mapper_id & 0x0F) as u8) << 4;
+        header[6] |= match self.mirroring {
+            Mirroring::Horizontal => 0x00,
+            Mirroring::Vertical => 0x01,
+            Mirroring::FourScreen => 0x08,
+        };
+        if self.has_battery {
+            header[6] |= 0x02;
+        }
+        if self.has_trainer {
+            header[6] |= 0x04;
+        }
+        header[7] = (self.mapper_id & 0xF0) as u8;
+
+        if !self.is_nes2 {
+            if self.mapper_id > 0xFF || prg_rom_banks > 0xFF || chr_rom_banks > 0xFF {
+                return Err(String::from("mapper or ROM size is too large for an iNES header"));
+            }
+            header[4] = prg_rom_banks as u8;
+            header[5] = chr_rom_banks as u8;
+            header[8] = ((self.prg_ram_bytes + self.prg_nvram_bytes) / 8192) as u8;
+            header[9] = if self.timing == Timing::Pal { 0x01 } else { 0x00 };
+            return Ok(header);
+        }
+
+        if self.mapper_id > 0xFFF || self.submapper_id > 0x0F || prg_rom_banks > 0xEFF || chr_rom_banks > 0xEFF {
+            return Err(String::from("mapper, submapper or ROM size is too large for an NES 2.0 header"));
+        }
+        header[4] = prg_rom_banks as u8;
+        header[5] = chr_rom_banks as u8;
+        header[7] |= 0x08;
+        header[8] = ((self.mapper_id >> 8) as u8) | (self.submapper_id << 4);
+        header[9] = ((prg_rom_banks >> 8) as u8) | (((chr_rom_banks >> 8) as u8) << 4);
+        header[10] = encode_ram_size(self.prg_ram_bytes)? | encode_ram_size(self.prg_nvram_bytes)? << 4;
+        header[11] = encode_ram_size(self.chr_ram_bytes)? | encode_ram_size(self.chr_nvram_bytes)? << 4;
+        header[12] = match self.timing {
+            Timing::Ntsc => 0,
+            Timing::Pal => 1,
+            Timing::MultipleRegion => 2,
+            Timing::Dendy => 3,
+        };
+
+        Ok(header)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Splits the contents of a .nes file into its header, trainer, PRG ROM and CHR ROM.
+pub fn parse_nes_file(contents: &[u8]) -> Result<NesFile<'_>, String> {
+    let header = NesHeader::parse(contents)?;
+
+    let trainer_bytes = if header.has_trainer { TRAINER_BYTES } else { 0 };
+    let prg_rom_start = NES_HEADER_BYTES + trainer_bytes;
+    let chr_rom_start = prg_rom_start + header.prg_rom_bytes;
+    let chr_rom_end = chr_rom_start + header.chr_rom_bytes;
+    if contents.len() < chr_rom_end {
+        return Err(format!("file is {} bytes, but the header needs {chr_rom_end}", contents.len()));
+    }
+
+    Ok(NesFile {
+        trainer: &contents[NES_HEADER_BYTES..prg_rom_start],
+        prg_rom_contents: &contents[prg_rom_start..chr_rom_start],
+        chr_rom_contents: &contents[chr_rom_start..chr_rom_end],
+        header,
+    })
+}
+
+// ---------------------------------------------------------------------------
+
+/// Decodes an NES 2.0 ROM size, where a high nibble of $F means the low byte holds an exponent
+/// and multiplier instead of a bank count.
+fn parse_rom_size(low_byte: u8, high_nibble: u8, bank_bytes: usize) -> usize {
+    if high_nibble == 0x0F {
+        let exponent = (low_byte >> 2) as u32;
+        let multiplier = (low_byte & 0x03) as usize * 2 + 1;
+        return 2usize.checked_pow(exponent).unwrap_or(0) * multiplier;
+    }
+
+    ((high_nibble as usize) << 8 | low_byte as usize) * bank_bytes
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_ram_size(shift_count: u8) -> usize {
+    if shift_count == 0 { 0 } else { 64 << shift_count }
+}
+
+// ---------------------------------------------------------------------------
+
+fn encode_ram_size(bytes: usize) -> Result<u8, String> {
+    if bytes == 0 {
+        return Ok(0);
+    }
+
+    (1..=0x0F)
+        .find(|shift_count| 64 << shift_count == bytes)
+        .ok_or_else(|| format!("RAM size of {bytes} bytes can't be given in an NES 2.0 header"))
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ines_header() {
+        let header = NesHeader::parse(b"NES\x1A\x02\x01\x13\x00\x00\x01\x00\x00\x00\x00\x00\x00").unwrap();
+
+        assert!(!header.is_nes2);
+        assert_eq!(header.mapper_id, 1);
+        assert_eq!((header.prg_rom_bytes, header.chr_rom_bytes), (32768, 8192));
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert!(header.has_battery);
+        assert_eq!((header.prg_ram_bytes, header.prg_nvram_bytes), (0, 8192));
+        assert_eq!(header.timing, Timing::Pal);
+    }
+
+    #[test]
+    fn round_trips_nes2_header() {
+        let header = NesHeader {
+            is_nes2: true,
+            mapper_id: 0x123,
+            submapper_id: 2,
+            prg_rom_bytes: 0x101 * PRG_ROM_BANK_BYTES,
+            chr_rom_bytes: 0,
+            mirroring: Mirroring::FourScreen,
+            has_battery: false,
+            has_trainer: true,
+            prg_ram_bytes: 8192,
+            prg_nvram_bytes: 0,
+            chr_ram_bytes: 32768,
+            chr_nvram_bytes: 0,
+            timing: Timing::Dendy,
+        };
+
+        let bytes = header.to_bytes().unwrap();
+        assert_eq!(&bytes[..4], NES_MAGIC);
+        assert_eq!(bytes[7] & 0x0C, 0x08);
+        assert_eq!(NesHeader::parse(&bytes).unwrap(), header);
+    }
+
+    #[test]
+    fn decodes_exponent_rom_sizes() {
+        // 2^10 * 3 bytes of PRG ROM.
+        let header = NesHeader::parse(b"NES\x1A\x29\x00\x00\x08\x00\x0F\x00\x00\x00\x00\x00\x00").unwrap();
+        assert_eq!(header.prg_rom_bytes, 3072);
+    }
+
+    #[test]
+    fn splits_file_past_trainer() {
+        let mut contents = b"NES\x1A\x01\x01\x04\x00\x00\x00\x00\x00\x00\x00\x00\x00".to_vec();
+        contents.extend_from_slice(&[0x11; TRAINER_BYTES]);
+        contents.extend_from_slice(&[0x22; PRG_ROM_BANK_BYTES]);
+        contents.extend_from_slice(&[0x33; CHR_ROM_BANK_BYTES]);
+
+        let nes_file = parse_nes_file(&contents).unwrap();
+        assert_eq!(nes_file.trainer.len(), TRAINER_BYTES);
+        assert!(nes_file.prg_rom_contents.iter().all(|byte| *byte == 0x22));
+        assert!(nes_file.chr_rom_contents.iter().all(|byte| *byte == 0x33));
+
+        assert!(parse_nes_file(&contents[..contents.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_unencodable_sizes() {
+        let mut header = NesHeader::parse(b"NES\x1A\x01\x01\x00\x08\x00\x00\x00\x00\x00\x00\x00\x00").unwrap();
+        header.prg_ram_bytes = 3000;
+        assert!(header.to_bytes().is_err());
+
+        assert!(NesHeader::parse(b"UNIF").is_err());
+    }
+}
@@ -0,0 +1,331 @@
+// NSF music rips hold the 6502 code and data of a game's sound engine behind a 128 byte header:
+//
+//     $00  `NESM\x1A`, version, song count, starting song
+//     $06  load, init and play addresses
+//     $0E  title, artist and copyright, 32 bytes each
+//     $70  initial values of the bank registers at $5FF8-$5FFF, all zeros if not bankswitched
+//     $7A  PAL/NTSC flags and expansion sound chip flags
+//
+// NSFe files hold the same things in chunks (length, four letter id, data) after `NSFE`. Chunks
+// whose id starts with an uppercase letter can't be skipped by a player that doesn't understand
+// them, while the others are optional. https://www.nesdev.org/wiki/NSF https://www.nesdev.org/wiki/NSFe
+//
+// When bankswitched, the data is split into 4 KiB banks, with the first one padded by the low 12
+// bits of the load address, and each of $8000-$FFFF is mapped to the bank written to $5FF8-$5FFF.
+
+pub const NSF_HEADER_BYTES: usize = 128;
+pub const BANK_BYTES: usize = 0x1000;
+
+const NSF_MAGIC: &[u8] = b"NESM\x1A";
+const NSFE_MAGIC: &[u8] = b"NSFE";
+
+/// The bank registers, along with the 4 KiB of the address space each one switches. $5FF6 and
+/// $5FF7 only exist with the FDS sound chip, which maps RAM at $6000-$DFFF.
+pub const BANK_REGISTERS: &[(usize, &str, usize)] = &[
+    (0x5FF6, "NsfBank6000_5FF6", 0x6000),
+    (0x5FF7, "NsfBank7000_5FF7", 0x7000),
+    (0x5FF8, "NsfBank8000_5FF8", 0x8000),
+    (0x5FF9, "NsfBank9000_5FF9", 0x9000),
+    (0x5FFA, "NsfBankA000_5FFA", 0xA000),
+    (0x5FFB, "NsfBankB000_5FFB", 0xB000),
+    (0x5FFC, "NsfBankC000_5FFC", 0xC000),
+    (0x5FFD, "NsfBankD000_5FFD", 0xD000),
+    (0x5FFE, "NsfBankE000_5FFE", 0xE000),
+    (0x5FFF, "NsfBankF000_5FFF", 0xF000),
+];
+
+/// A register's address and name, along with how many bytes it covers.
+pub type Register = (usize, &'static str, usize);
+
+/// The expansion sound chips by their header flag, along with their registers.
+pub const EXPANSION_CHIPS: &[(u8, &str, &[Register])] = &[
+    (0x01, "VRC6", &[
+        (0x9000, "Vrc6Pulse1Control_9000", 1),
+        (0x9001, "Vrc6Pulse1FreqLow_9001", 1),
+        (0x9002, "Vrc6Pulse1FreqHigh_9002", 1),
+        (0x9003, "Vrc6FreqControl_9003", 1),
+        (0xA000, "Vrc6Pulse2Control_A000", 1),
+        (0xA001, "Vrc6Pulse2FreqLow_A001", 1),
+        (0xA002, "Vrc6Pulse2FreqHigh_A002", 1),
+        (0xB000, "Vrc6SawAccumRate_B000", 1),
+        (0xB001, "Vrc6SawFreqLow_B001", 1),
+        (0xB002, "Vrc6SawFreqHigh_B002", 1),
+    ]),
+    (0x02, "VRC7", &[
+        (0x9010, "Vrc7AudioRegSelect_9010", 1),
+        (0x9030, "Vrc7AudioRegWrite_9030", 1),
+    ]),
+    (0x04, "FDS", &[
+        (0x4040, "FdsWaveTable_4040", 64),
+        (0x4080, "FdsVolumeEnvelope_4080", 1),
+        (0x4082, "FdsFreqLow_4082", 1),
+        (0x4083, "FdsFreqHigh_4083", 1),
+        (0x4084, "FdsModEnvelope_4084", 1),
+        (0x4085, "FdsModCounter_4085", 1),
+        (0x4086, "FdsModFreqLow_4086", 1),
+        (0x4087, "FdsModFreqHigh_4087", 1),
+        (0x4088, "FdsModTable_4088", 1),
+        (0x4089, "FdsWaveWrite_4089", 1),
+        (0x408A, "FdsEnvelopeSpeed_408A", 1),
+        (0x4090, "FdsVolumeGain_4090", 1),
+        (0x4092, "FdsModGain_4092", 1),
+    ]),
+    (0x08, "MMC5", &[
+        (0x5000, "Mmc5Pulse1Duty_5000", 1),
+        (0x5002, "Mmc5Pulse1Timer_5002", 1),
+        (0x5003, "Mmc5Pulse1Length_5003", 1),
+        (0x5004, "Mmc5Pulse2Duty_5004", 1),
+        (0x5006, "Mmc5Pulse2Timer_5006", 1),
+        (0x5007, "Mmc5Pulse2Length_5007", 1),
+        (0x5010, "Mmc5PcmControl_5010", 1),
+        (0x5011, "Mmc5PcmData_5011", 1),
+        (0x5015, "Mmc5AudioStatus_5015", 1),
+        (0x5205, "Mmc5MultiplierLow_5205", 1),
+        (0x5206, "Mmc5MultiplierHigh_5206", 1),
+        (0x5C00, "Mmc5ExRam_5C00", 1024),
+    ]),
+    (0x10, "N163", &[
+        (0x4800, "N163Data_4800", 1),
+        (0xF800, "N163Address_F800", 1),
+    ]),
+    (0x20, "Sunsoft 5B", &[
+        (0xC000, "S5bAudioRegSelect_C000", 1),
+        (0xE000, "S5bAudioRegWrite_E000", 1),
+    ]),
+];
+
+pub struct NsfFile {
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub song_count: u8,
+    pub starting_song: u8,
+    pub load_address: usize,
+    pub init_address: usize,
+    pub play_address: usize,
+    pub initial_banks: Option<[u8; 8]>,
+    pub expansion_chips: u8,
+    pub data: Vec<u8>,
+    pub skipped_chunks: Vec<String>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl NsfFile {
+    /// Returns the names of the expansion sound chips the header asks for.
+    pub fn expansion_chip_names(&self) -> Vec<&'static str> {
+        EXPANSION_CHIPS.iter()
+            .filter(|(flag, _, _)| self.expansion_chips & flag != 0)
+            .map(|(_, name, _)| *name)
+            .collect()
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn is_nsf_file(contents: &[u8]) -> bool {
+    contents.starts_with(NSF_MAGIC) || contents.starts_with(NSFE_MAGIC)
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn parse_nsf(contents: &[u8]) -> Result<NsfFile, String> {
+    if contents.starts_with(NSF_MAGIC) {
+        parse_nsf_header(contents)
+    } else if contents.starts_with(NSFE_MAGIC) {
+        parse_nsfe_chunks(contents)
+    } else {
+        Err(String::from("missing NESM or NSFE signature"))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Describes a value written to one of the bank registers, or returns None for other addresses.
+pub fn describe_bank_register_write(address: u16, value: u8) -> Option<String> {
+    let (_, _, bank_address) = BANK_REGISTERS.iter().find(|(register, _, _)| *register == address as usize)?;
+    Some(format!("NSF bank {value} at ${bank_address:04X}"))
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_nsf_header(contents: &[u8]) -> Result<NsfFile, String> {
+    if contents.len() < NSF_HEADER_BYTES {
+        return Err(String::from("header is truncated"));
+    }
+
+    let word_at = |offset: usize| (contents[offset + 1] as usize) << 8 | contents[offset] as usize;
+    let mut initial_banks = [0u8; 8];
+    initial_banks.copy_from_slice(&contents[0x70..0x78]);
+
+    Ok(NsfFile {
+        title: parse_string(&contents[0x0E..0x2E]),
+        artist: parse_string(&contents[0x2E..0x4E]),
+        copyright: parse_string(&contents[0x4E..0x6E]),
+        song_count: contents[0x06],
+        starting_song: contents[0x07],
+        load_address: word_at(0x08),
+        init_address: word_at(0x0A),
+        play_address: word_at(0x0C),
+        initial_banks: if initial_banks.iter().any(|bank| *bank != 0) { Some(initial_banks) } else { None },
+        expansion_chips: contents[0x7B],
+        data: contents[NSF_HEADER_BYTES..].to_vec(),
+        skipped_chunks: Vec::new(),
+    })
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_nsfe_chunks(contents: &[u8]) -> Result<NsfFile, String> {
+    let mut nsf_file = NsfFile {
+        title: String::new(),
+        artist: String::new(),
+        copyright: String::new(),
+        song_count: 1,
+        starting_song: 1,
+        load_address: 0,
+        init_address: 0,
+        play_address: 0,
+        initial_banks: None,
+        expansion_chips: 0,
+        data: Vec::new(),
+        skipped_chunks: Vec::new(),
+    };
+    let mut has_info = false;
+    let mut has_data = false;
+
+    let mut offset = NSFE_MAGIC.len();
+    while offset < contents.len() {
+        let Some(chunk_header) = contents.get(offset..offset + 8) else {
+            return Err(format!("chunk header at ${offset:X} is truncated"));
+        };
+        let length = u32::from_le_bytes([chunk_header[0], chunk_header[1], chunk_header[2], chunk_header[3]]) as usize;
+        let id = String::from_utf8_lossy(&chunk_header[4..8]).to_string();
+        let Some(chunk) = contents.get(offset + 8..offset + 8 + length) else {
+            return Err(format!("chunk {id} runs off the end of the file"));
+        };
+        offset += 8 + length;
+
+        match id.as_str() {
+            "INFO" => {
+                if chunk.len() < 8 {
+                    return Err(String::from("INFO chunk is too short"));
+                }
+                let word_at = |offset: usize| (chunk[offset + 1] as usize) << 8 | chunk[offset] as usize;
+                nsf_file.load_address = word_at(0);
+                nsf_file.init_address = word_at(2);
+                nsf_file.play_address = word_at(4);
+                nsf_file.expansion_chips = chunk[7];
+                nsf_file.song_count = chunk.get(8).copied().unwrap_or(1);
+                nsf_file.starting_song = chunk.get(9).map_or(1, |song| song + 1);
+                has_info = true;
+            },
+            "DATA" => {
+                nsf_file.data = chunk.to_vec();
+                has_data = true;
+            },
+            "BANK" => {
+                let mut initial_banks = [0u8; 8];
+                let bank_count = chunk.len().min(8);
+                initial_banks[..bank_count].copy_from_slice(&chunk[..bank_count]);
+                nsf_file.initial_banks = Some(initial_banks);
+            },
+            "auth" => {
+                let mut strings = chunk.split(|byte| *byte == 0).map(parse_string);
+                nsf_file.title = strings.next().unwrap_or_default();
+                nsf_file.artist = strings.next().unwrap_or_default();
+                nsf_file.copyright = strings.next().unwrap_or_default();
+            },
+            "NEND" => break,
+            _ if id.starts_with(|letter: char| letter.is_ascii_uppercase()) => {
+                return Err(format!("required chunk {id} isn't supported"));
+            },
+            _ => nsf_file.skipped_chunks.push(id),
+        }
+    }
+
+    if !has_info {
+        return Err(String::from("missing INFO chunk"));
+    }
+    if !has_data {
+        return Err(String::from("missing DATA chunk"));
+    }
+
+    Ok(nsf_file)
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_nsf(initial_banks: [u8; 8], expansion_chips: u8, data: &[u8]) -> Vec<u8> {
+        let mut contents = NSF_MAGIC.to_vec();
+        contents.extend_from_slice(&[1, 3, 1, 0x00, 0x80, 0x03, 0x80, 0x06, 0x80]);
+        contents.resize(0x0E, 0);
+        contents.extend_from_slice(b"Song");
+        contents.resize(0x70, 0);
+        contents.extend_from_slice(&initial_banks);
+        contents.resize(0x7B, 0);
+        contents.push(expansion_chips);
+        contents.resize(NSF_HEADER_BYTES, 0);
+        contents.extend_from_slice(data);
+        contents
+    }
+
+    #[test]
+    fn parses_nsf_header() {
+        let nsf_file = parse_nsf(&create_nsf([0; 8], 0x21, &[0x60; 9])).unwrap();
+
+        assert_eq!(nsf_file.title, "Song");
+        assert_eq!(nsf_file.song_count, 3);
+        assert_eq!((nsf_file.load_address, nsf_file.init_address, nsf_file.play_address), (0x8000, 0x8003, 0x8006));
+        assert_eq!(nsf_file.initial_banks, None);
+        assert_eq!(nsf_file.expansion_chip_names(), vec!["VRC6", "Sunsoft 5B"]);
+        assert_eq!(nsf_file.data.len(), 9);
+
+        let nsf_file = parse_nsf(&create_nsf([0, 1, 2, 3, 0, 1, 2, 3], 0, &[0x60; 9])).unwrap();
+        assert_eq!(nsf_file.initial_banks, Some([0, 1, 2, 3, 0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn parses_nsfe_chunks() {
+        let mut contents = NSFE_MAGIC.to_vec();
+        let mut push_chunk = |id: &[u8], data: &[u8]| {
+            contents.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            contents.extend_from_slice(id);
+            contents.extend_from_slice(data);
+        };
+        push_chunk(b"INFO", &[0x00, 0x80, 0x03, 0x80, 0x06, 0x80, 0, 0x04, 5, 1]);
+        push_chunk(b"tlbl", b"Title\0");
+        push_chunk(b"auth", b"Game\0Composer\0Company\0Ripper\0");
+        push_chunk(b"DATA", &[0x60; 9]);
+        push_chunk(b"NEND", &[]);
+
+        let nsf_file = parse_nsf(&contents).unwrap();
+        assert_eq!((nsf_file.title.as_str(), nsf_file.artist.as_str()), ("Game", "Composer"));
+        assert_eq!((nsf_file.song_count, nsf_file.starting_song), (5, 2));
+        assert_eq!(nsf_file.play_address, 0x8006);
+        assert_eq!(nsf_file.expansion_chip_names(), vec!["FDS"]);
+        assert_eq!(nsf_file.skipped_chunks, vec!["tlbl"]);
+        assert_eq!(nsf_file.data.len(), 9);
+    }
+
+    #[test]
+    fn rejects_unknown_required_chunks() {
+        let mut contents = NSFE_MAGIC.to_vec();
+        contents.extend_from_slice(&[0, 0, 0, 0]);
+        contents.extend_from_slice(b"XTRA");
+
+        assert!(parse_nsf(&contents).is_err_and(|error| error.contains("XTRA")));
+        assert!(parse_nsf(b"NESM\x1A\x01").is_err());
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use crate::annotations::{parse_annotation, Annotation};
+use crate::symbols::parse_hex_address;
+
+// Project files keep everything learned about a ROM, so each run picks up where the last one left
+// off. They live next to the ROM as `<rom>.nesdis.toml` and are a small subset of TOML, with hex
+// addresses as keys:
+//
+//     [labels]
+//     8000 = "Reset"
+//     0012 = "level_pointer"
+//
+//     [comments]
+//     8020 = "Reads the level pointer"
+//
+//     [entry_points]            code only reached through pointers, with an optional name
+//     9123 = "UpdatePlayer"
+//     9200 = ""
+//
+//     [code]                    regions traced as code, up to and including the end address
+//     A000 = 0xA0FF
+//
+//     [data]                    regions never traced as code
+//     B000 = 0xB3FF
+//
+//     [banks]                   the 16 KiB PRG bank in each window, for mappers beyond NROM
+//     8000 = 5
+//     C000 = 7
+//
+//     [annotations]             written the same way as in an annotation file
+//     8E04 = "pointer-table 4"
+//     C000 = "records 16 x y tile attributes"
+//
+//     [charmap]                 the characters text bytes decode to, from the byte given
+//     0A = "ABCDEFGHIJKLMNOPQRSTUVWXYZ"
+//
+// Strings may use the `\"`, `\\`, `\n` and `\t` escapes. Numbers are decimal, or hex with a `0x`
+// prefix. Lines starting with `#` are ignored.
+
+const SECTION_NAMES: &[&str] = &["labels", "comments", "entry_points", "code", "data", "banks", "annotations", "charmap"];
+
+pub struct Project {
+    pub labels: BTreeMap<usize, String>,
+    pub comments: BTreeMap<usize, String>,
+    pub entry_points: BTreeMap<usize, String>,
+    pub code_regions: BTreeMap<usize, usize>,
+    pub data_regions: BTreeMap<usize, usize>,
+    pub banks: BTreeMap<usize, usize>,
+    pub annotations: BTreeMap<usize, Annotation>,
+    pub character_map: BTreeMap<usize, String>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl Project {
+    pub fn new() -> Self {
+        Self {
+            labels: BTreeMap::new(),
+            comments: BTreeMap::new(),
+            entry_points: BTreeMap::new(),
+            code_regions: BTreeMap::new(),
+            data_regions: BTreeMap::new(),
+            banks: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+            character_map: BTreeMap::new(),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn filename_for(cartridge_filename: &str) -> String {
+        format!("{cartridge_filename}.nesdis.toml")
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Loads the project file, or starts an empty project if there isn't one yet.
+    pub fn load_or_new(filename: &str) -> Self {
+        if Path::new(filename).exists() { Self::load_from_file(filename) } else { Self::new() }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_from_file(filename: &str) -> Self {
+        let contents = match fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read project file: {error}"),
+        };
+
+        match Self::parse(&contents) {
+            Ok(project) => project,
+            Err(error) => panic!("[ERROR] Could not parse project file {filename}: {error}"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut project = Self::new();
+
+        let mut section = None;
+        for (line_index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(section_name) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                let section_name = section_name.trim();
+                if !SECTION_NAMES.contains(&section_name) {
+                    return Err(format!("line {}: unknown section `{section_name}`", line_index + 1));
+                }
+                section = Some(section_name);
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!("line {}: expected `address = value`", line_index + 1));
+            };
+            let Some(address) = parse_hex_address(key.trim()) else {
+                return Err(format!("line {}: invalid address `{}`", line_index + 1, key.trim()));
+            };
+            let Some(section) = section else {
+                return Err(format!("line {}: value outside of a section", line_index + 1));
+            };
+
+            project.insert(section, address, value.trim()).map_err(|error| format!("line {}: {error}", line_index + 1))?;
+        }
+
+        Ok(project)
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn insert(&mut self, section: &str, address: usize, value: &str) -> Result<(), String> {
+        match section {
+            "labels" => {
+                self.labels.insert(address, parse_string(value)?);
+            },
+            "comments" => {
+                self.comments.insert(address, parse_string(value)?);
+            },
+            "entry_points" => {
+                self.entry_points.insert(address, parse_string(value)?);
+            },
+            "code" | "data" => {
+                let end_address = parse_number(value)?;
+                if end_address < address {
+                    return Err(format!("region ends at ${end_address:04X}, before it starts"));
+                }
+                let regions = if section == "code" { &mut self.code_regions } else { &mut self.data_regions };
+                regions.insert(address, end_address);
+            },
+            "banks" => {
+                if address != 0x8000 && address != 0xC000 {
+                    return Err(format!("banks are mapped at 8000 or C000, not {address:04X}"));
+                }
+                self.banks.insert(address, parse_number(value)?);
+            },
+            "annotations" => {
+                self.annotations.insert(address, parse_annotation(&parse_string(value)?)?);
+            },
+            "charmap" => {
+                if address > 0xFF {
+                    return Err(format!("character map keys are bytes, not {address:04X}"));
+                }
+                self.character_map.insert(address, parse_string(value)?);
+            },
+            section => return Err(format!("unknown section `{section}`")),
+        }
+
+        Ok(())
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn format(&self) -> String {
+        let mut contents = String::from("# nesdis project\n");
+
+        let string_sections = [("labels", &self.labels), ("comments", &self.comments), ("entry_points", &self.entry_points)];
+        for (section_name, section) in string_sections {
+            format_section(&mut contents, section_name, section, |value| format_string(value));
+        }
+        format_section(&mut contents, "code", &self.code_regions, |end_address| format!("0x{end_address:04X}"));
+        format_section(&mut contents, "data", &self.data_regions, |end_address| format!("0x{end_address:04X}"));
+        format_section(&mut contents, "banks", &self.banks, |bank| bank.to_string());
+        format_section(&mut contents, "annotations", &self.annotations, |annotation| format_string(&annotation.format()));
+        format_section(&mut contents, "charmap", &self.character_map, |characters| format_string(characters));
+
+        contents
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn save_to_file(&self, filename: &str) {
+        if let Err(error) = fs::write(filename, self.format()) {
+            panic!("[ERROR] Could not write project file {filename}: {error}");
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// Writes a section, leaving it out when it's empty.
+fn format_section<T>(contents: &mut String, section_name: &str, section: &BTreeMap<usize, T>, format_value: impl Fn(&T) -> String) {
+    if section.is_empty() {
+        return;
+    }
+
+    contents.push_str(&format!("\n[{section_name}]\n"));
+    for (address, value) in section {
+        contents.push_str(&format!("{:04X} = {}\n", address, format_value(value)));
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    let number = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => usize::from_str_radix(digits, 16),
+        None => text.parse(),
+    };
+    number.map_err(|_| format!("expected a number, found `{text}`"))
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_string(text: &str) -> Result<String, String> {
+    let Some(quoted) = text.strip_prefix('"').and_then(|text| text.strip_suffix('"')) else {
+        return Err(format!("expected a quoted string, found `{text}`"));
+    };
+
+    let mut value = String::new();
+    let mut characters = quoted.chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            value.push(character);
+            continue;
+        }
+
+        match characters.next() {
+            Some('"') => value.push('"'),
+            Some('\\') => value.push('\\'),
+            Some('n') => value.push('\n'),
+            Some('t') => value.push('\t'),
+            escape => return Err(format!("unknown escape `\\{}`", escape.map(String::from).unwrap_or_default())),
+        }
+    }
+
+    Ok(value)
+}
+
+// ---------------------------------------------------------------------------
+
+fn format_string(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t");
+    format!("\"{escaped}\"")
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::{DataAnnotation, SubroutineAnnotation};
+
+    #[test]
+    fn parses_labels_and_comments() {
+        let project = Project::parse("# notes\n[labels]\n8000 = \"Reset\"\n$0012 = \"ptr\"\n\n[comments]\n8020 = \"say \\\"hi\\\"\\nthen leave\"\n").unwrap();
+
+        assert_eq!(project.labels[&0x8000], "Reset");
+        assert_eq!(project.labels[&0x12], "ptr");
+        assert_eq!(project.comments[&0x8020], "say \"hi\"\nthen leave");
+    }
+
+    #[test]
+    fn parses_regions_banks_and_annotations() {
+        let project = Project::parse("\
+[entry_points]
+9123 = \"UpdatePlayer\"
+9200 = \"\"
+
+[code]
+A000 = 0xA0FF
+
+[data]
+B000 = 0xB3FF
+
+[banks]
+8000 = 5
+C000 = 0x07
+
+[annotations]
+8E04 = \"pointer-table 4\"
+C000 = \"words 8\"
+
+[charmap]
+0A = \"ABC\"
+").unwrap();
+
+        assert_eq!(project.entry_points[&0x9123], "UpdatePlayer");
+        assert_eq!(project.entry_points[&0x9200], "");
+        assert_eq!(project.code_regions[&0xA000], 0xA0FF);
+        assert_eq!(project.data_regions[&0xB000], 0xB3FF);
+        assert_eq!(project.banks[&0x8000], 5);
+        assert_eq!(project.banks[&0xC000], 7);
+        assert_eq!(project.annotations[&0x8E04], Annotation::Subroutine(SubroutineAnnotation::PointerTable(Some(4))));
+        assert_eq!(project.annotations[&0xC000], Annotation::Data(DataAnnotation::Words(8)));
+        assert_eq!(project.character_map[&0x0A], "ABC");
+    }
+
+    #[test]
+    fn formats_what_it_parses() {
+        let mut project = Project::new();
+        project.labels.insert(0xC000, String::from("Table"));
+        project.comments.insert(0x12, String::from("a \\ b\t\"c\"\nd"));
+        project.entry_points.insert(0x9123, String::new());
+        project.code_regions.insert(0xA000, 0xA0FF);
+        project.data_regions.insert(0xB000, 0xB000);
+        project.banks.insert(0x8000, 12);
+        project.annotations.insert(0x8E04, Annotation::Subroutine(SubroutineAnnotation::InlineData(2)));
+        project.annotations.insert(0x8F00, Annotation::Subroutine(SubroutineAnnotation::PointerTable(None)));
+        project.annotations.insert(0xC000, Annotation::Data(DataAnnotation::SplitPointers { count: 4, high_address: 0xC004, is_code: true }));
+        project.character_map.insert(0x24, String::from(" !"));
+
+        let reparsed = Project::parse(&project.format()).unwrap();
+        assert_eq!(reparsed.labels, project.labels);
+        assert_eq!(reparsed.comments, project.comments);
+        assert_eq!(reparsed.entry_points, project.entry_points);
+        assert_eq!(reparsed.code_regions, project.code_regions);
+        assert_eq!(reparsed.data_regions, project.data_regions);
+        assert_eq!(reparsed.banks, project.banks);
+        assert_eq!(reparsed.annotations, project.annotations);
+        assert_eq!(reparsed.character_map, project.character_map);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(Project::parse("8000 = \"Reset\"\n").is_err_and(|error| error.contains("outside")));
+        assert!(Project::parse("[labels]\nxyz = \"Reset\"\n").is_err_and(|error| error.contains("address")));
+        assert!(Project::parse("[labels]\n8000 = Reset\n").is_err_and(|error| error.contains("quoted")));
+        assert!(Project::parse("[bogus]\n").is_err_and(|error| error.contains("bogus")));
+        assert!(Project::parse("[code]\nA000 = 0x9000\n").is_err_and(|error| error.contains("before")));
+        assert!(Project::parse("[banks]\nA000 = 1\n").is_err_and(|error| error.contains("8000 or C000")));
+        assert!(Project::parse("[banks]\n8000 = five\n").is_err_and(|error| error.contains("number")));
+        assert!(Project::parse("[annotations]\n8000 = \"sometimes\"\n").is_err_and(|error| error.contains("sometimes")));
+        assert!(Project::parse("[charmap]\n100 = \"A\"\n").is_err_and(|error| error.contains("bytes")));
+    }
+}
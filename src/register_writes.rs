@@ -0,0 +1,364 @@
+use crate::nsf::describe_bank_register_write;
+
+// Decodes values written to the PPU, APU and controller registers so the listing can show what
+// a write like `LDA #$90 / STA PpuControl_2000` actually does. Music rips also get their writes
+// to the NSF bank registers described.
+//
+// Bit layouts follow the NESdev wiki:
+// https://www.nesdev.org/wiki/PPU_registers
+// https://www.nesdev.org/wiki/APU_registers
+
+const LENGTH_COUNTER_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const SQUARE_DUTY_CYCLES: [&str; 4] = ["12.5%", "25%", "50%", "75%"];
+
+pub struct RegisterWriteTracker {
+    a: Option<u8>,
+    x: Option<u8>,
+    y: Option<u8>,
+    is_nsf: bool,
+}
+
+// ---------------------------------------------------------------------------
+
+impl RegisterWriteTracker {
+    /// Creates a tracker that also describes writes to the NSF bank registers if `is_nsf` is set,
+    /// since those addresses mean something else, or nothing, on cartridges.
+    pub fn new(is_nsf: bool) -> Self {
+        Self {
+            a: None,
+            x: None,
+            y: None,
+            is_nsf,
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn reset(&mut self) {
+        self.a = None;
+        self.x = None;
+        self.y = None;
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Feeds the next instruction in address order into the tracker, returning a description of
+    /// the value being written if the instruction stores a known immediate value into a register.
+    pub fn process_instruction(&mut self, opcode: u8, operand1: u8, operand2: u8) -> Option<String> {
+        let absolute_address = ((operand2 as u16) << 8) | (operand1 as u16);
+
+        match opcode {
+            // LDA/LDX/LDY immediate
+            0xA9 => self.a = Some(operand1),
+            0xA2 => self.x = Some(operand1),
+            0xA0 => self.y = Some(operand1),
+
+            // STA/STX/STY absolute
+            0x8D => return self.a.and_then(|value| self.describe_write(absolute_address, value)),
+            0x8E => return self.x.and_then(|value| self.describe_write(absolute_address, value)),
+            0x8C => return self.y.and_then(|value| self.describe_write(absolute_address, value)),
+
+            // TAX/TAY/TXA/TYA
+            0xAA => self.x = self.a,
+            0xA8 => self.y = self.a,
+            0x8A => self.a = self.x,
+            0x98 => self.a = self.y,
+
+            // Instructions that leave A, X and Y untouched: the remaining stores, flag changes,
+            // compares, BIT, pushes, branches and NOP.
+            0x85 | 0x95 | 0x9D | 0x99 | 0x81 | 0x91 | 0x86 | 0x96 | 0x84 | 0x94 |
+            0x18 | 0x38 | 0x58 | 0x78 | 0xB8 | 0xD8 | 0xF8 |
+            0xC9 | 0xC5 | 0xD5 | 0xCD | 0xDD | 0xD9 | 0xC1 | 0xD1 |
+            0xE0 | 0xE4 | 0xEC | 0xC0 | 0xC4 | 0xCC |
+            0x24 | 0x2C | 0x48 | 0x08 |
+            0x10 | 0x30 | 0x50 | 0x70 | 0x90 | 0xB0 | 0xD0 | 0xF0 |
+            0xEA => {},
+
+            _ => self.reset(),
+        }
+
+        None
+    }
+
+    // -----------------------------------------------------------------------
+
+    fn describe_write(&self, address: u16, value: u8) -> Option<String> {
+        if self.is_nsf && let Some(description) = describe_bank_register_write(address, value) {
+            return Some(description);
+        }
+        describe_register_write(address, value)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn describe_register_write(address: u16, value: u8) -> Option<String> {
+    let description = match address {
+        0x2000 => describe_ppu_control(value),
+        0x2001 => describe_ppu_mask(value),
+        0x2002 => String::from("read-only register, write has no effect"),
+        0x2003 => format!("OAM address ${value:02X}"),
+        0x2004 => format!("OAM data ${value:02X}"),
+        0x2005 => format!("scroll {value} (X on first write, Y on second)"),
+        0x2006 => format!("VRAM address byte ${value:02X} (high byte first)"),
+        0x2007 => format!("VRAM data ${value:02X}"),
+        0x4000 | 0x4004 => describe_square_duty(value),
+        0x4001 | 0x4005 => describe_square_sweep(value),
+        0x4002 | 0x4006 | 0x400A => format!("timer low ${value:02X}"),
+        0x4003 | 0x4007 | 0x400B => describe_length_and_timer_high(value),
+        0x4008 => describe_triangle_linear(value),
+        0x400C => describe_noise_volume(value),
+        0x400E => describe_noise_period(value),
+        0x400F => describe_length(value),
+        0x4010 => describe_dmc_frequency(value),
+        0x4011 => format!("DAC level {}", value & 0x7F),
+        0x4012 => format!("sample address ${:04X}", 0xC000 + (value as u16) * 64),
+        0x4013 => format!("sample length {} bytes", (value as u16) * 16 + 1),
+        0x4014 => format!("OAM DMA from ${value:02X}00"),
+        0x4015 => describe_apu_status(value),
+        0x4016 => describe_controller_strobe(value),
+        0x4017 => describe_frame_counter(value),
+        _ => return None,
+    };
+
+    Some(description)
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_ppu_control(value: u8) -> String {
+    let nametable = 0x2000 + (value as u16 & 0x03) * 0x400;
+
+    format!(
+        "NMI {}, sprites {}, BG table ${:04X}, sprite table ${:04X}, VRAM increment {}, nametable ${:04X}",
+        on_off(value & 0x80),
+        if value & 0x20 != 0 { "8x16" } else { "8x8" },
+        if value & 0x10 != 0 { 0x1000 } else { 0x0000 },
+        if value & 0x08 != 0 { 0x1000 } else { 0x0000 },
+        if value & 0x04 != 0 { 32 } else { 1 },
+        nametable,
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_ppu_mask(value: u8) -> String {
+    let mut emphasis = Vec::new();
+    if value & 0x20 != 0 { emphasis.push("red"); }
+    if value & 0x40 != 0 { emphasis.push("green"); }
+    if value & 0x80 != 0 { emphasis.push("blue"); }
+    let emphasis = if emphasis.is_empty() { String::from("none") } else { emphasis.join("+") };
+
+    format!(
+        "BG {}, sprites {}, BG left column {}, sprites left column {}, greyscale {}, emphasis {}",
+        on_off(value & 0x08),
+        on_off(value & 0x10),
+        on_off(value & 0x02),
+        on_off(value & 0x04),
+        on_off(value & 0x01),
+        emphasis,
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_square_duty(value: u8) -> String {
+    format!(
+        "duty {}, {}",
+        SQUARE_DUTY_CYCLES[(value >> 6) as usize],
+        describe_envelope(value),
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_envelope(value: u8) -> String {
+    let halt = if value & 0x20 != 0 { "length halted" } else { "length counting" };
+    if value & 0x10 != 0 {
+        format!("{halt}, constant volume {}", value & 0x0F)
+    } else {
+        format!("{halt}, envelope period {}", value & 0x0F)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_square_sweep(value: u8) -> String {
+    if value & 0x80 == 0 {
+        return String::from("sweep off");
+    }
+
+    format!(
+        "sweep on, period {}, {}, shift {}",
+        (value >> 4) & 0x07,
+        if value & 0x08 != 0 { "negate" } else { "add" },
+        value & 0x07,
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_length(value: u8) -> String {
+    format!("length {} frames", LENGTH_COUNTER_TABLE[(value >> 3) as usize])
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_length_and_timer_high(value: u8) -> String {
+    format!("{}, timer high {}", describe_length(value), value & 0x07)
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_triangle_linear(value: u8) -> String {
+    format!(
+        "{}, linear reload {}",
+        if value & 0x80 != 0 { "length halted" } else { "length counting" },
+        value & 0x7F,
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_noise_volume(value: u8) -> String {
+    describe_envelope(value)
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_noise_period(value: u8) -> String {
+    format!(
+        "{} mode, period index {}",
+        if value & 0x80 != 0 { "short" } else { "long" },
+        value & 0x0F,
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_dmc_frequency(value: u8) -> String {
+    format!(
+        "IRQ {}, loop {}, rate index {}",
+        on_off(value & 0x80),
+        on_off(value & 0x40),
+        value & 0x0F,
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_apu_status(value: u8) -> String {
+    let channels = [
+        (0x01, "square 1"),
+        (0x02, "square 2"),
+        (0x04, "triangle"),
+        (0x08, "noise"),
+        (0x10, "DMC"),
+    ];
+
+    let enabled: Vec<&str> = channels.iter()
+        .filter(|(bit, _)| value & bit != 0)
+        .map(|(_, name)| *name)
+        .collect();
+
+    if enabled.is_empty() {
+        String::from("all channels off")
+    } else {
+        format!("enable {}", enabled.join(", "))
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_controller_strobe(value: u8) -> String {
+    format!("controller strobe {}", on_off(value & 0x01))
+}
+
+// ---------------------------------------------------------------------------
+
+fn describe_frame_counter(value: u8) -> String {
+    format!(
+        "{}-step sequence, frame IRQ {}",
+        if value & 0x80 != 0 { 5 } else { 4 },
+        if value & 0x40 != 0 { "inhibited" } else { "enabled" },
+    )
+}
+
+// ---------------------------------------------------------------------------
+
+fn on_off(bits: u8) -> &'static str {
+    if bits != 0 { "on" } else { "off" }
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ppu_control() {
+        assert_eq!(
+            describe_register_write(0x2000, 0x90).unwrap(),
+            "NMI on, sprites 8x8, BG table $1000, sprite table $0000, VRAM increment 1, nametable $2000"
+        );
+    }
+
+    #[test]
+    fn ppu_mask() {
+        assert_eq!(
+            describe_register_write(0x2001, 0x1E).unwrap(),
+            "BG on, sprites on, BG left column on, sprites left column on, greyscale off, emphasis none"
+        );
+    }
+
+    #[test]
+    fn apu_status() {
+        assert_eq!(describe_register_write(0x4015, 0x0F).unwrap(), "enable square 1, square 2, triangle, noise");
+        assert_eq!(describe_register_write(0x4015, 0x00).unwrap(), "all channels off");
+    }
+
+    #[test]
+    fn unknown_register() {
+        assert!(describe_register_write(0x0300, 0x12).is_none());
+        assert!(describe_register_write(0x5FFA, 0x03).is_none());
+    }
+
+    #[test]
+    fn tracker_follows_immediate_load_into_store() {
+        let mut tracker = RegisterWriteTracker::new(false);
+        assert!(tracker.process_instruction(0xA9, 0x40, 0x00).is_none());
+        assert_eq!(tracker.process_instruction(0x8D, 0x17, 0x40).unwrap(), "4-step sequence, frame IRQ inhibited");
+    }
+
+    #[test]
+    fn tracker_forgets_value_after_unknown_instruction() {
+        let mut tracker = RegisterWriteTracker::new(false);
+        tracker.process_instruction(0xA9, 0x40, 0x00);
+        tracker.process_instruction(0x0A, 0x00, 0x00);
+        assert!(tracker.process_instruction(0x8D, 0x17, 0x40).is_none());
+    }
+
+    #[test]
+    fn tracker_describes_nsf_bank_writes_only_for_nsf() {
+        let mut tracker = RegisterWriteTracker::new(true);
+        tracker.process_instruction(0xA9, 0x03, 0x00);
+        assert_eq!(tracker.process_instruction(0x8D, 0xFA, 0x5F).unwrap(), "NSF bank 3 at $A000");
+
+        let mut tracker = RegisterWriteTracker::new(false);
+        tracker.process_instruction(0xA9, 0x03, 0x00);
+        assert!(tracker.process_instruction(0x8D, 0xFA, 0x5F).is_none());
+    }
+
+    #[test]
+    fn tracker_follows_transfers() {
+        let mut tracker = RegisterWriteTracker::new(false);
+        tracker.process_instruction(0xA9, 0x00, 0x00);
+        tracker.process_instruction(0xAA, 0x00, 0x00);
+        assert_eq!(tracker.process_instruction(0x8E, 0x15, 0x40).unwrap(), "all channels off");
+    }
+}
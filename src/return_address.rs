@@ -0,0 +1,96 @@
+use crate::opcodes::{decode_opcode, AddressingMode};
+
+// Subroutines such as jump engines pull their own return address off the stack to read the
+// bytes following the JSR, so execution doesn't come back to the instruction after it. They're
+// spotted by scanning the start of the subroutine for a pull before anything was pushed, or a
+// read of the return address through TSX.
+
+const MAX_INSTRUCTIONS_SCANNED: usize = 16;
+
+// ---------------------------------------------------------------------------
+
+/// Returns whether the subroutine at `offset` reads or pulls its own return address. Branches are
+/// assumed not taken, so only the straight-line start of the subroutine is checked.
+pub fn manipulates_return_address(prg_rom_contents: &[u8], offset: usize) -> bool {
+    let mut offset = offset;
+    let mut pushed_bytes = 0usize;
+    // How many bytes had been pushed when TSX copied the stack pointer, while X still holds it.
+    let mut pushed_bytes_at_tsx: Option<usize> = None;
+
+    for _ in 0..MAX_INSTRUCTIONS_SCANNED {
+        let Some(decoded_opcode) = prg_rom_contents.get(offset).and_then(|opcode| decode_opcode(*opcode)) else {
+            return false;
+        };
+
+        match decoded_opcode.mnemonic {
+            "PHA" | "PHP" => pushed_bytes += 1,
+            "PLA" | "PLP" if pushed_bytes == 0 => return true,
+            "PLA" | "PLP" => pushed_bytes -= 1,
+            "TSX" => pushed_bytes_at_tsx = Some(pushed_bytes),
+            "TAX" | "LDX" | "INX" | "DEX" => pushed_bytes_at_tsx = None,
+            "JSR" | "JMP" | "RTS" | "RTI" | "BRK" => return false,
+            _ => {},
+        }
+
+        if let Some(pushed_bytes_at_tsx) = pushed_bytes_at_tsx && decoded_opcode.addressing_mode == AddressingMode::AbsoluteX {
+            let low_byte = prg_rom_contents.get(offset + 1).copied().unwrap_or(0) as usize;
+            let high_byte = prg_rom_contents.get(offset + 2).copied().unwrap_or(0) as usize;
+            let stack_address = (high_byte << 8) | low_byte;
+
+            // With X holding the stack pointer, the return address sits just above what was pushed.
+            let return_address_start = 0x0101 + pushed_bytes_at_tsx;
+            if stack_address == return_address_start || stack_address == return_address_start + 1 {
+                return true;
+            }
+        }
+
+        offset += decoded_opcode.addressing_mode.bytes_count();
+    }
+
+    false
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_pulled_return_address() {
+        // ASL A, TAY, PLA, STA $04, PLA, STA $05
+        assert!(manipulates_return_address(&[0x0A, 0xA8, 0x68, 0x85, 0x04, 0x68, 0x85, 0x05], 0));
+    }
+
+    #[test]
+    fn balanced_pushes_and_pulls_are_fine() {
+        // PHA, LDA #$00, PLA, RTS
+        assert!(!manipulates_return_address(&[0x48, 0xA9, 0x00, 0x68, 0x60], 0));
+    }
+
+    #[test]
+    fn detects_return_address_read_through_tsx() {
+        // After PHA and TSX, $0101,X is the pushed byte and the return address follows it.
+        assert!(!manipulates_return_address(&[0x48, 0xBA, 0xBD, 0x01, 0x01, 0x60], 0));
+        assert!(manipulates_return_address(&[0x48, 0xBA, 0xBD, 0x02, 0x01], 0));
+        assert!(manipulates_return_address(&[0x48, 0xBA, 0xBD, 0x03, 0x01], 0));
+    }
+
+    #[test]
+    fn uses_the_stack_depth_from_when_tsx_ran() {
+        // PHA, TSX, PHA, LDA $0102,X: the later push doesn't move what X points at.
+        assert!(manipulates_return_address(&[0x48, 0xBA, 0x48, 0xBD, 0x02, 0x01], 0));
+        // PHA, TSX, PHA, LDA $0104,X reads past the return address, into the caller's stack.
+        assert!(!manipulates_return_address(&[0x48, 0xBA, 0x48, 0xBD, 0x04, 0x01, 0x68, 0x68, 0x60], 0));
+    }
+
+    #[test]
+    fn forgets_the_stack_pointer_once_x_is_overwritten() {
+        // TSX, INX, LDA $0101,X
+        assert!(!manipulates_return_address(&[0xBA, 0xE8, 0xBD, 0x01, 0x01, 0x60], 0));
+        // TSX, LDX #$00, LDA $0102,X
+        assert!(!manipulates_return_address(&[0xBA, 0xA2, 0x00, 0xBD, 0x02, 0x01, 0x60], 0));
+        // TSX, TAX, LDA $0101,X
+        assert!(!manipulates_return_address(&[0xBA, 0xAA, 0xBD, 0x01, 0x01, 0x60], 0));
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::control_flow::{BasicBlock, ControlFlowGraph, EdgeKind};
+use crate::opcodes::decode_opcode;
+
+// Worst-case stack usage, worked out over the traced control flow. Each routine is walked on its
+// own, tracking how many bytes it has pushed at every instruction, and the usage of the routines
+// it calls is added on top at the point of each call. A JSR pushes a two byte return address,
+// PHA/PHP push one byte and PLA/PLP pull one. TXS resets the count, since it's normally only
+// used to set up the stack.
+
+pub const INTERRUPT_BYTES: usize = 3;
+
+pub struct StackUsage {
+    /// The most bytes the routine and everything it calls can push, or `None` if it can reach a
+    /// cycle of calls.
+    pub max_bytes: Option<usize>,
+    pub imbalances: BTreeMap<usize, String>,
+}
+
+struct LocalStackUsage {
+    max_bytes: usize,
+    calls: Vec<(isize, usize)>,
+    imbalances: BTreeMap<usize, String>,
+}
+
+// ---------------------------------------------------------------------------
+
+/// Works out the stack usage of every routine, where `opcode_at` gives the opcode of a traced
+/// instruction.
+pub fn analyze_stack_usage(
+    control_flow_graph: &ControlFlowGraph,
+    basic_blocks: &BTreeMap<usize, BasicBlock>,
+    routine_entry_points: &BTreeSet<usize>,
+    opcode_at: &dyn Fn(usize) -> u8,
+) -> BTreeMap<usize, StackUsage> {
+    let local_usages: BTreeMap<usize, LocalStackUsage> = routine_entry_points.iter()
+        .map(|entry_point| {
+            let routine_blocks = control_flow_graph.routine_blocks(basic_blocks, *entry_point, routine_entry_points);
+            (*entry_point, analyze_routine(*entry_point, basic_blocks, &routine_blocks, routine_entry_points, opcode_at))
+        })
+        .collect();
+
+    let mut max_bytes = BTreeMap::new();
+    for entry_point in routine_entry_points {
+        calculate_max_bytes(*entry_point, &local_usages, &mut max_bytes, &mut BTreeSet::new());
+    }
+
+    local_usages.into_iter()
+        .map(|(entry_point, local_usage)| (entry_point, StackUsage {
+            max_bytes: max_bytes.get(&entry_point).copied().flatten(),
+            imbalances: local_usage.imbalances,
+        }))
+        .collect()
+}
+
+// ---------------------------------------------------------------------------
+
+fn analyze_routine(
+    entry_point: usize,
+    basic_blocks: &BTreeMap<usize, BasicBlock>,
+    routine_blocks: &BTreeSet<usize>,
+    routine_entry_points: &BTreeSet<usize>,
+    opcode_at: &dyn Fn(usize) -> u8,
+) -> LocalStackUsage {
+    let mut local_usage = LocalStackUsage {
+        max_bytes: 0,
+        calls: Vec::new(),
+        imbalances: BTreeMap::new(),
+    };
+
+    let mut block_entry_bytes: BTreeMap<usize, isize> = BTreeMap::from([(entry_point, 0)]);
+    let mut pending_blocks = vec![entry_point];
+
+    while let Some(block_address) = pending_blocks.pop() {
+        let basic_block = &basic_blocks[&block_address];
+        let mut pushed_bytes = block_entry_bytes[&block_address];
+
+        for address in &basic_block.instruction_addresses {
+            let Some(decoded_opcode) = decode_opcode(opcode_at(*address)) else {
+                continue;
+            };
+
+            match decoded_opcode.mnemonic {
+                "PHA" | "PHP" => pushed_bytes += 1,
+                "PLA" | "PLP" => {
+                    pushed_bytes -= 1;
+                    if pushed_bytes == -1 {
+                        local_usage.imbalances.entry(*address)
+                            .or_insert_with(|| String::from("pulls more bytes than the routine pushed"));
+                    }
+                },
+                "TXS" => pushed_bytes = 0,
+                // Pushing a return address and then returning to it is a common way to jump
+                // through a table, so two extra bytes at an RTS are expected.
+                "RTS" if pushed_bytes != 0 && pushed_bytes != 2 => {
+                    local_usage.imbalances.entry(*address).or_insert_with(|| format!("returns with {} bytes pushed", pushed_bytes));
+                },
+                "RTI" if pushed_bytes != 0 => {
+                    local_usage.imbalances.entry(*address)
+                        .or_insert_with(|| format!("returns from interrupt with {} bytes pushed", pushed_bytes));
+                },
+                _ => {},
+            }
+
+            local_usage.max_bytes = local_usage.max_bytes.max(pushed_bytes.max(0) as usize);
+        }
+
+        for (target_address, kind) in &basic_block.successors {
+            if *kind == EdgeKind::Call {
+                local_usage.calls.push((pushed_bytes + 2, *target_address));
+            } else if !routine_blocks.contains(target_address) {
+                if routine_entry_points.contains(target_address) {
+                    local_usage.calls.push((pushed_bytes, *target_address));
+                }
+            } else {
+                match block_entry_bytes.get(target_address) {
+                    None => {
+                        block_entry_bytes.insert(*target_address, pushed_bytes);
+                        pending_blocks.push(*target_address);
+                    },
+                    Some(entry_bytes) if *entry_bytes != pushed_bytes => {
+                        local_usage.imbalances.entry(*target_address).or_insert_with(|| {
+                            format!("reached with both {} and {} bytes pushed", entry_bytes, pushed_bytes)
+                        });
+                    },
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    local_usage
+}
+
+// ---------------------------------------------------------------------------
+
+fn calculate_max_bytes(
+    routine: usize,
+    local_usages: &BTreeMap<usize, LocalStackUsage>,
+    max_bytes: &mut BTreeMap<usize, Option<usize>>,
+    routines_in_progress: &mut BTreeSet<usize>,
+) -> Option<usize> {
+    if let Some(routine_max_bytes) = max_bytes.get(&routine) {
+        return *routine_max_bytes;
+    }
+    let local_usage = local_usages.get(&routine)?;
+
+    // Coming back around to a routine that's still being worked out means it's recursive.
+    if !routines_in_progress.insert(routine) {
+        return None;
+    }
+
+    let mut routine_max_bytes = Some(local_usage.max_bytes);
+    for (pushed_bytes, callee) in &local_usage.calls {
+        let callee_max_bytes = if local_usages.contains_key(callee) {
+            calculate_max_bytes(*callee, local_usages, max_bytes, routines_in_progress)
+        } else {
+            Some(0)
+        };
+
+        routine_max_bytes = match (routine_max_bytes, callee_max_bytes) {
+            (Some(routine_max_bytes), Some(callee_max_bytes)) => {
+                Some(routine_max_bytes.max((pushed_bytes + callee_max_bytes as isize).max(0) as usize))
+            },
+            _ => None,
+        };
+    }
+
+    routines_in_progress.remove(&routine);
+    max_bytes.insert(routine, routine_max_bytes);
+    routine_max_bytes
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each instruction is one byte long, except JSRs which take three.
+    fn analyze(instructions: &[(usize, u8)], edges: &[(usize, usize, EdgeKind)], entry_points: &[usize]) -> BTreeMap<usize, StackUsage> {
+        let mut control_flow_graph = ControlFlowGraph::new();
+        for entry_point in entry_points {
+            control_flow_graph.add_entry_point(*entry_point);
+        }
+        for (address, opcode) in instructions {
+            control_flow_graph.add_instruction(*address, if *opcode == 0x20 { 3 } else { 1 });
+        }
+        for (from_address, to_address, kind) in edges {
+            control_flow_graph.add_edge(*from_address, *to_address, *kind);
+        }
+
+        let opcodes: BTreeMap<usize, u8> = instructions.iter().copied().collect();
+        let opcode_at = |address: usize| opcodes[&address];
+        let routine_entry_points: BTreeSet<usize> = entry_points.iter().copied().collect();
+
+        analyze_stack_usage(&control_flow_graph, &control_flow_graph.basic_blocks(), &routine_entry_points, &opcode_at)
+    }
+
+    #[test]
+    fn adds_callee_usage_at_the_call() {
+        // $8000: PHA  $8001: JSR $9000  $8004: PLA  $8005: RTS
+        // $9000: PHP  $9001: PLP        $9002: RTS
+        let stack_usages = analyze(
+            &[(0x8000, 0x48), (0x8001, 0x20), (0x8004, 0x68), (0x8005, 0x60), (0x9000, 0x08), (0x9001, 0x28), (0x9002, 0x60)],
+            &[
+                (0x8000, 0x8001, EdgeKind::Fallthrough),
+                (0x8001, 0x9000, EdgeKind::Call),
+                (0x8001, 0x8004, EdgeKind::Fallthrough),
+                (0x8004, 0x8005, EdgeKind::Fallthrough),
+                (0x9000, 0x9001, EdgeKind::Fallthrough),
+                (0x9001, 0x9002, EdgeKind::Fallthrough),
+            ],
+            &[0x8000, 0x9000]);
+
+        assert_eq!(stack_usages[&0x9000].max_bytes, Some(1));
+        assert_eq!(stack_usages[&0x8000].max_bytes, Some(4));
+        assert!(stack_usages[&0x8000].imbalances.is_empty());
+    }
+
+    #[test]
+    fn flags_unbalanced_paths() {
+        // $8000: PHA  $8001: BNE $8003  $8002: PLA  $8003: RTS
+        let stack_usages = analyze(
+            &[(0x8000, 0x48), (0x8001, 0xD0), (0x8002, 0x68), (0x8003, 0x60)],
+            &[
+                (0x8000, 0x8001, EdgeKind::Fallthrough),
+                (0x8001, 0x8003, EdgeKind::Branch),
+                (0x8001, 0x8002, EdgeKind::Fallthrough),
+                (0x8002, 0x8003, EdgeKind::Fallthrough),
+            ],
+            &[0x8000]);
+
+        let imbalances = &stack_usages[&0x8000].imbalances;
+        assert_eq!(imbalances[&0x8003], "reached with both 1 and 0 bytes pushed");
+    }
+
+    #[test]
+    fn flags_extra_pulls() {
+        // $8000: PLA  $8001: RTS
+        let stack_usages = analyze(&[(0x8000, 0x68), (0x8001, 0x60)], &[(0x8000, 0x8001, EdgeKind::Fallthrough)], &[0x8000]);
+
+        assert_eq!(stack_usages[&0x8000].imbalances[&0x8000], "pulls more bytes than the routine pushed");
+    }
+
+    #[test]
+    fn recursion_is_unbounded() {
+        // $8000: JSR $8000  $8003: RTS
+        let stack_usages = analyze(
+            &[(0x8000, 0x20), (0x8003, 0x60)],
+            &[(0x8000, 0x8000, EdgeKind::Call), (0x8000, 0x8003, EdgeKind::Fallthrough)],
+            &[0x8000]);
+
+        assert_eq!(stack_usages[&0x8000].max_bytes, None);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::BTreeMap, fs};
+
+// Symbol files are plain text with one symbol per line:
+//
+//     address name [size] [; comment]
+//
+// Addresses are hex and may be written as `$12`, `0x12` or `12`. The size is decimal and defaults
+// to 1; addresses inside a multi-byte symbol are named `name+offset`. The `;` may be left out when
+// there's no size, so `$10 timer 60 frames` is a one-byte symbol with the comment `60 frames`.
+// Blank lines and lines starting with `;` or `#` are ignored.
+
+pub struct Symbol {
+    pub name: String,
+    pub size: usize,
+    pub comment: Option<String>,
+}
+
+pub struct SymbolTable {
+    symbols: BTreeMap<usize, Symbol>,
+    comments: BTreeMap<usize, String>,
+    sized_symbols: BTreeMap<usize, usize>,
+}
+
+// ---------------------------------------------------------------------------
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self {
+            symbols: BTreeMap::new(),
+            comments: BTreeMap::new(),
+            sized_symbols: BTreeMap::new(),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn load_from_file(filename: &str) -> Self {
+        let contents = match fs::read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(error) => panic!("[ERROR] Could not read symbol file: {error}"),
+        };
+
+        match Self::parse(&contents) {
+            Ok(symbol_table) => symbol_table,
+            Err(error) => panic!("[ERROR] Could not parse symbol file {filename}: {error}"),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut symbol_table = Self::new();
+
+        for (line_index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+                continue;
+            }
+
+            let mut fields = line.splitn(3, char::is_whitespace);
+            let address_field = fields.next().unwrap_or_default();
+            let Some(name) = fields.next().filter(|name| !name.is_empty()) else {
+                return Err(format!("line {}: missing symbol name", line_index + 1));
+            };
+            let Some(address) = parse_hex_address(address_field) else {
+                return Err(format!("line {}: invalid address `{address_field}`", line_index + 1));
+            };
+
+            let remainder = fields.next().unwrap_or_default().trim_start();
+            let (size_field, comment) = match remainder.split_once(';') {
+                Some((size_field, comment)) => (size_field.trim_end(), comment.trim()),
+                None => (remainder, ""),
+            };
+            let (size, comment) = if size_field.is_empty() {
+                (1, comment)
+            } else {
+                match size_field.parse::<usize>() {
+                    Ok(size) => (size, comment),
+                    Err(_) => (1, remainder),
+                }
+            };
+
+            symbol_table.insert(address, Symbol {
+                name: name.to_string(),
+                size: size.max(1),
+                comment: if comment.is_empty() { None } else { Some(comment.to_string()) },
+            });
+        }
+
+        Ok(symbol_table)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn insert(&mut self, address: usize, symbol: Symbol) {
+        if symbol.size > 1 {
+            self.sized_symbols.insert(address, symbol.size);
+        } else {
+            self.sized_symbols.remove(&address);
+        }
+        self.symbols.insert(address, symbol);
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Attaches a comment to an address that doesn't otherwise have a name.
+    pub fn insert_comment(&mut self, address: usize, comment: String) {
+        self.comments.insert(address, comment);
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Merges `other` into this table; its entries win when both name the same address.
+    pub fn extend(&mut self, other: SymbolTable) {
+        for (address, symbol) in other.symbols {
+            self.insert(address, symbol);
+        }
+        self.comments.extend(other.comments);
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the innermost symbol covering `address` along with the offset of `address` into it.
+    /// Only multi-byte symbols can reach past their own address, so those are kept apart and walked
+    /// back through when nothing starts at `address`.
+    pub fn find(&self, address: usize) -> Option<(&Symbol, usize)> {
+        if let Some(symbol) = self.symbols.get(&address) {
+            return Some((symbol, 0));
+        }
+
+        let (start_address, _) = self.sized_symbols
+            .range(..address)
+            .rev()
+            .find(|(start_address, size)| address - **start_address < **size)?;
+        Some((&self.symbols[start_address], address - start_address))
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn get_name(&self, address: usize) -> Option<String> {
+        match self.find(address)? {
+            (symbol, 0) => Some(symbol.name.clone()),
+            (symbol, offset) => Some(format!("{}+{offset}", symbol.name)),
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the symbol starting exactly at `address`, ignoring ones that merely cover it.
+    pub fn get_exact(&self, address: usize) -> Option<&Symbol> {
+        self.symbols.get(&address)
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn get_comment(&self, address: usize) -> Option<&String> {
+        self.get_exact(address)
+            .and_then(|symbol| symbol.comment.as_ref())
+            .or_else(|| self.comments.get(&address))
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn symbols(&self) -> impl Iterator<Item = (usize, &Symbol)> {
+        self.symbols.iter().map(|(address, symbol)| (*address, symbol))
+    }
+
+    // -----------------------------------------------------------------------
+
+    /// Returns the addresses of comments that aren't attached to a symbol.
+    pub fn comment_addresses(&self) -> impl Iterator<Item = usize> {
+        self.comments.keys().copied()
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn parse_hex_address(text: &str) -> Option<usize> {
+    let digits = text
+        .strip_prefix('$')
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+
+    usize::from_str_radix(digits, 16).ok()
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_all_fields() {
+        let symbol_table = SymbolTable::parse("$0012 ptr 2 ; pointer to level data\n").unwrap();
+        let (symbol, offset) = symbol_table.find(0x13).unwrap();
+
+        assert_eq!(symbol.name, "ptr");
+        assert_eq!(symbol.size, 2);
+        assert_eq!(symbol.comment.as_deref(), Some("pointer to level data"));
+        assert_eq!(offset, 1);
+    }
+
+    #[test]
+    fn size_and_comment_are_optional() {
+        let symbol_table = SymbolTable::parse("; comment\n\n0x0300 buffer\nC000 table lookup table\n").unwrap();
+
+        assert_eq!(symbol_table.get_name(0x0300).unwrap(), "buffer");
+        assert!(symbol_table.get_name(0x0301).is_none());
+        assert_eq!(symbol_table.get_exact(0xC000).unwrap().comment.as_deref(), Some("lookup table"));
+    }
+
+    #[test]
+    fn reads_a_leading_number_without_a_semicolon_as_comment() {
+        let symbol_table = SymbolTable::parse("$10 timer 60 frames\n$20 lives 3\n$30 flags ; 8 bits\n").unwrap();
+
+        assert_eq!(symbol_table.get_exact(0x10).unwrap().size, 1);
+        assert_eq!(symbol_table.get_comment(0x10).unwrap(), "60 frames");
+        assert_eq!(symbol_table.get_exact(0x20).unwrap().size, 3);
+        assert_eq!(symbol_table.get_exact(0x30).unwrap().size, 1);
+        assert_eq!(symbol_table.get_comment(0x30).unwrap(), "8 bits");
+    }
+
+    #[test]
+    fn names_offsets_into_ranges() {
+        let symbol_table = SymbolTable::parse("$0400 oam_buffer 256\n").unwrap();
+
+        assert_eq!(symbol_table.get_name(0x0400).unwrap(), "oam_buffer");
+        assert_eq!(symbol_table.get_name(0x04FF).unwrap(), "oam_buffer+255");
+        assert!(symbol_table.get_name(0x0500).is_none());
+    }
+
+    #[test]
+    fn finds_enclosing_symbols_past_smaller_ones() {
+        let symbol_table = SymbolTable::parse("$0300 table 32\n$0305 flag\n$0308 pair 2\n").unwrap();
+
+        assert_eq!(symbol_table.get_name(0x0305).unwrap(), "flag");
+        assert_eq!(symbol_table.get_name(0x0309).unwrap(), "pair+1");
+        assert_eq!(symbol_table.get_name(0x0310).unwrap(), "table+16");
+        assert!(symbol_table.get_name(0x0320).is_none());
+    }
+
+    #[test]
+    fn finds_symbols_past_a_large_ram_symbol() {
+        let symbol_table = SymbolTable::parse("$0000 ram 2048\n$0010 timer\n$0300 buffer 16\n").unwrap();
+
+        assert_eq!(symbol_table.get_name(0x0010).unwrap(), "timer");
+        assert_eq!(symbol_table.get_name(0x0011).unwrap(), "ram+17");
+        assert_eq!(symbol_table.get_name(0x0305).unwrap(), "buffer+5");
+        assert_eq!(symbol_table.get_name(0x07FF).unwrap(), "ram+2047");
+        assert!(symbol_table.get_name(0x0800).is_none());
+    }
+
+    #[test]
+    fn extend_prefers_new_entries() {
+        let mut symbol_table = SymbolTable::parse("$12 old 4\n").unwrap();
+        let mut other = SymbolTable::parse("$12 new\n").unwrap();
+        other.insert_comment(0x8000, String::from("entry"));
+        symbol_table.extend(other);
+
+        assert_eq!(symbol_table.get_name(0x12).unwrap(), "new");
+        assert!(symbol_table.get_name(0x13).is_none());
+        assert_eq!(symbol_table.get_comment(0x8000).unwrap(), "entry");
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        assert!(SymbolTable::parse("zz12 name\n").is_err());
+        assert!(SymbolTable::parse("$12\n").is_err());
+    }
+}
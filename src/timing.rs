@@ -0,0 +1,180 @@
+use crate::opcodes::{decode_opcode, AddressingMode};
+
+// Cycle counts for instructions and basic blocks. Branch penalties can be worked out statically
+// since the target is known, while indexed page crossing penalties depend on runtime register
+// values and are only flagged as possible.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimingPenalty {
+    None,
+    PossiblePageCross,
+    Branch { is_target_on_other_page: bool },
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct InstructionTiming {
+    pub cycles: u8,
+    pub penalty: TimingPenalty,
+}
+
+pub struct BasicBlockTiming {
+    pub start_address: usize,
+    pub end_address: usize,
+    pub min_cycles: usize,
+    pub max_cycles: usize,
+}
+
+// ---------------------------------------------------------------------------
+
+impl InstructionTiming {
+    pub fn min_cycles(&self) -> usize {
+        self.cycles as usize
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn max_cycles(&self) -> usize {
+        let penalty = match self.penalty {
+            TimingPenalty::None => 0,
+            TimingPenalty::PossiblePageCross => 1,
+            TimingPenalty::Branch { is_target_on_other_page: false } => 1,
+            TimingPenalty::Branch { is_target_on_other_page: true } => 2,
+        };
+
+        self.cycles as usize + penalty
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn describe(&self) -> String {
+        match self.penalty {
+            TimingPenalty::None => format!("{} cycles", self.cycles),
+            TimingPenalty::PossiblePageCross => format!("{}+ cycles (+1 if page crossed)", self.cycles),
+            TimingPenalty::Branch { is_target_on_other_page: false } => format!("{} cycles, {} if taken", self.cycles, self.max_cycles()),
+            TimingPenalty::Branch { is_target_on_other_page: true } => {
+                format!("{} cycles, {} if taken (target on another page)", self.cycles, self.max_cycles())
+            },
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+impl BasicBlockTiming {
+    pub fn new(start_address: usize) -> Self {
+        Self {
+            start_address,
+            end_address: start_address,
+            min_cycles: 0,
+            max_cycles: 0,
+        }
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn add_instruction(&mut self, address: usize, timing: &InstructionTiming) {
+        self.end_address = address;
+        self.min_cycles += timing.min_cycles();
+        self.max_cycles += timing.max_cycles();
+    }
+
+    // -----------------------------------------------------------------------
+
+    pub fn describe(&self) -> String {
+        let cycles = if self.min_cycles == self.max_cycles {
+            format!("{} cycles", self.min_cycles)
+        } else {
+            format!("{}-{} cycles", self.min_cycles, self.max_cycles)
+        };
+
+        format!("block ${:04X}-${:04X}: {cycles}", self.start_address, self.end_address)
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn calculate_instruction_timing(opcode: u8, operand1: u8, address: usize) -> Option<InstructionTiming> {
+    let decoded_opcode = decode_opcode(opcode)?;
+
+    let penalty = if decoded_opcode.addressing_mode == AddressingMode::Relative {
+        let next_address = (address + 2) as u16;
+        let target_address = next_address.wrapping_add(operand1 as i8 as u16);
+        TimingPenalty::Branch { is_target_on_other_page: (target_address & 0xFF00) != (next_address & 0xFF00) }
+    } else if decoded_opcode.has_page_cross_penalty {
+        TimingPenalty::PossiblePageCross
+    } else {
+        TimingPenalty::None
+    };
+
+    Some(InstructionTiming {
+        cycles: decoded_opcode.cycles,
+        penalty,
+    })
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns whether control can't simply fall through to the next instruction, ending a basic
+/// block.
+pub fn ends_basic_block(opcode: u8) -> bool {
+    match decode_opcode(opcode) {
+        Some(decoded_opcode) => {
+            decoded_opcode.addressing_mode == AddressingMode::Relative
+                || matches!(decoded_opcode.mnemonic, "JMP" | "RTS" | "RTI" | "BRK")
+        },
+        None => true,
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_timing() {
+        let timing = calculate_instruction_timing(0xAD, 0x00, 0x8000).unwrap();
+        assert_eq!(timing.describe(), "4 cycles");
+    }
+
+    #[test]
+    fn possible_page_cross() {
+        let timing = calculate_instruction_timing(0xBD, 0x00, 0x8000).unwrap();
+        assert_eq!((timing.min_cycles(), timing.max_cycles()), (4, 5));
+
+        // Stores always take the extra cycle, so there's nothing to flag.
+        let timing = calculate_instruction_timing(0x9D, 0x00, 0x8000).unwrap();
+        assert_eq!(timing.penalty, TimingPenalty::None);
+        assert_eq!(timing.min_cycles(), 5);
+    }
+
+    #[test]
+    fn branch_within_page() {
+        let timing = calculate_instruction_timing(0xD0, 0xFE, 0x8010).unwrap();
+        assert_eq!(timing.describe(), "2 cycles, 3 if taken");
+    }
+
+    #[test]
+    fn branch_to_other_page() {
+        // The branch at $80FC falls through to $80FE; going forward by $10 lands on $810E.
+        let timing = calculate_instruction_timing(0xF0, 0x10, 0x80FC).unwrap();
+        assert_eq!(timing.describe(), "2 cycles, 4 if taken (target on another page)");
+
+        // Branching back from $8100 to $80F0 crosses too.
+        let timing = calculate_instruction_timing(0xF0, 0xEE, 0x8100).unwrap();
+        assert_eq!(timing.max_cycles(), 4);
+    }
+
+    #[test]
+    fn basic_block_totals() {
+        let mut block = BasicBlockTiming::new(0x8000);
+        block.add_instruction(0x8000, &calculate_instruction_timing(0xA9, 0x00, 0x8000).unwrap());
+        block.add_instruction(0x8002, &calculate_instruction_timing(0xBD, 0x00, 0x8002).unwrap());
+        block.add_instruction(0x8005, &calculate_instruction_timing(0xD0, 0xF9, 0x8005).unwrap());
+
+        assert_eq!(block.describe(), "block $8000-$8005: 8-10 cycles");
+        assert!(ends_basic_block(0xD0));
+        assert!(!ends_basic_block(0x20));
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::BTreeMap;
+
+// UNIF files start with a 32 byte header (`UNIF`, revision, zero padding) followed by chunks made
+// of a four letter id, a little-endian length and the data:
+//
+//     MAPR  board name, e.g. `NES-NROM-256`
+//     PRGn  PRG ROM, with n a hex digit giving the order the chunks are joined in
+//     CHRn  CHR ROM, joined the same way
+//     MIRR  mirroring, 0 horizontal, 1 vertical, 2-3 one screen, 4 four screen, 5 mapper controlled
+//     BATR  battery-backed RAM present
+//     NAME  game name
+//
+// along with checksums, dumper info and comments that aren't needed for disassembly.
+// https://www.nesdev.org/wiki/UNIF
+
+const UNIF_HEADER_BYTES: usize = 32;
+const UNIF_MAGIC: &[u8] = b"UNIF";
+
+/// The chunks that are understood but not needed for disassembly.
+const IGNORED_CHUNK_IDS: &[&str] = &["READ", "DINF", "TVCI", "CTRL", "VROR"];
+
+/// Common boards by their name without the `NES-`/`HVC-`/... prefix, with the matching mapper.
+const BOARD_MAPPER_IDS: &[(&str, u8)] = &[
+    ("NROM", 0),
+    ("NROM-128", 0),
+    ("NROM-256", 0),
+    ("RROM", 0),
+    ("RROM-128", 0),
+    ("SAROM", 1),
+    ("SBROM", 1),
+    ("SCROM", 1),
+    ("SEROM", 1),
+    ("SGROM", 1),
+    ("SKROM", 1),
+    ("SLROM", 1),
+    ("SL1ROM", 1),
+    ("SNROM", 1),
+    ("SOROM", 1),
+    ("SUROM", 1),
+    ("SXROM", 1),
+    ("UNROM", 2),
+    ("UOROM", 2),
+    ("CNROM", 3),
+    ("TBROM", 4),
+    ("TEROM", 4),
+    ("TFROM", 4),
+    ("TGROM", 4),
+    ("TKROM", 4),
+    ("TLROM", 4),
+    ("TL1ROM", 4),
+    ("TSROM", 4),
+    ("TVROM", 4),
+    ("EKROM", 5),
+    ("ELROM", 5),
+    ("ETROM", 5),
+    ("EWROM", 5),
+    ("AMROM", 7),
+    ("ANROM", 7),
+    ("AOROM", 7),
+    ("PNROM", 9),
+    ("FJROM", 10),
+    ("FKROM", 10),
+    ("CPROM", 13),
+    ("BNROM", 34),
+    ("GNROM", 66),
+    ("MHROM", 66),
+];
+
+const BOARD_NAME_PREFIXES: &[&str] = &["NES-", "HVC-", "UNL-", "BTL-", "BMC-", "IREM-", "KONAMI-", "TAITO-"];
+
+pub struct UnifFile {
+    pub revision: u32,
+    pub board_name: String,
+    pub name: Option<String>,
+    pub prg_rom_contents: Vec<u8>,
+    pub chr_rom_contents: Vec<u8>,
+    pub mirroring: Option<u8>,
+    pub has_battery: bool,
+    pub unknown_chunks: Vec<(String, usize)>,
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn is_unif_file(contents: &[u8]) -> bool {
+    contents.starts_with(UNIF_MAGIC)
+}
+
+// ---------------------------------------------------------------------------
+
+/// Returns the mapper for a UNIF board name, if it's one of the common boards.
+pub fn board_mapper_id(board_name: &str) -> Option<u8> {
+    let board_name = BOARD_NAME_PREFIXES.iter()
+        .find_map(|prefix| board_name.strip_prefix(prefix))
+        .unwrap_or(board_name);
+
+    BOARD_MAPPER_IDS.iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(board_name))
+        .map(|(_, mapper_id)| *mapper_id)
+}
+
+// ---------------------------------------------------------------------------
+
+pub fn parse_unif(contents: &[u8]) -> Result<UnifFile, String> {
+    if !is_unif_file(contents) {
+        return Err(String::from("missing UNIF signature"));
+    }
+    if contents.len() < UNIF_HEADER_BYTES {
+        return Err(String::from("header is truncated"));
+    }
+
+    let mut unif_file = UnifFile {
+        revision: u32::from_le_bytes([contents[4], contents[5], contents[6], contents[7]]),
+        board_name: String::new(),
+        name: None,
+        prg_rom_contents: Vec::new(),
+        chr_rom_contents: Vec::new(),
+        mirroring: None,
+        has_battery: false,
+        unknown_chunks: Vec::new(),
+    };
+    let mut prg_chunks: BTreeMap<u8, &[u8]> = BTreeMap::new();
+    let mut chr_chunks: BTreeMap<u8, &[u8]> = BTreeMap::new();
+
+    let mut offset = UNIF_HEADER_BYTES;
+    while offset < contents.len() {
+        let Some(chunk_header) = contents.get(offset..offset + 8) else {
+            return Err(format!("chunk header at ${offset:X} is truncated"));
+        };
+        let id = String::from_utf8_lossy(&chunk_header[0..4]).to_string();
+        let length = u32::from_le_bytes([chunk_header[4], chunk_header[5], chunk_header[6], chunk_header[7]]) as usize;
+        let Some(chunk) = contents.get(offset + 8..offset + 8 + length) else {
+            return Err(format!("chunk {id} runs off the end of the file"));
+        };
+        offset += 8 + length;
+
+        let chunk_index = id.get(3..).and_then(|digit| u8::from_str_radix(digit, 16).ok());
+        match (id.get(..3).unwrap_or_default(), chunk_index) {
+            ("PRG", Some(index)) => { prg_chunks.insert(index, chunk); },
+            ("CHR", Some(index)) => { chr_chunks.insert(index, chunk); },
+            ("PCK" | "CCK", Some(_)) => {},
+            _ => match id.as_str() {
+                "MAPR" => unif_file.board_name = parse_string(chunk),
+                "NAME" => unif_file.name = Some(parse_string(chunk)),
+                "MIRR" => unif_file.mirroring = chunk.first().copied(),
+                "BATR" => unif_file.has_battery = chunk.first().is_some_and(|battery| *battery != 0),
+                _ if IGNORED_CHUNK_IDS.contains(&id.as_str()) => {},
+                _ => unif_file.unknown_chunks.push((id, length)),
+            },
+        }
+    }
+
+    if unif_file.board_name.is_empty() {
+        return Err(String::from("missing MAPR chunk"));
+    }
+    if prg_chunks.is_empty() {
+        return Err(String::from("missing PRG chunks"));
+    }
+
+    unif_file.prg_rom_contents = prg_chunks.into_values().flatten().copied().collect();
+    unif_file.chr_rom_contents = chr_chunks.into_values().flatten().copied().collect();
+
+    Ok(unif_file)
+}
+
+// ---------------------------------------------------------------------------
+
+fn parse_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_unif(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut contents = UNIF_MAGIC.to_vec();
+        contents.extend_from_slice(&7u32.to_le_bytes());
+        contents.resize(UNIF_HEADER_BYTES, 0);
+
+        for (id, data) in chunks {
+            contents.extend_from_slice(id);
+            contents.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            contents.extend_from_slice(data);
+        }
+
+        contents
+    }
+
+    #[test]
+    fn joins_rom_chunks_in_order() {
+        let contents = create_unif(&[
+            (b"MAPR", b"NES-NROM-256\0"),
+            (b"PRG1", &[2, 3]),
+            (b"PRG0", &[0, 1]),
+            (b"CHR0", &[4]),
+            (b"MIRR", &[1]),
+            (b"BATR", &[1]),
+            (b"DINF", &[0; 204]),
+            (b"WXYZ", &[0; 3]),
+        ]);
+
+        let unif_file = parse_unif(&contents).unwrap();
+        assert_eq!(unif_file.revision, 7);
+        assert_eq!(unif_file.board_name, "NES-NROM-256");
+        assert_eq!(unif_file.prg_rom_contents, vec![0, 1, 2, 3]);
+        assert_eq!(unif_file.chr_rom_contents, vec![4]);
+        assert_eq!(unif_file.mirroring, Some(1));
+        assert!(unif_file.has_battery);
+        assert_eq!(unif_file.unknown_chunks, vec![(String::from("WXYZ"), 3)]);
+    }
+
+    #[test]
+    fn maps_board_names() {
+        assert_eq!(board_mapper_id("NES-NROM-128"), Some(0));
+        assert_eq!(board_mapper_id("HVC-SNROM"), Some(1));
+        assert_eq!(board_mapper_id("UNL-TLROM"), Some(4));
+        assert_eq!(board_mapper_id("AOROM"), Some(7));
+        assert_eq!(board_mapper_id("UNL-SOMETHING"), None);
+    }
+
+    #[test]
+    fn rejects_missing_chunks() {
+        assert!(parse_unif(&create_unif(&[(b"PRG0", &[0])])).is_err_and(|error| error.contains("MAPR")));
+        assert!(parse_unif(&create_unif(&[(b"MAPR", b"NES-NROM\0")])).is_err_and(|error| error.contains("PRG")));
+        assert!(parse_unif(&create_unif(&[(b"MAPR", b"NES-NROM\0"), (b"PRG0", &[0; 16])])[..50]).is_err());
+    }
+}